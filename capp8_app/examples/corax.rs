@@ -1,7 +1,8 @@
 use capp8_core::frontend::Frontend;
-use capp8_desktop::gui::DesktopFrontend;
+use capp8_desktop::gui::{DesktopError, DesktopFrontend};
 
-fn main() {
-    let mut frontend = DesktopFrontend::new("./capp8_app/examples/3-corax+.ch8", 640, 320);
+fn main() -> Result<(), DesktopError> {
+    let mut frontend = DesktopFrontend::new("./capp8_app/examples/3-corax+.ch8")?;
     frontend.run();
+    Ok(())
 }
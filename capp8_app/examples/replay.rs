@@ -0,0 +1,33 @@
+//! Plays a movie recorded with the desktop frontend (F2) without opening a window:
+//! `cargo run --example replay -- <rom> <movie>`.
+
+use std::env;
+use std::process::ExitCode;
+
+use capp8_core::emulator::Emulator;
+use capp8_core::movie::{Movie, MoviePlayer};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [rom, movie] = args.as_slice() else {
+        eprintln!("usage: replay <rom> <movie>");
+        return ExitCode::FAILURE;
+    };
+
+    let result = Movie::load(movie).and_then(|movie| {
+        let mut emulator = movie.configure(Emulator::builder()).build_from_file(rom)?;
+        let frames = MoviePlayer::new(movie, &emulator)?.play_to_end(&mut emulator)?;
+        Ok((emulator, frames))
+    });
+    match result {
+        Ok((emulator, frames)) => {
+            println!("{}", emulator.display());
+            println!("Played {frames} frames without desync");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use capp8_core::frontend::Frontend;
+use capp8_core::quirks::Quirks;
+use capp8_desktop::gui::{DesktopError, DesktopFrontend};
+
+fn main() -> Result<(), DesktopError> {
+    let mut frontend =
+        DesktopFrontend::with_quirks("./capp8_app/examples/8-scrolling.ch8", Quirks::SUPER_CHIP)?;
+    frontend.run();
+    Ok(())
+}
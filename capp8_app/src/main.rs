@@ -1,7 +1,768 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{Args, Parser, Subcommand};
+
+use capp8_asm::assemble;
+
+use capp8_core::audio::Waveform;
+use capp8_core::debugger::Debugger;
+use capp8_core::disasm::disassemble;
+use capp8_core::emulator::{Emulator, EmulatorBuilder};
+use capp8_core::error::LoadError;
 use capp8_core::frontend::Frontend;
+use capp8_core::gdb::GdbStub;
+use capp8_core::instruction::Syntax;
+use capp8_core::movie::{Movie, MoviePlayer};
+use capp8_core::palette::Palette;
+use capp8_core::quirks::{Platform, Quirks};
+use capp8_core::trace::{self, Tracer};
+use capp8_desktop::config::{self, Config, Settings};
+use capp8_desktop::database::Database;
 use capp8_desktop::gui::DesktopFrontend;
+use capp8_headless::dump;
+use capp8_headless::headless::{HeadlessFrontend, StopCondition, StopReason};
+use capp8_headless::script::InputScript;
+use capp8_tui::render::Rendering;
+use capp8_tui::tui::TuiFrontend;
+
+/// How long `capp8 headless` runs without `--frames`, ten seconds at 60 FPS.
+const HEADLESS_FRAMES: u64 = 600;
+/// The sample rate of the sound `capp8 headless --wav` records.
+const WAV_SAMPLE_RATE: u32 = 44_100;
+
+/// The port `capp8 gdb` listens on by default, the one GDB's documentation uses.
+const GDB_PORT: u16 = 1234;
+
+/// A CHIP-8, SUPER-CHIP and XO-CHIP emulator.
+#[derive(Parser)]
+#[command(name = "capp8", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a ROM in a window.
+    Run(RunArgs),
+    /// Run a ROM in the terminal.
+    Tui {
+        #[command(flatten)]
+        machine: Machine,
+        /// Draw with braille characters, which fits high resolution programs in a
+        /// smaller terminal.
+        #[arg(long)]
+        braille: bool,
+    },
+    /// Run a ROM without a window until it stops, then write the display and the result.
+    Headless(HeadlessArgs),
+    /// Print the instructions and data of a ROM.
+    Disasm {
+        rom: PathBuf,
+        /// Print Octo source that assembles back into the ROM, instead of a listing.
+        #[arg(long)]
+        octo: bool,
+    },
+    /// Show the size, hash and code of a ROM.
+    Info { rom: PathBuf },
+    /// Assemble Octo source into a ROM, writing the symbol table next to it.
+    Asm {
+        source: PathBuf,
+        /// The ROM to write, the source with a .ch8 extension by default.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Serve a ROM to a GDB front-end on a local port until it detaches, with the symbols
+    /// of the `.sym` file next to it.
+    Gdb {
+        #[command(flatten)]
+        machine: Machine,
+        #[arg(long, default_value_t = GDB_PORT)]
+        port: u16,
+    },
+    /// Run a ROM without a window, tracing every instruction.
+    Trace {
+        #[command(flatten)]
+        machine: Machine,
+        /// How many instructions to run at most.
+        #[arg(short = 'n', long, default_value_t = 10_000)]
+        steps: u64,
+        /// The file to write the trace to, standard output by default.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Compare two traces, exiting with 0 if they match, 1 if they diverge and 2 if they
+    /// could not be read.
+    TraceDiff { first: PathBuf, second: PathBuf },
+}
+
+/// The ROM to run and how the machine runs it.
+#[derive(Args)]
+struct Machine {
+    /// The ROM to run.
+    rom: PathBuf,
+    /// The platform whose quirks to use: chip8 (the default), chip48, schip or xochip.
+    #[arg(long)]
+    platform: Option<Platform>,
+    /// Quirks to change from those of the platform, comma separated. A `no-` prefix
+    /// turns one off, as in `--quirks shift,no-clipping`.
+    #[arg(long, value_delimiter = ',', value_parser = parse_quirk)]
+    quirks: Vec<(&'static str, bool)>,
+    /// Instructions per second, 700 by default.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ips: Option<u32>,
+    /// The seed of the random number generator, a random one by default.
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: Machine,
+    /// Window pixels per CHIP-8 pixel, 10 by default.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    scale: Option<u16>,
+    /// The display colours: grey, amber, green, octo, or two to four hex colours like
+    /// `#000000,#33ff66`.
+    #[arg(long)]
+    palette: Option<Palette>,
+    /// Frames undone per frame while Backspace is held, 1 by default.
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    rewind_speed: Option<u16>,
+    /// The shape of the beep: square (the default), sine or triangle.
+    #[arg(long)]
+    waveform: Option<Waveform>,
+    /// The pitch of the beep in Hz, from 20 to 20000, 440 by default.
+    #[arg(long, value_parser = parse_frequency)]
+    frequency: Option<f32>,
+    /// The volume of the sound, from 0 to 1, 0.25 by default.
+    #[arg(long, value_parser = parse_volume)]
+    volume: Option<f32>,
+    /// Start without sound, M turns it back on.
+    #[arg(long)]
+    mute: bool,
+    /// Start in fullscreen.
+    #[arg(long)]
+    fullscreen: bool,
+    /// The configuration file to read instead of the one in the user's configuration
+    /// directory. The options above override what it sets.
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct HeadlessArgs {
+    #[command(flatten)]
+    machine: Machine,
+    /// Stop after this many frames, 600 by default, or at the end of the movie.
+    #[arg(long)]
+    frames: Option<u64>,
+    /// Stop when the program jumps to the jump it is on, the usual way test ROMs end.
+    #[arg(long)]
+    until_loop: bool,
+    /// Stop when the byte at the address, in hex, holds the value, in decimal or `0x` hex.
+    #[arg(long, value_name = "ADDR=BYTE", value_parser = parse_memory_condition)]
+    until: Vec<StopCondition>,
+    /// The keys to press, see `capp8_headless::script`.
+    #[arg(long, value_name = "SCRIPT")]
+    input: Option<PathBuf>,
+    /// Play a movie recorded by the desktop frontend instead, failing if it desyncs.
+    #[arg(long, value_name = "FILE", conflicts_with = "input")]
+    movie: Option<PathBuf>,
+    /// Write the display as a PBM image.
+    #[arg(long, value_name = "FILE")]
+    pbm: Option<PathBuf>,
+    /// Write the display as a PNG image.
+    #[arg(long, value_name = "FILE")]
+    png: Option<PathBuf>,
+    /// Write the display as text.
+    #[arg(long, value_name = "FILE")]
+    text: Option<PathBuf>,
+    /// Record the sound to a WAV file.
+    #[arg(long, value_name = "FILE")]
+    wav: Option<PathBuf>,
+    /// Write the result to a file instead of standard output.
+    #[arg(long, value_name = "FILE")]
+    json: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    match Cli::parse().command {
+        Command::Run(args) => run(args),
+        Command::Tui { machine, braille } => {
+            let rendering = if braille {
+                Rendering::Braille
+            } else {
+                Rendering::HalfBlock
+            };
+            tui(&machine, rendering)
+        }
+        Command::Headless(args) => headless(args),
+        Command::Disasm { rom, octo } => {
+            let syntax = if octo { Syntax::Octo } else { Syntax::Cowgod };
+            disasm(&rom, syntax)
+        }
+        Command::Info { rom } => info(&rom),
+        Command::Asm { source, output } => {
+            let rom = output.unwrap_or_else(|| source.with_extension("ch8"));
+            asm(&source, &rom)
+        }
+        Command::Gdb { machine, port } => gdb(&machine, port),
+        Command::Trace {
+            machine,
+            steps,
+            output,
+        } => trace(&machine, steps, output.as_deref()),
+        Command::TraceDiff { first, second } => trace_diff(&first, &second),
+    }
+}
 
-fn main() {
-    let mut frontend = DesktopFrontend::new("./roms/space_invaders.ch8", 640, 320);
+impl Machine {
+    /// The options set on the command line, which override the configuration file.
+    fn settings(&self) -> Settings {
+        Settings {
+            platform: self.platform,
+            quirks: self
+                .quirks
+                .iter()
+                .map(|&(name, enabled)| (name.to_string(), enabled))
+                .collect(),
+            ips: self.ips,
+            ..Settings::default()
+        }
+    }
+
+    fn quirks(&self) -> Quirks {
+        self.settings().apply_quirks(Quirks::default())
+    }
+
+    fn builder(&self) -> EmulatorBuilder {
+        let builder = EmulatorBuilder::new()
+            .quirks(self.quirks())
+            .clock_speed(self.ips.unwrap_or(EmulatorBuilder::DEFAULT_CLOCK_SPEED));
+        match self.seed {
+            Some(seed) => builder.seed(seed),
+            None => builder,
+        }
+    }
+
+    /// Load the ROM, explaining what went wrong when it cannot be.
+    fn load(&self) -> Result<Emulator, String> {
+        self.builder()
+            .build_from_file(&self.rom)
+            .map_err(|error| load_error(&self.rom, error))
+    }
+}
+
+/// A `--quirks` entry, the name of a quirk with an optional `no-` prefix.
+fn parse_quirk(text: &str) -> Result<(&'static str, bool), String> {
+    let (name, enabled) = match text.strip_prefix("no-") {
+        Some(name) => (name, false),
+        None => (text, true),
+    };
+    Quirks::NAMES
+        .into_iter()
+        .find(|&known| known == name)
+        .map(|name| (name, enabled))
+        .ok_or_else(|| {
+            format!(
+                "unknown quirk {name}, expected {}",
+                Quirks::NAMES.join(", ")
+            )
+        })
+}
+
+fn parse_frequency(text: &str) -> Result<f32, String> {
+    parse_within(text, config::FREQUENCIES)
+}
+
+fn parse_volume(text: &str) -> Result<f32, String> {
+    parse_within(text, config::VOLUMES)
+}
+
+/// A number in `range`.
+fn parse_within(text: &str, range: RangeInclusive<f32>) -> Result<f32, String> {
+    text.parse()
+        .ok()
+        .filter(|value| range.contains(value))
+        .ok_or_else(|| {
+            format!(
+                "expected a number from {} to {}",
+                range.start(),
+                range.end()
+            )
+        })
+}
+
+/// Why `rom` could not be loaded, pointing at a ROM with a similar name when it does not
+/// exist.
+fn load_error(rom: &Path, error: LoadError) -> String {
+    match error {
+        LoadError::Io(error) if error.kind() == io::ErrorKind::NotFound => match similar_rom(rom) {
+            Some(similar) => format!(
+                "could not find the ROM {}, did you mean {}?",
+                rom.display(),
+                similar.display()
+            ),
+            None => format!("could not find the ROM {}", rom.display()),
+        },
+        error => format!("could not load {}: {error}", rom.display()),
+    }
+}
+
+/// A file next to `rom` with the same name but for case and extension.
+fn similar_rom(rom: &Path) -> Option<PathBuf> {
+    let stem = rom.file_stem()?.to_str()?.to_lowercase();
+    let directory = rom
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| rom.with_file_name(entry.file_name()))
+        .find(|path| {
+            path.file_stem()
+                .and_then(OsStr::to_str)
+                .is_some_and(|other| other.to_lowercase() == stem)
+        })
+}
+
+/// Read a ROM that is not run, for the commands that only look at it.
+fn read_rom(rom: &Path) -> Result<Vec<u8>, String> {
+    fs::read(rom).map_err(|error| load_error(rom, error.into()))
+}
+
+fn run(args: RunArgs) -> ExitCode {
+    let config = match &args.config {
+        Some(path) => Config::load_from(path),
+        None => Config::load(),
+    };
+    let config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let database = match config.database() {
+        Ok(database) => database,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let emulator = match args.machine.load() {
+        Ok(emulator) => emulator,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let settings = Settings {
+        scale: args.scale,
+        palette: args.palette,
+        rewind_speed: args.rewind_speed,
+        waveform: args.waveform,
+        frequency: args.frequency,
+        volume: args.volume,
+        ..args.machine.settings()
+    }
+    .over(config.settings(&emulator.rom_hash(), &database));
+    let mut frontend = DesktopFrontend::with_emulator(emulator, &args.machine.rom, settings);
+    frontend.set_muted(args.mute);
+    frontend.set_fullscreen(args.fullscreen);
     frontend.run();
+    ExitCode::SUCCESS
+}
+
+/// Run `machine` in the terminal.
+fn tui(machine: &Machine, rendering: Rendering) -> ExitCode {
+    let emulator = match machine.load() {
+        Ok(emulator) => emulator,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match TuiFrontend::new(emulator).rendering(rendering).try_run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("the terminal failed: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn disasm(rom: &Path, syntax: Syntax) -> ExitCode {
+    match read_rom(rom) {
+        Ok(bytes) => {
+            let disassembly = disassemble(&bytes, EmulatorBuilder::DEFAULT_LOAD_ADDRESS);
+            print!("{}", disassembly.render(syntax));
+            ExitCode::SUCCESS
+        }
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Print what can be told about `rom` without running it.
+fn info(rom: &Path) -> ExitCode {
+    let bytes = match read_rom(rom) {
+        Ok(bytes) => bytes,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let emulator = match Emulator::from_bytes(&bytes) {
+        Ok(emulator) => emulator,
+        Err(error) => {
+            eprintln!("could not load {}: {error}", rom.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let origin = EmulatorBuilder::DEFAULT_LOAD_ADDRESS;
+    let disassembly = disassemble(&bytes, origin);
+    let instructions = (0..bytes.len() as u16)
+        .filter(|&offset| disassembly.is_code(origin + offset))
+        .count();
+    let hash: String = emulator
+        .rom_hash()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    println!("rom           {}", rom.display());
+    println!("size          {} bytes", bytes.len());
+    println!("sha1          {hash}");
+    // The database only adds to what the ROM itself shows, so a broken one is no reason
+    // to fail.
+    let database = Config::load()
+        .map_err(|error| error.to_string())
+        .and_then(|config| config.database().map_err(|error| error.to_string()))
+        .unwrap_or_else(|message| {
+            eprintln!("{message}");
+            Database::default()
+        });
+    if let Some(entry) = database.get(&emulator.rom_hash()) {
+        println!("title         {}", entry.title);
+        println!("platform      {}", entry.platform);
+    }
+    println!("instructions  {instructions} reachable");
+    println!("labels        {}", disassembly.labels().len());
+    ExitCode::SUCCESS
+}
+
+/// Assemble `source` into `rom`, writing the symbol table next to it for the debugger.
+fn asm(source: &Path, rom: &Path) -> ExitCode {
+    let text = match fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(error) => {
+            eprintln!("could not read {}: {error}", source.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let program = match assemble(&text) {
+        Ok(program) => program,
+        Err(error) => {
+            eprintln!("{}:{error}", source.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    let symbols = rom.with_extension("sym");
+    let written =
+        fs::write(rom, &program.bytes).and_then(|()| fs::write(&symbols, program.symbol_table()));
+    match written {
+        Ok(()) => {
+            println!("{}: {} bytes", rom.display(), program.bytes.len());
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("could not write {}: {error}", rom.display());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Serve `machine` to a GDB front-end on a local port until it detaches.
+fn gdb(machine: &Machine, port: u16) -> ExitCode {
+    let emulator = match machine.load() {
+        Ok(emulator) => emulator,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    // The symbol table `capp8 asm` writes names labels and sets breakpoints.
+    let mut debugger = Debugger::new(emulator);
+    let symbols = machine.rom.with_extension("sym");
+    match fs::read_to_string(&symbols) {
+        Ok(table) => debugger.load_symbols(&table),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+        Err(error) => {
+            eprintln!("could not read {}: {error}", symbols.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    println!("waiting for a debugger on 127.0.0.1:{port}");
+    match GdbStub::new(debugger).listen(("127.0.0.1", port)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("gdb connection failed: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Run `machine` without a window for a number of steps, tracing every instruction to a
+/// file or standard output.
+fn trace(machine: &Machine, steps: u64, output: Option<&Path>) -> ExitCode {
+    let mut emulator = match machine.load() {
+        Ok(emulator) => emulator,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let tracer = match output {
+        Some(path) => match Tracer::to_file(path) {
+            Ok(tracer) => tracer,
+            Err(error) => {
+                eprintln!("could not create {}: {error}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Tracer::to_writer(io::stdout()),
+    };
+    emulator.set_tracer(Some(tracer));
+    let dt = Duration::from_secs(1) / emulator.clock_speed().max(1);
+    let mut status = ExitCode::SUCCESS;
+    for _ in 0..steps {
+        match emulator.step(dt) {
+            Ok(_) if emulator.is_halted() => break,
+            Ok(_) => {}
+            Err(error) => {
+                eprintln!("{error}");
+                status = ExitCode::FAILURE;
+                break;
+            }
+        }
+    }
+    if let Some(Err(error)) = emulator.take_tracer().map(Tracer::finish) {
+        eprintln!("could not write the trace: {error}");
+        return ExitCode::FAILURE;
+    }
+    status
+}
+
+/// Compare two traces, exiting like `diff`: 0 if they match, 1 if they diverge and 2 if
+/// they could not be read.
+fn trace_diff(first: &Path, second: &Path) -> ExitCode {
+    let read = |path: &Path| {
+        fs::read_to_string(path)
+            .map_err(|error| eprintln!("could not read {}: {error}", path.display()))
+    };
+    let (Ok(first_text), Ok(second_text)) = (read(first), read(second)) else {
+        return ExitCode::from(2);
+    };
+    match trace::diff(&first_text, &second_text) {
+        Ok(None) => {
+            println!("the traces match");
+            ExitCode::SUCCESS
+        }
+        Ok(Some(divergence)) => {
+            print!("{divergence}");
+            ExitCode::from(1)
+        }
+        Err(error) => {
+            eprintln!("could not read the traces: {error}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Run without a window until the program stops, then write the display and the result.
+fn headless(args: HeadlessArgs) -> ExitCode {
+    let script = match &args.input {
+        Some(path) => {
+            let parsed = fs::read_to_string(path)
+                .map_err(|error| format!("could not read {}: {error}", path.display()))
+                .and_then(|text| {
+                    InputScript::parse(&text).map_err(|error| format!("{}:{error}", path.display()))
+                });
+            match parsed {
+                Ok(script) => script,
+                Err(message) => {
+                    eprintln!("{message}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => InputScript::new(),
+    };
+    let loaded = match &args.movie {
+        Some(path) => load_movie(&args.machine, path).map(|(emulator, player)| {
+            let frontend = HeadlessFrontend::new(emulator).movie(player);
+            match args.frames {
+                Some(frames) => frontend.stop_when(StopCondition::Frames(frames)),
+                None => frontend,
+            }
+        }),
+        None => args.machine.load().map(|emulator| {
+            let frames = args.frames.unwrap_or(HEADLESS_FRAMES);
+            HeadlessFrontend::new(emulator)
+                .script(script)
+                .stop_when(StopCondition::Frames(frames))
+        }),
+    };
+    let frontend = match loaded {
+        Ok(frontend) => frontend,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut conditions = args.until;
+    if args.until_loop {
+        conditions.push(StopCondition::PcLoop);
+    }
+    let mut frontend = conditions
+        .into_iter()
+        .fold(frontend, HeadlessFrontend::stop_when);
+    if args.wav.is_some() {
+        frontend = frontend.record_audio(WAV_SAMPLE_RATE);
+    }
+    let result = frontend.run_to_end();
+    let display = frontend.emulator().display();
+    let outputs = [
+        args.pbm
+            .as_deref()
+            .map(|path| (path, fs::write(path, dump::to_pbm(display)))),
+        args.text
+            .as_deref()
+            .map(|path| (path, fs::write(path, dump::to_text(display)))),
+        args.png.as_deref().map(|path| {
+            let written = fs::File::create(path)
+                .and_then(|file| dump::write_png(display, 1, io::BufWriter::new(file)));
+            (path, written)
+        }),
+        args.wav.as_deref().map(|path| {
+            let written = fs::File::create(path).and_then(|file| {
+                dump::write_wav(frontend.audio(), WAV_SAMPLE_RATE, io::BufWriter::new(file))
+            });
+            (path, written)
+        }),
+        args.json
+            .as_deref()
+            .map(|path| (path, fs::write(path, result.to_json()))),
+    ];
+    for (path, written) in outputs.into_iter().flatten() {
+        if let Err(error) = written {
+            eprintln!("could not write {}: {error}", path.display());
+            return ExitCode::FAILURE;
+        }
+    }
+    if args.json.is_none() {
+        println!("{}", result.to_json());
+    }
+    match result.reason {
+        StopReason::Error | StopReason::Desync => ExitCode::FAILURE,
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// Load the ROM the way the movie at `path` was recorded, ready to play it back.
+fn load_movie(machine: &Machine, path: &Path) -> Result<(Emulator, MoviePlayer), String> {
+    let movie =
+        Movie::load(path).map_err(|error| format!("could not load {}: {error}", path.display()))?;
+    let emulator = movie
+        .configure(machine.builder())
+        .build_from_file(&machine.rom)
+        .map_err(|error| load_error(&machine.rom, error))?;
+    let player = MoviePlayer::new(movie, &emulator)
+        .map_err(|error| format!("could not play {}: {error}", path.display()))?;
+    Ok((emulator, player))
+}
+
+/// An `--until` condition, `<addr>=<byte>` with the address in hex and the byte in
+/// decimal or `0x` hex.
+fn parse_memory_condition(text: &str) -> Result<StopCondition, String> {
+    let invalid = || format!("expected <addr>=<byte>, found {text}");
+    let (addr, value) = text.split_once('=').ok_or_else(invalid)?;
+    let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16).map_err(|_| invalid())?;
+    let value = match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| invalid())?;
+    Ok(StopCondition::Memory { addr, value })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn parses_the_machine_options() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "capp8",
+            "run",
+            "pong.ch8",
+            "--platform",
+            "schip",
+            "--quirks",
+            "no-shift,display-wait",
+            "--ips",
+            "1200",
+        ])
+        .unwrap();
+        let Command::Run(args) = cli.command else {
+            panic!("expected the run command");
+        };
+        assert_eq!(
+            args.machine.quirks(),
+            Quirks {
+                shift: false,
+                display_wait: true,
+                ..Quirks::SUPER_CHIP
+            }
+        );
+        assert_eq!((args.machine.ips, args.scale), (Some(1200), None));
+
+        assert!(Cli::try_parse_from(["capp8", "run", "pong.ch8", "--quirks", "wrap"]).is_err());
+        assert!(Cli::try_parse_from(["capp8", "run", "pong.ch8", "--ips", "0"]).is_err());
+
+        let cli = Cli::try_parse_from(["capp8", "run", "pong.ch8", "--frequency", "220"]).unwrap();
+        let Command::Run(args) = cli.command else {
+            panic!("expected the run command");
+        };
+        assert_eq!((args.frequency, args.volume), (Some(220.0), None));
+        assert!(Cli::try_parse_from(["capp8", "run", "pong.ch8", "--volume", "2"]).is_err());
+
+        let cli = Cli::try_parse_from(["capp8", "headless", "pong.ch8", "--movie", "pong.c8m"]);
+        let Command::Headless(args) = cli.unwrap().command else {
+            panic!("expected the headless command");
+        };
+        assert_eq!(args.movie.as_deref(), Some(Path::new("pong.c8m")));
+        assert_eq!(args.frames, None);
+        let both = [
+            "capp8", "headless", "pong.ch8", "--movie", "a.c8m", "--input", "keys",
+        ];
+        assert!(Cli::try_parse_from(both).is_err());
+    }
 }
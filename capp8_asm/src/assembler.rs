@@ -0,0 +1,847 @@
+//! A single pass over the token stream that emits bytes as it goes and patches forward
+//! references to labels once the whole source has been read.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use capp8_core::instruction::Instruction;
+
+use crate::{
+    Program, calc,
+    error::{AsmError, AsmErrorKind},
+    lexer::{Token, parse_number, tokenize},
+};
+
+/// Where programs are loaded.
+pub const ORIGIN: usize = 0x200;
+/// How many macro invocations a source may expand, to catch recursive macros.
+const MAX_EXPANSIONS: usize = 100_000;
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a forward reference is filled in once the label is known.
+#[derive(Clone, Copy)]
+enum Width {
+    /// The low 12 bits of the opcode at the patched address.
+    Short,
+    /// The whole 16 bit word at the patched address.
+    Long,
+}
+
+struct Patch {
+    at: usize,
+    width: Width,
+    name: Token,
+}
+
+/// An open structured control flow block.
+enum Block {
+    /// `if ... begin`, with the jump taken when the condition does not hold.
+    Begin { jump: usize, token: Token },
+    /// `else`, with the jump over the else branch.
+    Else { jump: usize, token: Token },
+    /// `loop`, with the jumps out of it made by `while`.
+    Loop {
+        start: usize,
+        exits: Vec<usize>,
+        token: Token,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Key,
+    NotKey,
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(usize),
+    Byte(u8),
+}
+
+#[derive(Clone, Copy)]
+struct Condition {
+    reg: usize,
+    comparison: Comparison,
+    operand: Operand,
+}
+
+impl Condition {
+    fn negate(self) -> Self {
+        let comparison = match self.comparison {
+            Comparison::Eq => Comparison::Ne,
+            Comparison::Ne => Comparison::Eq,
+            Comparison::Lt => Comparison::Ge,
+            Comparison::Ge => Comparison::Lt,
+            Comparison::Gt => Comparison::Le,
+            Comparison::Le => Comparison::Gt,
+            Comparison::Key => Comparison::NotKey,
+            Comparison::NotKey => Comparison::Key,
+        };
+        Self { comparison, ..self }
+    }
+}
+
+pub(crate) struct Assembler {
+    tokens: VecDeque<Token>,
+    /// The last token read, for errors at the end of the source.
+    last: Token,
+    memory: Vec<u8>,
+    here: usize,
+    /// One past the highest address written.
+    end: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    patches: Vec<Patch>,
+    blocks: Vec<Block>,
+    breakpoints: BTreeMap<u16, String>,
+    expansions: usize,
+    /// Whether a jump to `main` is due before the first byte, if `main` is not there.
+    jump_to_main: bool,
+}
+
+impl Assembler {
+    pub(crate) fn new(source: &str) -> Self {
+        Self {
+            tokens: tokenize(source).into(),
+            last: Token {
+                text: String::new(),
+                line: 1,
+                column: 1,
+            },
+            memory: vec![0; 0x10000],
+            here: ORIGIN,
+            end: ORIGIN,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            patches: Vec::new(),
+            blocks: Vec::new(),
+            breakpoints: BTreeMap::new(),
+            expansions: 0,
+            jump_to_main: false,
+        }
+    }
+
+    pub(crate) fn run(mut self) -> Result<Program, AsmError> {
+        // Like Octo, start with a jump to `main` when the program defines it elsewhere.
+        let tokens: Vec<&Token> = self.tokens.iter().collect();
+        self.jump_to_main = tokens
+            .windows(2)
+            .any(|pair| pair[0].text == ":" && pair[1].text == "main");
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.last = token.clone();
+            self.statement(token)?;
+        }
+        if let Some(block) = self.blocks.pop() {
+            let (Block::Begin { token, .. }
+            | Block::Else { token, .. }
+            | Block::Loop { token, .. }) = block;
+            return Err(error(&token, AsmErrorKind::Unbalanced(token.text.clone())));
+        }
+
+        for patch in std::mem::take(&mut self.patches) {
+            let addr = *self.labels.get(&patch.name.text).ok_or_else(|| {
+                error(
+                    &patch.name,
+                    AsmErrorKind::Undefined(patch.name.text.clone()),
+                )
+            })?;
+            let word = match patch.width {
+                Width::Short => {
+                    check(&patch.name, addr as f64, 0xFFF, "12 bits")?;
+                    u16::from_be_bytes([self.memory[patch.at], self.memory[patch.at + 1]]) | addr
+                }
+                Width::Long => addr,
+            };
+            self.memory[patch.at..patch.at + 2].copy_from_slice(&word.to_be_bytes());
+        }
+
+        Ok(Program {
+            bytes: self.memory[ORIGIN..self.end].to_vec(),
+            labels: self.labels,
+            breakpoints: self.breakpoints,
+        })
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| error(&self.last, AsmErrorKind::UnexpectedEnd))?;
+        self.last = token.clone();
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &'static str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(expected(&token, text));
+        }
+        Ok(token)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        let simple = match token.text.as_str() {
+            "clear" => Some(Instruction::ClearScreen),
+            "return" | ";" => Some(Instruction::Return),
+            "scroll-left" => Some(Instruction::ScrollLeft),
+            "scroll-right" => Some(Instruction::ScrollRight),
+            "exit" => Some(Instruction::Exit),
+            "lores" => Some(Instruction::LowRes),
+            "hires" => Some(Instruction::HighRes),
+            "audio" => Some(Instruction::LoadAudioPattern),
+            _ => None,
+        };
+        if let Some(instruction) = simple {
+            return self.emit(&token, instruction);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.definition()?;
+                self.labels.insert(name, self.here as u16);
+            }
+            ":const" => {
+                let name = self.definition()?;
+                let value = self.next()?;
+                let value = self.value(&value)?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.definition()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+            }
+            ":calc" => {
+                let name = self.definition()?;
+                let value = self.calc()?.1;
+                self.constants.insert(name, value);
+            }
+            ":macro" => self.define_macro()?,
+            ":org" => {
+                let addr = self.next()?;
+                let value = self.value(&addr)?;
+                self.here = check(&addr, value, 0xFFFF, "the address space")? as usize;
+            }
+            ":byte" => {
+                let byte = if self.tokens.front().is_some_and(|next| next.text == "{") {
+                    let (open, value) = self.calc()?;
+                    check(&open, value, 0xFF, "a byte")? as u8
+                } else {
+                    self.byte()?
+                };
+                self.emit_byte(&token, byte)?;
+            }
+            ":pointer" => {
+                let at = self.here;
+                let addr = self.address(at, Width::Long)?;
+                self.emit_bytes(&token, &addr.to_be_bytes())?;
+            }
+            ":call" => {
+                let at = self.here;
+                let addr = self.address(at, Width::Short)?;
+                self.emit(&token, Instruction::Call { addr })?;
+            }
+            ":breakpoint" => {
+                let name = self.next()?;
+                self.breakpoints.insert(self.here as u16, name.text);
+            }
+            ":assert" => {
+                if self.calc()?.1 == 0.0 {
+                    return Err(error(&token, AsmErrorKind::AssertionFailed));
+                }
+            }
+            "scroll-down" | "scroll-up" | "plane" => {
+                let n = self.nibble()?;
+                let instruction = match token.text.as_str() {
+                    "scroll-down" => Instruction::ScrollDown { rows: n },
+                    "scroll-up" => Instruction::ScrollUp { rows: n },
+                    _ => Instruction::SelectPlanes { planes: n },
+                };
+                self.emit(&token, instruction)?;
+            }
+            "save" | "load" => {
+                let reg_x = self.register()?;
+                let ranged = self.tokens.front().is_some_and(|next| next.text == "-");
+                let save = token.text == "save";
+                let instruction = if ranged {
+                    self.next()?;
+                    let reg_y = self.register()?;
+                    if save {
+                        Instruction::StoreRegisterRange { reg_x, reg_y }
+                    } else {
+                        Instruction::LoadRegisterRange { reg_x, reg_y }
+                    }
+                } else if save {
+                    Instruction::StoreRegisters { reg: reg_x }
+                } else {
+                    Instruction::LoadRegisters { reg: reg_x }
+                };
+                self.emit(&token, instruction)?;
+            }
+            "bcd" | "saveflags" | "loadflags" => {
+                let reg = self.register()?;
+                let instruction = match token.text.as_str() {
+                    "bcd" => Instruction::StoreBCD { reg },
+                    "saveflags" => Instruction::StoreFlags { reg },
+                    _ => Instruction::LoadFlags { reg },
+                };
+                self.emit(&token, instruction)?;
+            }
+            "sprite" => {
+                let reg_x = self.register()?;
+                let reg_y = self.register()?;
+                let nibble = self.nibble()?;
+                self.emit(
+                    &token,
+                    Instruction::Draw {
+                        reg_x,
+                        reg_y,
+                        nibble,
+                    },
+                )?;
+            }
+            "jump" | "jump0" | "native" => {
+                let at = self.here;
+                let addr = self.address(at, Width::Short)?;
+                let instruction = match token.text.as_str() {
+                    "jump" => Instruction::Jump { addr },
+                    "jump0" => Instruction::JumpWithOffset { addr },
+                    _ => Instruction::Sys { addr },
+                };
+                self.emit(&token, instruction)?;
+            }
+            "i" => self.index(&token)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let reg = self.register()?;
+                let instruction = match token.text.as_str() {
+                    "delay" => Instruction::SetDelayTimer { reg },
+                    "buzzer" => Instruction::SetSoundTimer { reg },
+                    _ => Instruction::SetPitch { reg },
+                };
+                self.emit(&token, instruction)?;
+            }
+            "if" => self.conditional()?,
+            "else" => match self.blocks.pop() {
+                Some(Block::Begin { jump, .. }) => {
+                    let skip = self.here;
+                    self.emit(&token, Instruction::Jump { addr: 0 })?;
+                    self.patch_jump(&token, jump, self.here)?;
+                    self.blocks.push(Block::Else { jump: skip, token });
+                }
+                _ => return Err(error(&token, AsmErrorKind::Unbalanced(token.text.clone()))),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::Begin { jump, .. } | Block::Else { jump, .. }) => {
+                    self.patch_jump(&token, jump, self.here)?;
+                }
+                _ => return Err(error(&token, AsmErrorKind::Unbalanced(token.text.clone()))),
+            },
+            "loop" => self.blocks.push(Block::Loop {
+                start: self.here,
+                exits: Vec::new(),
+                token,
+            }),
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_condition(&token, condition.negate())?;
+                let exit = self.here;
+                self.emit(&token, Instruction::Jump { addr: 0 })?;
+                let innermost = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost {
+                    Some(exits) => exits.push(exit),
+                    None => {
+                        return Err(error(&token, AsmErrorKind::Unbalanced(token.text.clone())));
+                    }
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(&token, Instruction::Jump { addr: start as u16 })?;
+                    for exit in exits {
+                        self.patch_jump(&token, exit, self.here)?;
+                    }
+                }
+                _ => return Err(error(&token, AsmErrorKind::Unbalanced(token.text.clone()))),
+            },
+            text if self.macros.contains_key(text) => self.expand(&token)?,
+            text if self.is_register(text) => self.arithmetic(&token)?,
+            text if parse_number(text).is_some() || self.constants.contains_key(text) => {
+                let byte = self.byte_of(&token)?;
+                self.emit_byte(&token, byte)?;
+            }
+            // Anything else names a subroutine, possibly defined further down.
+            _ => {
+                let at = self.here;
+                let addr = self.address_of(&token, at, Width::Short)?;
+                self.emit(&token, Instruction::Call { addr })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the name being defined and check that it is free.
+    fn definition(&mut self) -> Result<String, AsmError> {
+        let token = self.next()?;
+        let name = token.text.as_str();
+        if parse_number(name).is_some() || self.is_register(name) || name.starts_with(':') {
+            return Err(expected(&token, "a name"));
+        }
+        if self.labels.contains_key(name)
+            || self.constants.contains_key(name)
+            || self.aliases.contains_key(name)
+            || self.macros.contains_key(name)
+        {
+            return Err(error(&token, AsmErrorKind::Redefined(token.text.clone())));
+        }
+        Ok(token.text)
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.definition()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "{" {
+                break;
+            }
+            params.push(token.text);
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Replace a macro invocation by its body, with the arguments substituted.
+    fn expand(&mut self, token: &Token) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(error(
+                token,
+                AsmErrorKind::MacroRecursion(token.text.clone()),
+            ));
+        }
+        let params = self.macros[&token.text].params.clone();
+        let mut args = Vec::new();
+        for _ in &params {
+            args.push(self.next()?);
+        }
+        let body = &self.macros[&token.text].body;
+        for body_token in body.iter().rev() {
+            let arg = params.iter().position(|param| *param == body_token.text);
+            let token = arg.map_or(body_token, |arg| &args[arg]);
+            self.tokens.push_front(token.clone());
+        }
+        Ok(())
+    }
+
+    /// Read a `{ expression }`, returning the opening brace and the value.
+    fn calc(&mut self) -> Result<(Token, f64), AsmError> {
+        let open = self.expect("{")?;
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.text == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let value = calc::evaluate(&tokens, &open, &|name| self.lookup(name))?;
+        Ok((open, value))
+    }
+
+    fn lookup(&self, name: &str) -> Option<f64> {
+        match name {
+            "HERE" => Some(self.here as f64),
+            _ => self
+                .constants
+                .get(name)
+                .copied()
+                .or_else(|| self.labels.get(name).map(|&addr| addr as f64)),
+        }
+    }
+
+    /// The value of a number, constant or already defined label.
+    fn value(&self, token: &Token) -> Result<f64, AsmError> {
+        parse_number(&token.text)
+            .map(|value| value as f64)
+            .or_else(|| self.lookup(&token.text))
+            .ok_or_else(|| error(token, AsmErrorKind::Undefined(token.text.clone())))
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.byte_of(&token)
+    }
+
+    /// A byte operand. Negative values down to -128 are stored in two's complement.
+    fn byte_of(&self, token: &Token) -> Result<u8, AsmError> {
+        let value = self.value(token)?;
+        if !(-128.0..=255.0).contains(&value) {
+            return Err(error(
+                token,
+                AsmErrorKind::OutOfRange {
+                    value,
+                    limit: "a byte",
+                },
+            ));
+        }
+        Ok(value as i64 as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        let value = self.value(&token)?;
+        Ok(check(&token, value, 0xF, "a nibble")? as u8)
+    }
+
+    fn address(&mut self, at: usize, width: Width) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        self.address_of(&token, at, width)
+    }
+
+    /// The address `token` refers to. Labels that are not defined yet are patched into
+    /// the instruction at `at` at the end.
+    fn address_of(&mut self, token: &Token, at: usize, width: Width) -> Result<u16, AsmError> {
+        let (limit, name) = match width {
+            Width::Short => (0xFFF, "12 bits"),
+            Width::Long => (0xFFFF, "16 bits"),
+        };
+        if let Ok(value) = self.value(token) {
+            return Ok(check(token, value, limit, name)? as u16);
+        }
+        if self.is_register(&token.text) || token.text.starts_with(':') {
+            return Err(expected(token, "an address"));
+        }
+        self.patches.push(Patch {
+            at,
+            width,
+            name: token.clone(),
+        });
+        Ok(0)
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        register_index(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    fn register(&mut self) -> Result<usize, AsmError> {
+        let token = self.next()?;
+        self.register_of(&token)
+    }
+
+    fn register_of(&self, token: &Token) -> Result<usize, AsmError> {
+        register_index(&token.text)
+            .or_else(|| self.aliases.get(&token.text).copied())
+            .ok_or_else(|| expected(token, "a register"))
+    }
+
+    /// `vx := ...`, `vx += ...` and the other register assignments.
+    fn arithmetic(&mut self, token: &Token) -> Result<(), AsmError> {
+        let reg_x = self.register_of(token)?;
+        let op = self.next()?;
+        let source = self.next()?;
+        let reg_y = self.register_of(&source).ok();
+        let instruction = match (op.text.as_str(), reg_y) {
+            (":=", Some(reg_y)) => Instruction::StoreRegFromReg { reg_x, reg_y },
+            (":=", None) => match source.text.as_str() {
+                "random" => Instruction::Rand {
+                    reg: reg_x,
+                    mask: self.byte()?,
+                },
+                "key" => Instruction::WaitKeyPress { reg: reg_x },
+                "delay" => Instruction::LoadDelayTimer { reg: reg_x },
+                _ => Instruction::StoreRegFromImm {
+                    reg: reg_x,
+                    imm: self.byte_of(&source)?,
+                },
+            },
+            ("+=", Some(reg_y)) => Instruction::AddRegReg { reg_x, reg_y },
+            ("+=", None) => Instruction::AddRegImm {
+                reg: reg_x,
+                imm: self.byte_of(&source)?,
+            },
+            ("-=", Some(reg_y)) => Instruction::SubRegReg { reg_x, reg_y },
+            ("-=", None) => Instruction::AddRegImm {
+                reg: reg_x,
+                imm: self.byte_of(&source)?.wrapping_neg(),
+            },
+            ("=-", Some(reg_y)) => Instruction::SubnRegReg { reg_x, reg_y },
+            ("|=", Some(reg_y)) => Instruction::OrRegReg { reg_x, reg_y },
+            ("&=", Some(reg_y)) => Instruction::AndRegReg { reg_x, reg_y },
+            ("^=", Some(reg_y)) => Instruction::XorRegReg { reg_x, reg_y },
+            (">>=", Some(reg_y)) => Instruction::ShiftRight { reg_x, reg_y },
+            ("<<=", Some(reg_y)) => Instruction::ShiftLeft { reg_x, reg_y },
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(expected(&source, "a register"));
+            }
+            _ => return Err(expected(&op, "an assignment operator")),
+        };
+        self.emit(token, instruction)
+    }
+
+    /// `i := addr`, `i := long addr`, `i := hex vx`, `i := bighex vx` and `i += vx`.
+    fn index(&mut self, token: &Token) -> Result<(), AsmError> {
+        let op = self.next()?;
+        let instruction = match op.text.as_str() {
+            ":=" => {
+                let source = self.next()?;
+                match source.text.as_str() {
+                    "hex" => Instruction::SetIToSprite {
+                        reg: self.register()?,
+                    },
+                    "bighex" => Instruction::SetIToBigSprite {
+                        reg: self.register()?,
+                    },
+                    "long" => {
+                        self.emit_bytes(token, &[0xF0, 0x00])?;
+                        let at = self.here;
+                        let addr = self.address(at, Width::Long)?;
+                        return self.emit_bytes(token, &addr.to_be_bytes());
+                    }
+                    _ => {
+                        let at = self.here;
+                        Instruction::SetI {
+                            addr: self.address_of(&source, at, Width::Short)?,
+                        }
+                    }
+                }
+            }
+            "+=" => Instruction::AddI {
+                reg: self.register()?,
+            },
+            _ => return Err(expected(&op, "`:=` or `+=`")),
+        };
+        self.emit(token, instruction)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let reg = self.register()?;
+        let op = self.next()?;
+        let comparison = match op.text.as_str() {
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            ">" => Comparison::Gt,
+            "<=" => Comparison::Le,
+            ">=" => Comparison::Ge,
+            _ => return Err(expected(&op, "a comparison")),
+        };
+        let operand = match comparison {
+            Comparison::Key | Comparison::NotKey => Operand::Byte(0),
+            _ => {
+                let token = self.next()?;
+                match self.register_of(&token) {
+                    Ok(reg) => Operand::Register(reg),
+                    Err(_) => Operand::Byte(self.byte_of(&token)?),
+                }
+            }
+        };
+        Ok(Condition {
+            reg,
+            comparison,
+            operand,
+        })
+    }
+
+    /// `if ... then` runs the next statement when the condition holds; `if ... begin`
+    /// jumps past the block when it does not.
+    fn conditional(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let token = self.next()?;
+        match token.text.as_str() {
+            "then" => self.emit_condition(&token, condition),
+            "begin" => {
+                self.emit_condition(&token, condition.negate())?;
+                let jump = self.here;
+                self.emit(&token, Instruction::Jump { addr: 0 })?;
+                self.blocks.push(Block::Begin { jump, token });
+                Ok(())
+            }
+            _ => Err(expected(&token, "`then` or `begin`")),
+        }
+    }
+
+    /// Emit the instructions after which the next one only runs when `condition` holds.
+    /// The ordering comparisons compute a subtraction in VF and test its borrow flag.
+    fn emit_condition(&mut self, token: &Token, condition: Condition) -> Result<(), AsmError> {
+        let Condition {
+            reg,
+            comparison,
+            operand,
+        } = condition;
+        let skip = match (comparison, operand) {
+            (Comparison::Eq, Operand::Byte(imm)) => Instruction::SkipRegNeqImm { reg, imm },
+            (Comparison::Eq, Operand::Register(reg_y)) => {
+                Instruction::SkipRegNeqReg { reg_x: reg, reg_y }
+            }
+            (Comparison::Ne, Operand::Byte(imm)) => Instruction::SkipRegEqImm { reg, imm },
+            (Comparison::Ne, Operand::Register(reg_y)) => {
+                Instruction::SkipRegEqReg { reg_x: reg, reg_y }
+            }
+            (Comparison::Key, _) => Instruction::SkipIfNotKey { reg },
+            (Comparison::NotKey, _) => Instruction::SkipIfKey { reg },
+            (Comparison::Lt | Comparison::Ge, operand) => {
+                // VF = 1 when reg >= operand.
+                match operand {
+                    Operand::Register(reg_y) => {
+                        self.emit(token, vf_from_register(reg))?;
+                        self.emit(token, Instruction::SubRegReg { reg_x: 0xF, reg_y })?;
+                    }
+                    Operand::Byte(imm) => {
+                        self.emit(token, Instruction::StoreRegFromImm { reg: 0xF, imm })?;
+                        self.emit(
+                            token,
+                            Instruction::SubnRegReg {
+                                reg_x: 0xF,
+                                reg_y: reg,
+                            },
+                        )?;
+                    }
+                }
+                let holds = matches!(comparison, Comparison::Ge) as u8;
+                Instruction::SkipRegNeqImm {
+                    reg: 0xF,
+                    imm: holds,
+                }
+            }
+            (Comparison::Gt | Comparison::Le, operand) => {
+                // VF = 1 when operand >= reg.
+                match operand {
+                    Operand::Register(reg_y) => self.emit(token, vf_from_register(reg_y))?,
+                    Operand::Byte(imm) => {
+                        self.emit(token, Instruction::StoreRegFromImm { reg: 0xF, imm })?
+                    }
+                }
+                self.emit(
+                    token,
+                    Instruction::SubRegReg {
+                        reg_x: 0xF,
+                        reg_y: reg,
+                    },
+                )?;
+                let holds = matches!(comparison, Comparison::Le) as u8;
+                Instruction::SkipRegNeqImm {
+                    reg: 0xF,
+                    imm: holds,
+                }
+            }
+        };
+        self.emit(token, skip)
+    }
+
+    fn patch_jump(&mut self, token: &Token, at: usize, target: usize) -> Result<(), AsmError> {
+        let target = check(token, target as f64, 0xFFF, "12 bits")? as u16;
+        let opcode = Instruction::Jump { addr: target }.encode();
+        self.memory[at..at + 2].copy_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, instruction: Instruction) -> Result<(), AsmError> {
+        self.emit_bytes(token, &instruction.encode().to_be_bytes())
+    }
+
+    fn emit_byte(&mut self, token: &Token, byte: u8) -> Result<(), AsmError> {
+        self.emit_bytes(token, &[byte])
+    }
+
+    fn emit_bytes(&mut self, token: &Token, bytes: &[u8]) -> Result<(), AsmError> {
+        if std::mem::take(&mut self.jump_to_main) && !self.labels.contains_key("main") {
+            self.patches.push(Patch {
+                at: self.here,
+                width: Width::Short,
+                name: Token {
+                    text: "main".to_string(),
+                    ..token.clone()
+                },
+            });
+            self.emit(token, Instruction::Jump { addr: 0 })?;
+        }
+        if self.here < ORIGIN || self.here + bytes.len() > self.memory.len() {
+            return Err(error(
+                token,
+                AsmErrorKind::OutOfRange {
+                    value: self.here as f64,
+                    limit: "the program space",
+                },
+            ));
+        }
+        self.memory[self.here..self.here + bytes.len()].copy_from_slice(bytes);
+        self.here += bytes.len();
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+}
+
+fn vf_from_register(reg: usize) -> Instruction {
+    Instruction::StoreRegFromReg {
+        reg_x: 0xF,
+        reg_y: reg,
+    }
+}
+
+/// The index of `v0` to `vf`, in either case.
+fn register_index(text: &str) -> Option<usize> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    usize::from_str_radix(digit, 16).ok()
+}
+
+/// Check that `value` is a whole number in `0..=max`.
+fn check(token: &Token, value: f64, max: u32, limit: &'static str) -> Result<u32, AsmError> {
+    if value < 0.0 || value > max as f64 || value.fract() != 0.0 {
+        return Err(error(token, AsmErrorKind::OutOfRange { value, limit }));
+    }
+    Ok(value as u32)
+}
+
+fn expected(token: &Token, expected: &'static str) -> AsmError {
+    error(
+        token,
+        AsmErrorKind::Expected {
+            expected,
+            found: token.text.clone(),
+        },
+    )
+}
+
+fn error(token: &Token, kind: AsmErrorKind) -> AsmError {
+    AsmError {
+        line: token.line,
+        column: token.column,
+        kind,
+    }
+}
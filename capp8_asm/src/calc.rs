@@ -0,0 +1,156 @@
+//! `:calc` expressions.
+//!
+//! Like Octo, expressions are evaluated right to left without operator precedence, so
+//! `2 * 3 + 1` is 8; use parentheses to group. Unary operators apply to everything on
+//! their right. Values are floating point and are only rounded where they are used.
+
+use crate::{
+    error::{AsmError, AsmErrorKind},
+    lexer::{Token, parse_number},
+};
+
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+const UNARY: [&str; 13] = [
+    "-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor",
+];
+
+/// Evaluate `tokens`, resolving names with `lookup`. `open` is the token before the
+/// expression, used to locate errors in empty expressions.
+pub(crate) fn evaluate(
+    tokens: &[Token],
+    open: &Token,
+    lookup: &dyn Fn(&str) -> Option<f64>,
+) -> Result<f64, AsmError> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        last: open,
+        lookup,
+    };
+    let value = parser.expression()?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(error(
+            token,
+            AsmErrorKind::UnexpectedToken(token.text.clone()),
+        )),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// The last token read, for errors at the end of the expression.
+    last: &'a Token,
+    lookup: &'a dyn Fn(&str) -> Option<f64>,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<&'a Token, AsmError> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| error(self.last, AsmErrorKind::UnexpectedEnd))?;
+        self.position += 1;
+        self.last = token;
+        Ok(token)
+    }
+
+    fn expression(&mut self) -> Result<f64, AsmError> {
+        let left = self.term()?;
+        match self.tokens.get(self.position) {
+            Some(token) if BINARY.contains(&token.text.as_str()) => {
+                self.position += 1;
+                self.last = token;
+                let right = self.expression()?;
+                Ok(binary(&token.text, left, right))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        let text = token.text.as_str();
+        if text == "(" {
+            let value = self.expression()?;
+            let close = self.next()?;
+            if close.text != ")" {
+                return Err(error(
+                    close,
+                    AsmErrorKind::Expected {
+                        expected: "`)`",
+                        found: close.text.clone(),
+                    },
+                ));
+            }
+            Ok(value)
+        } else if UNARY.contains(&text) {
+            Ok(unary(text, self.expression()?))
+        } else if let Some(value) = parse_number(text) {
+            Ok(value as f64)
+        } else if text == "PI" {
+            Ok(std::f64::consts::PI)
+        } else if text == "E" {
+            Ok(std::f64::consts::E)
+        } else {
+            (self.lookup)(text).ok_or_else(|| error(token, AsmErrorKind::Undefined(text.into())))
+        }
+    }
+}
+
+fn binary(op: &str, a: f64, b: f64) -> f64 {
+    let (x, y) = (a as i64, b as i64);
+    match op {
+        "+" => a + b,
+        "-" => a - b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        "&" => (x & y) as f64,
+        "|" => (x | y) as f64,
+        "^" => (x ^ y) as f64,
+        "<<" => x.checked_shl(y as u32).unwrap_or(0) as f64,
+        ">>" => x.checked_shr(y as u32).unwrap_or(0) as f64,
+        "pow" => a.powf(b),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "<" => (a < b) as u8 as f64,
+        "<=" => (a <= b) as u8 as f64,
+        "==" => (a == b) as u8 as f64,
+        "!=" => (a != b) as u8 as f64,
+        ">=" => (a >= b) as u8 as f64,
+        ">" => (a > b) as u8 as f64,
+        _ => unreachable!("not a binary operator: {op}"),
+    }
+}
+
+fn unary(op: &str, a: f64) -> f64 {
+    match op {
+        "-" => -a,
+        "~" => !(a as i64) as f64,
+        "!" => (a == 0.0) as u8 as f64,
+        "sin" => a.sin(),
+        "cos" => a.cos(),
+        "tan" => a.tan(),
+        "exp" => a.exp(),
+        "log" => a.ln(),
+        "abs" => a.abs(),
+        "sqrt" => a.sqrt(),
+        "sign" => a.signum(),
+        "ceil" => a.ceil(),
+        "floor" => a.floor(),
+        _ => unreachable!("not a unary operator: {op}"),
+    }
+}
+
+fn error(token: &Token, kind: AsmErrorKind) -> AsmError {
+    AsmError {
+        line: token.line,
+        column: token.column,
+        kind,
+    }
+}
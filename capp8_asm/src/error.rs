@@ -0,0 +1,62 @@
+use std::fmt;
+
+/// Why a program could not be assembled, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+    /// 1-based line of the offending token.
+    pub line: usize,
+    /// 1-based column of the offending token, in characters.
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AsmErrorKind {
+    /// The source ends in the middle of a statement.
+    UnexpectedEnd,
+    /// A token that cannot start a statement.
+    UnexpectedToken(String),
+    /// A specific kind of token was expected.
+    Expected {
+        expected: &'static str,
+        found: String,
+    },
+    /// A name that is never defined.
+    Undefined(String),
+    /// A label, constant, alias or macro defined twice.
+    Redefined(String),
+    /// A value that does not fit where it is used.
+    OutOfRange { value: f64, limit: &'static str },
+    /// `else`, `end`, `again` or `while` without the matching opening keyword, or a block
+    /// that is never closed.
+    Unbalanced(String),
+    /// An `:assert` whose expression evaluated to zero.
+    AssertionFailed,
+    /// Macros expanded too many times, most likely because one invokes itself.
+    MacroRecursion(String),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            AsmErrorKind::UnexpectedEnd => write!(f, "unexpected end of input"),
+            AsmErrorKind::UnexpectedToken(token) => write!(f, "unexpected `{token}`"),
+            AsmErrorKind::Expected { expected, found } => {
+                write!(f, "expected {expected}, found `{found}`")
+            }
+            AsmErrorKind::Undefined(name) => write!(f, "`{name}` is not defined"),
+            AsmErrorKind::Redefined(name) => write!(f, "`{name}` is already defined"),
+            AsmErrorKind::OutOfRange { value, limit } => {
+                write!(f, "{value} does not fit in {limit}")
+            }
+            AsmErrorKind::Unbalanced(token) => write!(f, "unbalanced `{token}`"),
+            AsmErrorKind::AssertionFailed => write!(f, "assertion failed"),
+            AsmErrorKind::MacroRecursion(name) => {
+                write!(f, "macro `{name}` expands without end")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
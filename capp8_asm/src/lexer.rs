@@ -0,0 +1,62 @@
+/// A whitespace separated word of source, with where it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub text: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Split `source` into tokens. Tokens are separated by whitespace, `#` starts a comment
+/// that runs to the end of the line, and braces and parentheses always stand alone so
+/// that `{1 + 2}` reads the same as `{ 1 + 2 }`.
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (line, text) in source.lines().enumerate() {
+        let mut current: Option<Token> = None;
+        for (column, c) in text.chars().enumerate() {
+            let position = (line + 1, column + 1);
+            if c == '#' && current.is_none() {
+                break;
+            }
+            if c.is_whitespace() || "{}()".contains(c) {
+                tokens.extend(current.take());
+                if !c.is_whitespace() {
+                    tokens.push(Token {
+                        text: c.to_string(),
+                        line: position.0,
+                        column: position.1,
+                    });
+                }
+                continue;
+            }
+            current
+                .get_or_insert_with(|| Token {
+                    text: String::new(),
+                    line: position.0,
+                    column: position.1,
+                })
+                .text
+                .push(c);
+        }
+        tokens.extend(current);
+    }
+    tokens
+}
+
+/// Parse an Octo number literal: decimal, `0x` hex or `0b` binary, optionally negative.
+pub(crate) fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b").or(digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
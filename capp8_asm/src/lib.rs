@@ -0,0 +1,157 @@
+//! An assembler for the [Octo](https://github.com/JohnEarnest/Octo) dialect of CHIP-8
+//! assembly.
+//!
+//! Supported are labels, `:const`, `:alias`, `:macro`, `:calc`, `:byte`, `:pointer`,
+//! `:org`, `:call`, `:breakpoint` and `:assert`, every CHIP-8, SUPER-CHIP and XO-CHIP
+//! instruction, and the structured `if ... then`, `if ... begin ... else ... end` and
+//! `loop ... while ... again` forms. Like Octo, the ordering comparisons (`<`, `>`, `<=`,
+//! `>=`) clobber VF.
+//!
+//! ```
+//! let program = capp8_asm::assemble(": main  v0 := 1  loop again").unwrap();
+//! assert_eq!(program.bytes, [0x60, 0x01, 0x12, 0x02]);
+//! ```
+
+mod assembler;
+mod calc;
+pub mod error;
+mod lexer;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub use assembler::ORIGIN;
+use error::AsmError;
+
+/// An assembled program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// The image to load at [`ORIGIN`].
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    /// Addresses marked with `:breakpoint`, and their names.
+    pub breakpoints: BTreeMap<u16, String>,
+}
+
+impl Program {
+    /// One line per symbol, sorted by address: the address in hex, `label` or
+    /// `breakpoint`, and the name, e.g. `0x0200 label main`.
+    pub fn symbol_table(&self) -> String {
+        let mut symbols: Vec<(u16, &str, &str)> = self
+            .labels
+            .iter()
+            .map(|(name, &addr)| (addr, "label", name.as_str()))
+            .chain(
+                self.breakpoints
+                    .iter()
+                    .map(|(&addr, name)| (addr, "breakpoint", name.as_str())),
+            )
+            .collect();
+        symbols.sort();
+        let mut out = String::new();
+        for (addr, kind, name) in symbols {
+            writeln!(out, "{addr:#06x} {kind} {name}").unwrap();
+        }
+        out
+    }
+}
+
+/// Assemble Octo source into a program.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    assembler::Assembler::new(source).run()
+}
+
+#[cfg(test)]
+mod tests {
+    use capp8_core::{
+        debugger::{Debugger, Register, Stop},
+        disasm::disassemble,
+        emulator::Emulator,
+        instruction::Syntax,
+    };
+
+    use super::*;
+    use crate::error::AsmErrorKind;
+
+    #[test]
+    fn reassembles_disassembled_roms() {
+        let roms: [&[u8]; 4] = [
+            include_bytes!("../../capp8_app/examples/2-ibm-logo.ch8"),
+            include_bytes!("../../capp8_app/examples/3-corax+.ch8"),
+            include_bytes!("../../capp8_app/examples/6-keypad.ch8"),
+            include_bytes!("../../capp8_app/examples/8-scrolling.ch8"),
+        ];
+        for rom in roms {
+            let source = disassemble(rom, ORIGIN as u16).render(Syntax::Octo);
+            assert_eq!(assemble(&source).unwrap().bytes, rom);
+        }
+    }
+
+    #[test]
+    fn assembles_structured_code() {
+        let source = "
+            :const SPEED 3
+            :alias x v1
+            :calc TWICE { SPEED * 2 }
+            :macro bump reg { reg += TWICE }
+            : main
+                loop
+                    bump x
+                    while x != 30
+                    if x > 12 begin
+                        x := 0
+                    else
+                        draw
+                    end
+                again
+            : draw
+                i := glyph
+                sprite x x 1
+                ;
+            : glyph 0b10000001
+        ";
+        let program = assemble(source).unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            0x71, 0x06,             // bump x
+            0x41, 0x1E, 0x12, 0x16, // while x != 30
+            0x6F, 0x0C, 0x8F, 0x15, // if x > 12 begin
+            0x4F, 0x01, 0x12, 0x12,
+            0x61, 0x00,             // x := 0
+            0x12, 0x14,             // else
+            0x22, 0x16,             // draw
+            0x12, 0x00,             // again
+            0xA2, 0x1C, 0xD1, 0x11, 0x00, 0xEE,
+            0x81,
+        ];
+        assert_eq!(program.bytes, expected);
+        assert_eq!(program.labels["glyph"], 0x21C);
+        assert!(program.symbol_table().starts_with("0x0200 label main\n"));
+
+        let program = assemble("0xFF : main jump main").unwrap();
+        assert_eq!(program.bytes, [0x12, 0x03, 0xFF, 0x12, 0x03]);
+    }
+
+    #[test]
+    fn symbol_table_loads_into_the_debugger() {
+        let program = assemble(": main\n  v0 += 1\n  :breakpoint again\n  jump main").unwrap();
+        let emulator = Emulator::from_bytes(&program.bytes).unwrap();
+        let mut debugger = Debugger::new(emulator);
+        debugger.load_symbols(&program.symbol_table());
+        assert_eq!(debugger.symbol(0x200), Some("main"));
+        assert_eq!(debugger.symbol(0x202), None);
+        assert_eq!(debugger.resume(100).unwrap(), Stop::Breakpoint(0x202));
+        assert_eq!(debugger.register(Register::V(0)), 1);
+    }
+
+    #[test]
+    fn reports_positions() {
+        let error = assemble("clear\n  jump nowhere").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 8));
+        assert_eq!(error.kind, AsmErrorKind::Undefined("nowhere".into()));
+        assert_eq!(error.to_string(), "2:8: `nowhere` is not defined");
+
+        let error = assemble("loop\n  v0 += 1\nend").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 1));
+    }
+}
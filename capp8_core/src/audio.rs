@@ -0,0 +1,227 @@
+//! Sound synthesis, so that frontends only have to move samples to a device.
+//!
+//! While the sound timer runs, [`Emulator::render_audio`](crate::emulator::Emulator::render_audio)
+//! plays the XO-CHIP audio pattern if the program loaded one, and the beep described by
+//! its [`Tone`] otherwise. The sound fades in and out over a few milliseconds so that
+//! starting and stopping does not click.
+
+use std::f32::consts::TAU;
+use std::fmt;
+use std::str::FromStr;
+
+/// How long the sound takes to fade in or out.
+const ENVELOPE_SECONDS: f32 = 0.005;
+/// Bits in the XO-CHIP audio pattern, which loops.
+const PATTERN_BITS: f32 = 128.0;
+
+/// The shape of the beep.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 3] = [Waveform::Square, Waveform::Sine, Waveform::Triangle];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+        }
+    }
+
+    /// The value at `phase`, a fraction of the period, between -1 and 1.
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Waveform::ALL
+            .into_iter()
+            .find(|waveform| waveform.name() == s)
+            .ok_or_else(|| format!("unknown waveform {s}, expected square, sine or triangle"))
+    }
+}
+
+/// The beep played for programs without an audio pattern.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    /// In Hz.
+    pub frequency: f32,
+    /// From 0 to 1, also the volume of audio patterns.
+    pub volume: f32,
+}
+
+impl Default for Tone {
+    fn default() -> Self {
+        Self {
+            waveform: Waveform::default(),
+            frequency: 440.0,
+            volume: 0.25,
+        }
+    }
+}
+
+/// How many bits of the audio pattern XO-CHIP plays per second at `pitch`, 4000 at the
+/// default pitch of 64 and doubling every 48 steps.
+pub fn pattern_rate(pitch: u8) -> f32 {
+    4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0)
+}
+
+/// The state of the sound between calls to `render`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Synth {
+    pub(crate) tone: Tone,
+    /// Where in the period of the tone or the pattern the next sample is, kept across
+    /// buffers so that the sound is continuous.
+    phase: f32,
+    /// The current amplitude, moving towards the volume or 0 along the envelope.
+    gain: f32,
+}
+
+impl Synth {
+    /// Fill `samples` with the sound while `on`. `pattern` is the XO-CHIP audio pattern
+    /// and the rate it plays at, if there is one.
+    pub(crate) fn render(
+        &mut self,
+        samples: &mut [f32],
+        sample_rate: u32,
+        on: bool,
+        pattern: Option<(&[u8; 16], f32)>,
+    ) {
+        let sample_rate = sample_rate.max(1) as f32;
+        let target = if on {
+            self.tone.volume.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let ramp = 1.0 / (ENVELOPE_SECONDS * sample_rate);
+        let advance = match pattern {
+            Some((_, rate)) => rate / PATTERN_BITS / sample_rate,
+            None => self.tone.frequency / sample_rate,
+        };
+        for sample in samples {
+            self.gain = if self.gain < target {
+                (self.gain + ramp).min(target)
+            } else {
+                (self.gain - ramp).max(target)
+            };
+            let level = match pattern {
+                Some((pattern, _)) => {
+                    pattern_level(pattern, self.phase * PATTERN_BITS, advance * PATTERN_BITS)
+                }
+                None => self.tone.waveform.sample(self.phase),
+            };
+            *sample = level * self.gain;
+            self.phase = (self.phase + advance).fract();
+        }
+        // Start the next sound at the beginning of a period.
+        if self.gain == 0.0 {
+            self.phase = 0.0;
+        }
+    }
+}
+
+/// The average of the pattern bits from bit `start` over `width` bits, between -1 and 1.
+/// Averaging rather than picking the nearest bit keeps high pitches from aliasing.
+fn pattern_level(pattern: &[u8; 16], start: f32, width: f32) -> f32 {
+    let bit = |position: f32| {
+        let index = position as usize % 128;
+        (pattern[index / 8] >> (7 - index % 8)) & 1
+    };
+    let end = start + width;
+    let mut position = start;
+    let mut lit = 0.0;
+    while position < end {
+        let next = (position.floor() + 1.0).min(end);
+        lit += (next - position) * bit(position) as f32;
+        position = next;
+    }
+    2.0 * lit / width - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_the_tone_in_and_out() {
+        let mut synth = Synth {
+            tone: Tone {
+                waveform: Waveform::Sine,
+                frequency: 1000.0,
+                volume: 0.5,
+            },
+            ..Synth::default()
+        };
+
+        let mut samples = [0.0; 4800];
+        synth.render(&mut samples, 48_000, true, None);
+        // Full scale takes 240 samples to fade in, so half volume is reached by then.
+        assert!(samples[..10].iter().all(|sample| sample.abs() < 0.05));
+        let peak = samples[240..]
+            .iter()
+            .fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.5).abs() < 0.01);
+        let crossings = samples[240..]
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        assert_eq!(crossings, 95);
+
+        synth.render(&mut samples, 48_000, false, None);
+        assert!(samples[..240].iter().any(|sample| sample.abs() > 0.1));
+        assert!(samples[240..].iter().all(|&sample| sample == 0.0));
+        assert_eq!("triangle".parse(), Ok(Waveform::Triangle));
+    }
+
+    #[test]
+    fn plays_audio_patterns() {
+        assert_eq!(pattern_rate(64), 4000.0);
+        assert_eq!(pattern_rate(112), 8000.0);
+
+        // Eight bits on, eight off: a 250 Hz square wave at 4000 bits per second.
+        let pattern = [0xFF, 0x00].repeat(8).try_into().unwrap();
+        assert_eq!(pattern_level(&pattern, 0.0, 1.0), 1.0);
+        assert_eq!(pattern_level(&pattern, 7.5, 1.0), 0.0);
+        assert_eq!(pattern_level(&pattern, 127.0, 2.0), 0.0);
+
+        let mut synth = Synth {
+            tone: Tone {
+                volume: 1.0,
+                ..Tone::default()
+            },
+            ..Synth::default()
+        };
+        let mut samples = [0.0; 8000];
+        synth.render(&mut samples, 8000, true, Some((&pattern, 4000.0)));
+        // Past the fade in, two samples per bit.
+        assert_eq!(&samples[96..100], &[1.0; 4]);
+        assert_eq!(&samples[112..116], &[-1.0; 4]);
+        let rising = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] > 0.0)
+            .count();
+        assert_eq!(rising, 249);
+    }
+}
@@ -0,0 +1,479 @@
+//! Breakpoints, watchpoints, single-stepping and state inspection on top of [`Emulator`].
+//!
+//! Memory watchpoints are checked against the accesses the instruction about to run will
+//! make, worked out from its operands, so the emulator itself pays nothing for them.
+//! Watchpoints and register watches stop execution after the instruction that triggered
+//! them; breakpoints stop before the instruction at their address runs.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    ops::{Range, RangeInclusive},
+    time::Duration,
+};
+
+use crate::{
+    emulator::Emulator,
+    error::{EmulatorError, StepOutcome},
+    instruction::Instruction,
+};
+
+/// A register that can be inspected, changed or watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    /// V0 to VF.
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    /// The delay timer.
+    Dt,
+    /// The sound timer.
+    St,
+}
+
+impl Register {
+    /// Every register, V0 to VF first.
+    pub const ALL: [Register; 21] = [
+        Register::V(0),
+        Register::V(1),
+        Register::V(2),
+        Register::V(3),
+        Register::V(4),
+        Register::V(5),
+        Register::V(6),
+        Register::V(7),
+        Register::V(8),
+        Register::V(9),
+        Register::V(10),
+        Register::V(11),
+        Register::V(12),
+        Register::V(13),
+        Register::V(14),
+        Register::V(15),
+        Register::I,
+        Register::Pc,
+        Register::Sp,
+        Register::Dt,
+        Register::St,
+    ];
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(reg) => write!(f, "V{:X}", reg & 0xF),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Which accesses a memory watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::ReadWrite, _)
+                | (WatchKind::Read, Access::Read)
+                | (WatchKind::Write, Access::Write)
+        )
+    }
+}
+
+/// Why execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested step completed.
+    Stepped,
+    /// PC reached a breakpoint.
+    Breakpoint(u16),
+    /// The last instruction accessed watched memory, `addr` being the first watched byte.
+    Watchpoint { addr: u16, access: Access },
+    /// The last instruction changed a watched register.
+    RegisterChanged {
+        register: Register,
+        old: u16,
+        new: u16,
+    },
+    /// The program stopped the interpreter with 00FD.
+    Halted,
+    /// The instruction budget ran out first.
+    Limit,
+}
+
+pub struct Debugger {
+    emulator: Emulator,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<(RangeInclusive<u16>, WatchKind)>,
+    watched_registers: BTreeSet<Register>,
+    /// Names for addresses, from an assembler symbol table.
+    symbols: BTreeMap<u16, String>,
+}
+
+impl Debugger {
+    pub fn new(emulator: Emulator) -> Self {
+        Self {
+            emulator,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            watched_registers: BTreeSet::new(),
+            symbols: BTreeMap::new(),
+        }
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn emulator_mut(&mut self) -> &mut Emulator {
+        &mut self.emulator
+    }
+
+    pub fn into_inner(self) -> Emulator {
+        self.emulator
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Returns whether there was a breakpoint at `addr`.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Stop after any instruction that accesses `addrs` the way `kind` says.
+    pub fn watch_memory(&mut self, addrs: RangeInclusive<u16>, kind: WatchKind) {
+        self.watchpoints.push((addrs, kind));
+    }
+
+    /// Returns whether such a watchpoint existed.
+    pub fn unwatch_memory(&mut self, addrs: RangeInclusive<u16>, kind: WatchKind) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints
+            .retain(|watch| *watch != (addrs.clone(), kind));
+        self.watchpoints.len() != before
+    }
+
+    /// Stop after any instruction that changes `register`.
+    pub fn watch_register(&mut self, register: Register) {
+        self.watched_registers.insert(register);
+    }
+
+    pub fn unwatch_register(&mut self, register: Register) -> bool {
+        self.watched_registers.remove(&register)
+    }
+
+    /// Read a symbol table written by the assembler: one `<addr> <kind> <name>` line per
+    /// symbol. Labels name addresses, breakpoints are set.
+    pub fn load_symbols(&mut self, table: &str) {
+        for line in table.lines() {
+            let mut fields = line.split_whitespace();
+            let (Some(addr), Some(kind), Some(name)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Some(addr) = addr
+                .strip_prefix("0x")
+                .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+            else {
+                continue;
+            };
+            match kind {
+                "breakpoint" => self.add_breakpoint(addr),
+                _ => {
+                    self.symbols.insert(addr, name.to_string());
+                }
+            }
+        }
+    }
+
+    /// The label at `addr`, if the symbol table has one.
+    pub fn symbol(&self, addr: u16) -> Option<&str> {
+        self.symbols.get(&addr).map(String::as_str)
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        let emulator = &self.emulator;
+        match register {
+            Register::V(reg) => emulator.v[reg & 0xF] as u16,
+            Register::I => emulator.i,
+            Register::Pc => emulator.program_counter,
+            Register::Sp => emulator.stack_pointer as u16,
+            Register::Dt => emulator.timers.delay() as u16,
+            Register::St => emulator.timers.sound() as u16,
+        }
+    }
+
+    /// Set a register, truncating `value` to its width. SP is capped to the stack depth.
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        let emulator = &mut self.emulator;
+        match register {
+            Register::V(reg) => emulator.v[reg & 0xF] = value as u8,
+            Register::I => emulator.i = value,
+            Register::Pc => emulator.program_counter = value,
+            Register::Sp => emulator.stack_pointer = (value as usize).min(emulator.stack.len()),
+            Register::Dt => emulator.timers.set_delay(value as u8),
+            Register::St => emulator.timers.set_sound(value as u8),
+        }
+    }
+
+    /// The return addresses on the stack, oldest first.
+    pub fn stack(&self) -> &[u16] {
+        &self.emulator.stack[..self.emulator.stack_pointer]
+    }
+
+    /// Up to `len` bytes of memory from `addr`, fewer at the end of the address space.
+    pub fn read_memory(&self, addr: u16, len: usize) -> &[u8] {
+        let memory = &self.emulator.memory;
+        let start = (addr as usize).min(memory.len());
+        &memory[start..start.saturating_add(len).min(memory.len())]
+    }
+
+    /// Write `bytes` from `addr`, returning how many fit before the end of the address space.
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> usize {
+        let memory = &mut self.emulator.memory;
+        let start = (addr as usize).min(memory.len());
+        let len = bytes.len().min(memory.len() - start);
+        memory[start..start + len].copy_from_slice(&bytes[..len]);
+        len
+    }
+
+    /// The instruction at PC, if it decodes.
+    pub fn current_instruction(&self) -> Option<Instruction> {
+        let pc = self.emulator.program_counter;
+        let word = |addr: u16| {
+            let bytes = self.read_memory(addr, 2);
+            (bytes.len() == 2).then(|| u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        Instruction::decode(word(pc)?, word(pc.wrapping_add(2)).unwrap_or(0))
+    }
+
+    /// Run a single instruction.
+    pub fn step_into(&mut self) -> Result<Stop, EmulatorError> {
+        Ok(self.step()?.unwrap_or(Stop::Stepped))
+    }
+
+    /// Run a single instruction, or a whole subroutine if it is a call, running at most
+    /// `limit` instructions.
+    pub fn step_over(&mut self, limit: usize) -> Result<Stop, EmulatorError> {
+        match self.current_instruction() {
+            Some(Instruction::Call { .. }) => {
+                let resume = self.emulator.program_counter.wrapping_add(2);
+                let depth = self.emulator.stack_pointer;
+                self.run_until(limit, |emulator| {
+                    emulator.program_counter == resume && emulator.stack_pointer == depth
+                })
+            }
+            _ => self.step_into(),
+        }
+    }
+
+    /// Run until the current subroutine returns, running at most `limit` instructions.
+    pub fn step_out(&mut self, limit: usize) -> Result<Stop, EmulatorError> {
+        let depth = self.emulator.stack_pointer;
+        self.run_until(limit, |emulator| emulator.stack_pointer < depth)
+    }
+
+    /// Run until a breakpoint or watchpoint is hit, running at most `limit` instructions.
+    pub fn resume(&mut self, limit: usize) -> Result<Stop, EmulatorError> {
+        self.run_until(limit, |_| false)
+    }
+
+    fn run_until(
+        &mut self,
+        limit: usize,
+        done: impl Fn(&Emulator) -> bool,
+    ) -> Result<Stop, EmulatorError> {
+        for _ in 0..limit {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+            if done(&self.emulator) {
+                return Ok(Stop::Stepped);
+            }
+            let pc = self.emulator.program_counter;
+            if self.breakpoints.contains(&pc) {
+                return Ok(Stop::Breakpoint(pc));
+            }
+        }
+        Ok(Stop::Limit)
+    }
+
+    /// Run one instruction, reporting the watchpoint it triggered, if any.
+    fn step(&mut self) -> Result<Option<Stop>, EmulatorError> {
+        let registers: Vec<(Register, u16)> = self
+            .watched_registers
+            .iter()
+            .map(|&register| (register, self.register(register)))
+            .collect();
+        let access = self
+            .current_instruction()
+            .and_then(|instruction| self.memory_access(instruction));
+
+        // Run at the configured clock speed as far as the timers are concerned.
+        let dt = Duration::from_secs(1) / self.emulator.clock_speed().max(1);
+        let outcome = self.emulator.step(dt)?;
+        if outcome == StepOutcome::Halted {
+            return Ok(Some(Stop::Halted));
+        }
+        // A draw that waits for vblank is retried, so it has not accessed anything yet.
+        let hit = access
+            .filter(|_| outcome == StepOutcome::Executed)
+            .and_then(|(range, access)| self.watch_hit(range, access));
+        if let Some(stop) = hit {
+            return Ok(Some(stop));
+        }
+        for (register, old) in registers {
+            let new = self.register(register);
+            if new != old {
+                return Ok(Some(Stop::RegisterChanged { register, old, new }));
+            }
+        }
+        Ok(None)
+    }
+
+    fn watch_hit(&self, range: Range<usize>, access: Access) -> Option<Stop> {
+        self.watchpoints
+            .iter()
+            .filter(|(_, kind)| kind.matches(access))
+            .filter_map(|(watched, _)| {
+                let start = range.start.max(*watched.start() as usize);
+                (start < range.end.min(*watched.end() as usize + 1)).then_some(start as u16)
+            })
+            .min()
+            .map(|addr| Stop::Watchpoint { addr, access })
+    }
+
+    /// The memory `instruction` is about to read or write, besides fetching itself.
+    fn memory_access(&self, instruction: Instruction) -> Option<(Range<usize>, Access)> {
+        let emulator = &self.emulator;
+        let i = emulator.i as usize;
+        let span = |reg_x: usize, reg_y: usize| reg_x.abs_diff(reg_y) + 1;
+        let (len, access) = match instruction {
+            Instruction::Draw { nibble, .. } => {
+                let sprite_len = if nibble == 0 { 32 } else { nibble as usize };
+                let planes = emulator.display.planes().count_ones() as usize;
+                (sprite_len * planes, Access::Read)
+            }
+            Instruction::LoadAudioPattern => (16, Access::Read),
+            Instruction::StoreBCD { .. } => (3, Access::Write),
+            Instruction::StoreRegisters { reg } => (reg + 1, Access::Write),
+            Instruction::LoadRegisters { reg } => (reg + 1, Access::Read),
+            Instruction::StoreRegisterRange { reg_x, reg_y } => (span(reg_x, reg_y), Access::Write),
+            Instruction::LoadRegisterRange { reg_x, reg_y } => (span(reg_x, reg_y), Access::Read),
+            _ => return None,
+        };
+        Some((i..i + len, access))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 200: 6001  V0 := 1
+    // 202: 220A  CALL 20A
+    // 204: A300  I := 300
+    // 206: F055  store V0 at 300
+    // 208: 1208  loop
+    // 20A: 7001  V0 += 1
+    // 20C: 00EE  RET
+    const ROM: [u8; 14] = [
+        0x60, 0x01, 0x22, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x08, 0x70, 0x01, 0x00, 0xEE,
+    ];
+
+    fn debugger() -> Debugger {
+        Debugger::new(Emulator::from_bytes(&ROM).unwrap())
+    }
+
+    #[test]
+    fn steps_over_and_out_of_calls() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.step_into().unwrap(), Stop::Stepped);
+        assert_eq!(debugger.step_over(100).unwrap(), Stop::Stepped);
+        assert_eq!(debugger.register(Register::Pc), 0x204);
+        assert_eq!(debugger.register(Register::V(0)), 2);
+
+        let mut debugger = self::debugger();
+        debugger.step_into().unwrap();
+        debugger.step_into().unwrap();
+        assert_eq!(debugger.stack(), [0x204]);
+        assert_eq!(debugger.step_out(100).unwrap(), Stop::Stepped);
+        assert_eq!(debugger.register(Register::Pc), 0x204);
+        assert!(debugger.stack().is_empty());
+    }
+
+    #[test]
+    fn stops_on_breakpoints_and_watchpoints() {
+        let mut debugger = debugger();
+        debugger.add_breakpoint(0x20A);
+        assert_eq!(debugger.resume(100).unwrap(), Stop::Breakpoint(0x20A));
+
+        debugger.watch_register(Register::V(0));
+        assert_eq!(
+            debugger.resume(100).unwrap(),
+            Stop::RegisterChanged {
+                register: Register::V(0),
+                old: 1,
+                new: 2
+            }
+        );
+        debugger.unwatch_register(Register::V(0));
+
+        debugger.watch_memory(0x300..=0x300, WatchKind::Write);
+        assert_eq!(
+            debugger.resume(100).unwrap(),
+            Stop::Watchpoint {
+                addr: 0x300,
+                access: Access::Write
+            }
+        );
+        assert_eq!(debugger.read_memory(0x300, 1), [2]);
+        assert_eq!(debugger.read_memory(0xFFFF, usize::MAX), [0]);
+        assert_eq!(debugger.resume(100).unwrap(), Stop::Limit);
+    }
+
+    #[test]
+    fn watches_the_last_byte_of_memory() {
+        // 200: F000 FFFF  I := 0xFFFF
+        // 204: F065       load V0 from FFFF
+        let rom = [0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x65];
+        let emulator = Emulator::builder()
+            .memory_size(0x10000)
+            .build(&rom)
+            .unwrap();
+        let mut debugger = Debugger::new(emulator);
+        debugger.watch_memory(0xFFFF..=0xFFFF, WatchKind::Read);
+        assert_eq!(
+            debugger.resume(100).unwrap(),
+            Stop::Watchpoint {
+                addr: 0xFFFF,
+                access: Access::Read
+            }
+        );
+        assert!(debugger.unwatch_memory(0xFFFF..=0xFFFF, WatchKind::Read));
+    }
+}
@@ -0,0 +1,234 @@
+//! A ROM disassembler that separates code from data by following control flow.
+//!
+//! Starting from the load address, every reachable instruction is decoded: jumps are
+//! followed, calls and skips continue on both paths, and returns, exits and computed
+//! jumps end a path. Whatever is never reached is treated as data, which is how sprites
+//! usually end up. Jump, call and `LD I` targets get generated labels.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use crate::instruction::{Instruction, Syntax};
+
+/// How many data bytes go on a single line.
+const DATA_PER_LINE: usize = 8;
+
+pub struct Disassembly {
+    origin: u16,
+    rom: Vec<u8>,
+    /// Addresses of the instructions found by following control flow.
+    code: BTreeSet<u16>,
+    labels: BTreeMap<u16, String>,
+}
+
+/// Disassemble `rom`, loaded at `origin`, following control flow from its first byte.
+pub fn disassemble(rom: &[u8], origin: u16) -> Disassembly {
+    let end = origin as usize + rom.len();
+    let contains = |addr: u16| (origin as usize..end).contains(&(addr as usize));
+    let word = |addr: u16| -> Option<u16> {
+        let idx = addr.checked_sub(origin)? as usize;
+        Some(u16::from_be_bytes([*rom.get(idx)?, *rom.get(idx + 1)?]))
+    };
+    let decode = |addr: u16| -> Option<Instruction> {
+        Instruction::decode(word(addr)?, word(addr.wrapping_add(2)).unwrap_or(0)).filter(
+            |instruction| {
+                // The second word of a long instruction must be in the ROM too.
+                addr.checked_add(instruction.size() - 2)
+                    .and_then(word)
+                    .is_some()
+            },
+        )
+    };
+
+    let mut code = BTreeSet::new();
+    let mut targets = BTreeMap::new();
+    let mut pending = vec![origin];
+    while let Some(addr) = pending.pop() {
+        if !contains(addr) || code.contains(&addr) {
+            continue;
+        }
+        let Some(instruction) = decode(addr) else {
+            continue;
+        };
+        code.insert(addr);
+        let next = addr.wrapping_add(instruction.size());
+        match instruction {
+            Instruction::Jump { addr: target } => {
+                targets.insert(target, "label");
+                pending.push(target);
+            }
+            Instruction::Call { addr: target } => {
+                targets.entry(target).or_insert("sub");
+                pending.extend([target, next]);
+            }
+            // The offset is only known at runtime, but the base usually starts a jump table.
+            Instruction::JumpWithOffset { addr: target } => {
+                targets.insert(target, "table");
+                pending.push(target);
+            }
+            Instruction::SetI { addr: target } | Instruction::LongSetI { addr: target } => {
+                targets.entry(target).or_insert("data");
+                pending.push(next);
+            }
+            Instruction::Return | Instruction::Exit => {}
+            Instruction::SkipRegEqImm { .. }
+            | Instruction::SkipRegNeqImm { .. }
+            | Instruction::SkipRegEqReg { .. }
+            | Instruction::SkipRegNeqReg { .. }
+            | Instruction::SkipIfKey { .. }
+            | Instruction::SkipIfNotKey { .. } => {
+                let skipped = decode(next).map_or(2, |instruction| instruction.size());
+                pending.extend([next, next.wrapping_add(skipped)]);
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    let labels = targets
+        .into_iter()
+        .filter(|&(addr, _)| contains(addr))
+        .map(|(addr, kind)| (addr, format!("{kind}_{addr:03x}")))
+        .collect();
+    Disassembly {
+        origin,
+        rom: rom.to_vec(),
+        code,
+        labels,
+    }
+}
+
+impl Disassembly {
+    /// The generated labels, by address.
+    pub fn labels(&self) -> &BTreeMap<u16, String> {
+        &self.labels
+    }
+
+    /// Whether `addr` starts an instruction that was reached by following control flow.
+    pub fn is_code(&self, addr: u16) -> bool {
+        self.code.contains(&addr)
+    }
+
+    /// Render the listing. Cowgod listings show the address and raw bytes of every line;
+    /// Octo listings are plain source that assembles back into the ROM.
+    pub fn render(&self, syntax: Syntax) -> String {
+        let mut out = String::new();
+        if syntax == Syntax::Octo && self.origin != 0x200 {
+            writeln!(out, ":org {:#05x}", self.origin).unwrap();
+        }
+        let name = |addr: u16| {
+            self.labels
+                .get(&addr)
+                .cloned()
+                .unwrap_or_else(|| format!("{addr:#05X}"))
+        };
+
+        let end = self.origin as usize + self.rom.len();
+        let mut addr = self.origin as usize;
+        while addr < end {
+            if let Some(label) = self.labels.get(&(addr as u16)) {
+                match syntax {
+                    Syntax::Cowgod => writeln!(out, "{label}:"),
+                    Syntax::Octo => writeln!(out, ": {label}"),
+                }
+                .unwrap();
+            }
+
+            let offset = addr - self.origin as usize;
+            if self.code.contains(&(addr as u16)) {
+                let word = u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]]);
+                let next_word = self
+                    .rom
+                    .get(offset + 2..offset + 4)
+                    .map_or(0, |bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+                let instruction =
+                    Instruction::decode(word, next_word).expect("code was decoded before");
+                let size = instruction.size() as usize;
+                let text = instruction.render(syntax, &name);
+                match syntax {
+                    Syntax::Cowgod => {
+                        let bytes: String = self.rom[offset..offset + size]
+                            .iter()
+                            .map(|byte| format!("{byte:02X}"))
+                            .collect();
+                        writeln!(out, "{addr:03X}  {bytes:<8}  {text}")
+                    }
+                    Syntax::Octo => writeln!(out, "\t{text}"),
+                }
+                .unwrap();
+                addr += size;
+            } else {
+                // Data runs until the next label or instruction, or the end of the line.
+                let len = (addr + 1..end)
+                    .take(DATA_PER_LINE - 1)
+                    .take_while(|&next| {
+                        !self.labels.contains_key(&(next as u16))
+                            && !self.code.contains(&(next as u16))
+                    })
+                    .count()
+                    + 1;
+                let bytes = &self.rom[offset..offset + len];
+                match syntax {
+                    Syntax::Cowgod => {
+                        let hex: Vec<String> =
+                            bytes.iter().map(|byte| format!("{byte:#04X}")).collect();
+                        writeln!(out, "{addr:03X}  {:<8}  DB {}", "", hex.join(", "))
+                    }
+                    Syntax::Octo => {
+                        let hex: Vec<String> =
+                            bytes.iter().map(|byte| format!("{byte:#04x}")).collect();
+                        writeln!(out, "\t{}", hex.join(" "))
+                    }
+                }
+                .unwrap();
+                addr += len;
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separates_code_from_data() {
+        // A208 6000 2208 1206 | D015 00EE | F0 90 F0 90 90
+        let rom = [
+            0xA2, 0x0C, 0x60, 0x00, 0x22, 0x08, 0x12, 0x06, 0xD0, 0x05, 0x00, 0xEE, 0xF0, 0x90,
+            0xF0, 0x90, 0x90,
+        ];
+        let disassembly = disassemble(&rom, 0x200);
+        assert!(disassembly.is_code(0x208) && disassembly.is_code(0x20A));
+        assert!(!disassembly.is_code(0x20C));
+        assert_eq!(disassembly.labels()[&0x206], "label_206");
+        assert_eq!(disassembly.labels()[&0x208], "sub_208");
+        assert_eq!(disassembly.labels()[&0x20C], "data_20c");
+
+        let octo = disassembly.render(Syntax::Octo);
+        assert!(octo.contains("\t:call sub_208\n: label_206\n\tjump label_206\n"));
+        assert!(octo.ends_with(": data_20c\n\t0xf0 0x90 0xf0 0x90 0x90\n"));
+        let cowgod = disassembly.render(Syntax::Cowgod);
+        assert!(cowgod.starts_with("200  A20C      LD I, data_20c\n"));
+    }
+
+    #[test]
+    fn stops_at_the_end_of_the_address_space() {
+        // A full 64 KiB XO-CHIP image, ending with the first half of `i := long`.
+        let mut rom = vec![0x00; 0x10000 - 0x200];
+        rom[0xFDFE..].copy_from_slice(&[0xF0, 0x00]);
+        let disassembly = disassemble(&rom, 0x200);
+        assert!(disassembly.is_code(0xFFFC));
+        assert!(!disassembly.is_code(0xFFFE));
+        assert!(
+            disassembly
+                .render(Syntax::Cowgod)
+                .ends_with("FFFE            DB 0xF0, 0x00\n")
+        );
+
+        let disassembly = disassemble(&[0xF0, 0x00], 0xFFFE);
+        assert!(!disassembly.is_code(0xFFFE));
+    }
+}
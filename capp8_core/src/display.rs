@@ -1,42 +1,174 @@
 #![allow(unused)]
 use std::ops::{Index, IndexMut};
 
+/// The screen, made of up to two XO-CHIP bitplanes.
+///
+/// Each pixel holds a colour index from 0 to 3, bit `n` being set when the pixel is lit
+/// on plane `n`. Plain CHIP-8 and SUPER-CHIP programs only ever use the first plane.
 #[derive(Clone)]
 pub struct Display {
-    pixel: [bool; Display::HEIGHT * Display::WIDTH],
+    pub(crate) pixel: [u8; Display::HIRES_HEIGHT * Display::HIRES_WIDTH],
+    pub(crate) hires: bool,
+    pub(crate) planes: u8,
 }
 impl Display {
-    pub const WIDTH: usize = 64;
-    pub const HEIGHT: usize = 32;
+    pub const LORES_WIDTH: usize = 64;
+    pub const LORES_HEIGHT: usize = 32;
+    pub const HIRES_WIDTH: usize = 128;
+    pub const HIRES_HEIGHT: usize = 64;
+    pub const PLANES: usize = 2;
     pub fn new() -> Self {
         Self::default()
     }
+    /// Clear every plane.
     pub fn reset(&mut self) {
-        self.pixel = [false; Display::HEIGHT * Display::WIDTH];
+        self.pixel = [0; Display::HIRES_HEIGHT * Display::HIRES_WIDTH];
     }
-    pub fn draw_byte(&mut self, byte: u8, x: usize, y: usize) -> bool {
+    /// Clear the selected planes only.
+    pub fn clear(&mut self) {
+        let planes = self.planes;
+        self.pixel.iter_mut().for_each(|pixel| *pixel &= !planes);
+    }
+    pub fn width(&self) -> usize {
+        if self.hires {
+            Self::HIRES_WIDTH
+        } else {
+            Self::LORES_WIDTH
+        }
+    }
+    pub fn height(&self) -> usize {
+        if self.hires {
+            Self::HIRES_HEIGHT
+        } else {
+            Self::LORES_HEIGHT
+        }
+    }
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+    /// Switch between the 64x32 and the 128x64 SUPER-CHIP mode, clearing the screen.
+    pub fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.reset();
+    }
+    /// The bitmask of the planes affected by drawing, clearing and scrolling.
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+    pub fn select_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+    /// XOR a byte of sprite data onto `plane`, returning whether a lit pixel was turned off.
+    ///
+    /// Pixels falling off the screen wrap around, unless `clip` is set, in which case they
+    /// are dropped.
+    pub fn draw_byte(&mut self, byte: u8, x: usize, y: usize, clip: bool, plane: usize) -> bool {
+        self.draw_row(byte as u16, 8, x, y, clip, plane)
+    }
+    /// Same as [`Display::draw_byte`], for the 16 pixel wide rows of SUPER-CHIP sprites.
+    pub fn draw_word(&mut self, word: u16, x: usize, y: usize, clip: bool, plane: usize) -> bool {
+        self.draw_row(word, 16, x, y, clip, plane)
+    }
+    fn draw_row(
+        &mut self,
+        bits: u16,
+        len: usize,
+        x: usize,
+        y: usize,
+        clip: bool,
+        plane: usize,
+    ) -> bool {
+        let (width, height) = (self.width(), self.height());
+        if clip && y >= height {
+            return false;
+        }
+        let mask = 1 << plane;
         let mut flip = false;
-        for shift in (0usize..8usize).rev() {
-            let new_pixel = ((byte >> shift) & 1) > 0;
-            let idx = ((x + (7 - shift)) % Self::WIDTH, y % Self::HEIGHT);
-            let old_pixel = self[idx];
-            flip |= old_pixel;
-            self[idx] ^= new_pixel;
+        for shift in (0..len).rev() {
+            let new_pixel = ((bits >> shift) & 1) > 0;
+            let pixel_x = x + (len - 1 - shift);
+            if clip && pixel_x >= width {
+                break;
+            }
+            if !new_pixel {
+                continue;
+            }
+            let idx = (pixel_x % width, y % height);
+            flip |= self[idx] & mask != 0;
+            self[idx] ^= mask;
         }
         flip
     }
+    /// Scroll the selected planes down by `rows` pixels, filling the top with blank pixels.
+    pub fn scroll_down(&mut self, rows: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let source = if y >= rows { self[(x, y - rows)] } else { 0 };
+                self.scroll_pixel((x, y), source);
+            }
+        }
+    }
+    /// Scroll the selected planes up by `rows` pixels, filling the bottom with blank pixels.
+    pub fn scroll_up(&mut self, rows: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                let source = if y + rows < height {
+                    self[(x, y + rows)]
+                } else {
+                    0
+                };
+                self.scroll_pixel((x, y), source);
+            }
+        }
+    }
+    /// Scroll the selected planes left by `columns` pixels, filling the right edge with blank pixels.
+    pub fn scroll_left(&mut self, columns: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in 0..width {
+                let source = if x + columns < width {
+                    self[(x + columns, y)]
+                } else {
+                    0
+                };
+                self.scroll_pixel((x, y), source);
+            }
+        }
+    }
+    /// Scroll the selected planes right by `columns` pixels, filling the left edge with blank pixels.
+    pub fn scroll_right(&mut self, columns: usize) {
+        let (width, height) = (self.width(), self.height());
+        for y in 0..height {
+            for x in (0..width).rev() {
+                let source = if x >= columns {
+                    self[(x - columns, y)]
+                } else {
+                    0
+                };
+                self.scroll_pixel((x, y), source);
+            }
+        }
+    }
+    /// Replace the selected planes of the pixel at `idx` with the ones of `source`.
+    fn scroll_pixel(&mut self, idx: (usize, usize), source: u8) {
+        let planes = self.planes;
+        self[idx] = (self[idx] & !planes) | (source & planes);
+    }
 }
 impl Index<(usize, usize)> for Display {
-    type Output = bool;
+    type Output = u8;
 
     fn index(&self, index: (usize, usize)) -> &Self::Output {
-        &self.pixel[index.1 * Display::WIDTH + index.0]
+        &self.pixel[index.1 * self.width() + index.0]
     }
 }
 
 impl IndexMut<(usize, usize)> for Display {
     fn index_mut(&mut self, index: (usize, usize)) -> &mut Self::Output {
-        &mut self.pixel[index.1 * Display::WIDTH + index.0]
+        let width = self.width();
+        &mut self.pixel[index.1 * width + index.0]
     }
 }
 impl std::fmt::Display for Display {
@@ -44,10 +176,10 @@ impl std::fmt::Display for Display {
         write!(f, "\x1B[2J\x1B[H")?;
         // write!(f, "\x1B[H")?;
 
-        for y in 0..Display::HEIGHT {
-            for x in 0..Display::WIDTH {
+        for y in 0..self.height() {
+            for x in 0..self.width() {
                 // write!(f, "{}", if self[(x, y)] { "⬜" } else { "⬛" })?;
-                write!(f, "{}", if self[(x, y)] { "##" } else { "  " })?;
+                write!(f, "{}", ["  ", "##", "++", "@@"][self[(x, y)] as usize])?;
             }
             writeln!(f)?
         }
@@ -57,7 +189,9 @@ impl std::fmt::Display for Display {
 impl Default for Display {
     fn default() -> Self {
         Self {
-            pixel: [false; Display::HEIGHT * Display::WIDTH],
+            pixel: [0; Display::HIRES_HEIGHT * Display::HIRES_WIDTH],
+            hires: false,
+            planes: 1,
         }
     }
 }
@@ -70,8 +204,28 @@ mod tests {
     #[test]
     fn test_display() {
         let mut display = Display::new();
-        display[(3, 2)] = true;
-        display[(2, 3)] = true;
+        display[(3, 2)] = 1;
+        display[(2, 3)] = 1;
         println!("{}", display)
     }
+
+    #[test]
+    fn scrolls_only_the_selected_planes() {
+        let mut display = Display::new();
+        display[(10, 5)] = 0b11;
+        display.select_planes(0b01);
+        display.scroll_down(3);
+        assert_eq!([display[(10, 5)], display[(10, 8)]], [0b10, 0b01]);
+        display.scroll_right(4);
+        assert_eq!([display[(10, 8)], display[(14, 8)]], [0, 0b01]);
+        display.scroll_left(4);
+        display.scroll_up(3);
+        assert_eq!(display[(10, 5)], 0b11);
+        assert_eq!(display.pixel.iter().filter(|&&pixel| pixel != 0).count(), 1);
+
+        // Pixels scrolled off the edge are lost.
+        display.select_planes(0b11);
+        display.scroll_left(12);
+        assert!(display.pixel.iter().all(|&pixel| pixel == 0));
+    }
 }
@@ -4,226 +4,340 @@
 use std::{
     fs::{File, OpenOptions},
     io::Read,
+    ops::Range,
     path::{Path, PathBuf},
     thread,
     time::Duration,
 };
 
 use crate::{
-    display::Display, fonts::FONTS, frontend::Frontend, instruction::Instruction, opcode::Opcode,
+    audio::{Synth, Tone, pattern_rate},
+    display::Display,
+    error::{EmulatorError, LoadError, StepOutcome},
+    fonts::{BIG_FONTS, FONT_ADDRESS, FONTS},
+    frontend::Frontend,
+    instruction::Instruction,
+    opcode::Opcode,
+    quirks::Quirks,
+    random::{RandomSource, SplitMix64},
+    timer::{SoundEvent, Timers},
+    trace::{TraceRecord, Tracer},
 };
-use rand::{Rng, rngs::ThreadRng};
+use sha1::{Digest, Sha1};
 
 pub struct Emulator {
-    v: [u8; 16],
-    i: u16,
-    memory: [u8; 4096],
-    program_counter: u16,
-    stack: [u16; 16],
-    stack_pointer: usize,
-    delay_timer: u8,
-    sound_timer: u8,
-    display: Display,
-    keypad: [bool; 16],
-    rng: ThreadRng,
-    timer_accum: Duration,
+    pub(crate) v: [u8; 16],
+    pub(crate) i: u16,
+    pub(crate) memory: Vec<u8>,
+    pub(crate) program_counter: u16,
+    pub(crate) stack: [u16; 16],
+    pub(crate) stack_pointer: usize,
+    pub(crate) timers: Timers,
+    pub(crate) display: Display,
+    pub(crate) keypad: [bool; 16],
+    /// SUPER-CHIP RPL user flags, written by Fx75 and read by Fx85.
+    pub(crate) flags: [u8; 16],
+    /// Set by 00FD, the interpreter does nothing afterwards.
+    pub(crate) halted: bool,
+    /// XO-CHIP 1-bit audio samples, loaded by F002.
+    pub(crate) audio_pattern: [u8; 16],
+    /// XO-CHIP playback rate of `audio_pattern`, set by Fx3A.
+    pub(crate) pitch: u8,
+    pub(crate) rng: Box<dyn RandomSource>,
+    /// The seed `rng` started from, recorded so that runs can be reproduced.
+    pub(crate) seed: u64,
+    pub(crate) quirks: Quirks,
+    /// Where the small font starts, the large one follows it.
+    pub(crate) font_address: usize,
+    /// Where the ROM was loaded, and execution started.
+    pub(crate) load_address: u16,
+    /// Instructions per second the frontend should aim for.
+    pub(crate) clock_speed: u32,
+    /// SHA-1 of the ROM image, used to tell save states of different programs apart.
+    pub(crate) rom_hash: [u8; 20],
+    /// Set when the timers tick, cleared by a draw when the `display_wait` quirk is enabled.
+    pub(crate) vblank: bool,
+    /// Receives every executed instruction when tracing is enabled.
+    pub(crate) tracer: Option<Tracer>,
+    pub(crate) synth: Synth,
 }
 
 impl Emulator {
-    pub fn new<P: AsRef<Path>>(rom_path: &P) -> Result<Self, std::io::Error> {
-        let mut memory = [0; 4096];
-        memory[0x50..=0x9F].copy_from_slice(&FONTS[..]);
-        let mut file = OpenOptions::new().read(true).open(rom_path)?;
-        let mut buf: Vec<u8> = Vec::new();
-        let file_size = file.read_to_end(&mut buf)?;
-        memory[0x200..0x200 + file_size].copy_from_slice(buf.as_slice());
-        Ok(Self {
-            v: [0; 16],
-            i: 0,
-            program_counter: 0x200,
-            memory,
-            stack_pointer: 0,
-            stack: [0; 16],
-            delay_timer: 0,
-            sound_timer: 0,
-            display: Display::new(),
-            keypad: [false; 16],
-            rng: rand::rng(),
-            timer_accum: Duration::new(0, 0),
-        })
+    /// The XO-CHIP address space, a superset of the 4 KiB of the original machine.
+    pub const MEMORY_SIZE: usize = 0x10000;
+    pub fn new<P: AsRef<Path>>(rom_path: &P) -> Result<Self, LoadError> {
+        EmulatorBuilder::new().build_from_file(rom_path)
+    }
+    pub fn with_quirks<P: AsRef<Path>>(rom_path: &P, quirks: Quirks) -> Result<Self, LoadError> {
+        EmulatorBuilder::new()
+            .quirks(quirks)
+            .build_from_file(rom_path)
+    }
+    /// Create an emulator with the default configuration running the given ROM image.
+    pub fn from_bytes(rom: &[u8]) -> Result<Self, LoadError> {
+        EmulatorBuilder::new().build(rom)
+    }
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder::new()
+    }
+    /// A builder set up the way this emulator was built, to start the program over with
+    /// the same memory layout, quirks, seed, clock speed and timer rate. A custom random
+    /// source is not carried over.
+    pub fn to_builder(&self) -> EmulatorBuilder {
+        EmulatorBuilder::new()
+            .load_address(self.load_address)
+            .font_address(self.font_address as u16)
+            .memory_size(self.memory.len())
+            .quirks(self.quirks)
+            .seed(self.seed)
+            .clock_speed(self.clock_speed)
+            .timer_rate(self.timers.rate())
+    }
+    /// The seed the random source was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// SHA-1 of the ROM image the emulator was created with.
+    pub fn rom_hash(&self) -> [u8; 20] {
+        self.rom_hash
+    }
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+    pub fn set_clock_speed(&mut self, clock_speed: u32) {
+        self.clock_speed = clock_speed;
+    }
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
     }
     pub fn display(&self) -> &Display {
         &self.display
     }
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.v
+    }
+    pub fn index(&self) -> u16 {
+        self.i
+    }
+    /// How many return addresses are on the stack.
+    pub fn stack_pointer(&self) -> usize {
+        self.stack_pointer
+    }
+    pub fn delay_timer(&self) -> u8 {
+        self.timers.delay()
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.timers.sound()
+    }
+    pub fn timers(&self) -> &Timers {
+        &self.timers
+    }
+    /// Take the times the sound started or stopped since the last call, oldest first.
+    pub fn sound_events(&mut self) -> impl Iterator<Item = SoundEvent> + '_ {
+        self.timers.events()
+    }
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
     pub fn set_keypad(&mut self, keypad: [bool; 16]) {
         self.keypad = keypad;
     }
-    pub fn step(&mut self, dt: Duration) {
-        let opcode = self.fetch();
-        self.program_counter += 2;
-        let instruction = self.decode(opcode);
-        self.execute(instruction);
-        // 2. accumulate elapsed time
-        self.timer_accum += dt; // dt comes from the main loop
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+    pub fn tone(&self) -> Tone {
+        self.synth.tone
+    }
+    /// Set the beep played for programs without an XO-CHIP audio pattern, and the volume
+    /// of all sound. Volume changes fade like the sound itself.
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.synth.tone = tone;
+    }
+    /// Fill `samples` with mono sound at `sample_rate` for the current sound timer.
+    ///
+    /// Frontends call this with consecutive buffers as the device asks for them, the sound
+    /// carrying on from one buffer to the next. The audio pattern is played once the
+    /// program loaded a non-silent one with F002, the [`Tone`] otherwise.
+    pub fn render_audio(&mut self, samples: &mut [f32], sample_rate: u32) {
+        let pattern = (self.audio_pattern != [0; 16])
+            .then(|| (&self.audio_pattern, pattern_rate(self.pitch)));
+        self.synth
+            .render(samples, sample_rate, self.timers.is_sounding(), pattern);
+    }
+    /// Whether the program stopped the interpreter with 00FD.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+    /// Trace every executed instruction to `tracer`, or stop tracing with `None`.
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+    /// Stop tracing, returning the tracer so that it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+    /// Execute one instruction, then move the timers' clock on by `dt`.
+    pub fn step(&mut self, dt: Duration) -> Result<StepOutcome, EmulatorError> {
+        let outcome = self.execute_next()?;
+        if self.timers.advance(dt) > 0 {
+            self.vblank = true;
+        }
+        Ok(outcome)
+    }
 
-        // 3. tick timers every 16-17 ms no matter how many opcodes we executed
-        while self.timer_accum >= Duration::from_micros(16_667) {
-            self.timer_accum -= Duration::from_micros(16_667);
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-            }
+    /// Run one frame: up to `instructions` instructions, then a frame's worth of timer
+    /// ticks, a single one at the default rate of 60 Hz.
+    ///
+    /// The frame ends early when the program halts, waits for a key or, under the
+    /// `display_wait` quirk, waits for the vertical blank. Returns how many instructions
+    /// were executed.
+    pub fn run_frame(&mut self, instructions: u32) -> Result<u32, EmulatorError> {
+        let mut executed = 0;
+        while executed < instructions && self.execute_next()? == StepOutcome::Executed {
+            executed += 1;
+        }
+        self.timers.advance_frame();
+        self.vblank = true;
+        Ok(executed)
+    }
+
+    /// Fetch, decode and execute the instruction at PC, tracing it if enabled.
+    fn execute_next(&mut self) -> Result<StepOutcome, EmulatorError> {
+        if self.halted {
+            return Ok(StepOutcome::Halted);
         }
+        let pc = self.program_counter;
+        let opcode = self.fetch().map_err(|fault| fault.at(pc, 0))?;
+        self.program_counter = self.program_counter.wrapping_add(2);
+        self.decode(&opcode)
+            .and_then(|instruction| {
+                let Some(mut tracer) = self.tracer.take() else {
+                    return self.execute(instruction);
+                };
+                let record = TraceRecord::capture(self, pc, opcode.raw(), instruction);
+                let outcome = self.execute(instruction);
+                if matches!(outcome, Ok(StepOutcome::Executed | StepOutcome::Halted)) {
+                    tracer.record(&record);
+                }
+                self.tracer = Some(tracer);
+                outcome
+            })
+            .map_err(|fault| fault.at(pc, opcode.raw()))
     }
 
     /// Read the instruction that PC is currently pointing at from memory.
-    fn fetch(&self) -> Opcode {
-        let msb = self.memory[self.program_counter as usize];
-        let lsb = self.memory[(self.program_counter + 1) as usize];
-        Opcode::from(u16::from_be_bytes([msb, lsb]))
-    }
-    fn decode(&self, opcode: Opcode) -> Instruction {
-        match opcode.nibbles() {
-            (0, 0, 0xE, 0) => Instruction::ClearScreen,
-
-            (0, 0, 0xE, 0xE) => Instruction::Return,
-
-            (0, _, _, _) => Instruction::Sys {
-                addr: opcode.addr(),
-            },
-
-            (1, _, _, _) => Instruction::Jump {
-                addr: opcode.addr(),
-            },
-            (2, _, _, _) => Instruction::Call {
-                addr: opcode.addr(),
-            },
-            (3, _, _, _) => Instruction::SkipRegEqImm {
-                reg: opcode.x(),
-                imm: opcode.byte(),
-            },
-
-            (4, _, _, _) => Instruction::SkipRegNeqImm {
-                reg: opcode.x(),
-                imm: opcode.byte(),
-            },
-            (5, _, _, 0) => Instruction::SkipRegEqReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (6, _, _, _) => Instruction::StoreRegFromImm {
-                reg: opcode.x(),
-                imm: opcode.byte(),
-            },
-            (7, _, _, _) => Instruction::AddRegImm {
-                reg: opcode.x(),
-                imm: opcode.byte(),
-            },
-            (8, _, _, 0) => Instruction::StoreRegFromReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 1) => Instruction::OrRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 2) => Instruction::AndRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 3) => Instruction::XorRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 4) => Instruction::AddRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 5) => Instruction::SubRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 6) => Instruction::ShiftRight { reg: opcode.x() },
-            (8, _, _, 7) => Instruction::SubnRegReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (8, _, _, 0xE) => Instruction::ShiftLeft { reg: opcode.x() },
-            (9, _, _, 0) => Instruction::SkipRegNeqReg {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-            },
-            (0xA, _, _, _) => Instruction::SetI {
-                addr: opcode.addr(),
-            },
-            (0xB, _, _, _) => Instruction::JumpWithOffset {
-                addr: opcode.addr(),
-            },
-            (0xC, _, _, _) => Instruction::Rand {
-                reg: opcode.x(),
-                mask: opcode.byte(),
-            },
-            (0xD, _, _, _) => Instruction::Draw {
-                reg_x: opcode.x(),
-                reg_y: opcode.y(),
-                nibble: opcode.nibble(),
-            },
-            (0xE, _, 9, 0xE) => Instruction::SkipIfKey { reg: opcode.x() },
-            (0xE, _, 0xA, 1) => Instruction::SkipIfNotKey { reg: opcode.x() },
-            (0xF, _, 0, 7) => Instruction::LoadDelayTimer { reg: opcode.x() },
-            (0xF, _, 0, 0xA) => Instruction::WaitKeyPress { reg: opcode.x() },
-            (0xF, _, 1, 5) => Instruction::SetDelayTimer { reg: opcode.x() },
-            (0xF, _, 1, 8) => Instruction::SetSoundTimer { reg: opcode.x() },
-            (0xF, _, 1, 0xE) => Instruction::AddI { reg: opcode.x() },
-            (0xF, _, 2, 9) => Instruction::SetIToSprite { reg: opcode.x() },
-            (0xF, _, 3, 3) => Instruction::StoreBCD { reg: opcode.x() },
-            (0xF, _, 5, 5) => Instruction::StoreRegisters { reg: opcode.x() },
-            (0xF, _, 6, 5) => Instruction::LoadRegisters { reg: opcode.x() },
-
-            _ => unimplemented!(),
+    fn fetch(&self) -> Result<Opcode, Fault> {
+        self.read_word(self.program_counter as usize)
+            .map(Opcode::from)
+    }
+    fn read_word(&self, addr: usize) -> Result<u16, Fault> {
+        let range = self.memory_range(addr, 2)?;
+        Ok(u16::from_be_bytes([
+            self.memory[range.start],
+            self.memory[range.start + 1],
+        ]))
+    }
+    /// The `len` bytes of memory starting at `start`, or the first address out of bounds.
+    fn memory_range(&self, start: usize, len: usize) -> Result<Range<usize>, Fault> {
+        if start + len > self.memory.len() {
+            Err(Fault::MemoryOutOfBounds {
+                addr: start.max(self.memory.len()),
+            })
+        } else {
+            Ok(start..start + len)
         }
     }
-    fn execute(&mut self, instruction: Instruction) {
+    /// Skip the next instruction, which is two words long if it is an XO-CHIP `F000 nnnn`.
+    fn skip(&mut self) {
+        let long = self
+            .fetch()
+            .is_ok_and(|next| next.nibbles() == (0xF, 0, 0, 0));
+        self.program_counter = self.program_counter.wrapping_add(if long { 4 } else { 2 });
+    }
+    fn decode(&self, opcode: &Opcode) -> Result<Instruction, Fault> {
+        // The address of F000 nnnn lives in the word after the opcode, which PC points at by now.
+        let next_word = match opcode.raw() {
+            0xF000 => self.read_word(self.program_counter as usize)?,
+            _ => 0,
+        };
+        Instruction::decode(opcode.raw(), next_word).ok_or(Fault::UnknownOpcode)
+    }
+    fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, Fault> {
         match instruction {
-            Instruction::Sys { addr: _ } => {
-                // TODO: Should probably add a log and ignore maybe (?)
-                unimplemented!("This should be ignored on most modern interpreters.")
-            }
+            Instruction::Sys { addr } => return Err(Fault::MachineCodeRoutine { addr }),
             Instruction::ClearScreen => {
-                self.display.reset();
+                self.display.clear();
             }
             Instruction::Return => {
-                self.program_counter = self.stack[self.stack_pointer];
+                if self.stack_pointer == 0 {
+                    return Err(Fault::StackUnderflow);
+                }
                 self.stack_pointer -= 1;
+                self.program_counter = self.stack[self.stack_pointer];
             }
+            Instruction::ScrollDown { rows } => self.display.scroll_down(rows as usize),
+            Instruction::ScrollUp { rows } => self.display.scroll_up(rows as usize),
+            Instruction::ScrollRight => self.display.scroll_right(4),
+            Instruction::ScrollLeft => self.display.scroll_left(4),
+            Instruction::Exit => {
+                self.halted = true;
+                return Ok(StepOutcome::Halted);
+            }
+            Instruction::LowRes => self.display.set_hires(false),
+            Instruction::HighRes => self.display.set_hires(true),
             Instruction::Jump { addr } => self.program_counter = addr,
             Instruction::Call { addr } => {
-                self.stack_pointer += 1;
+                if self.stack_pointer == self.stack.len() {
+                    return Err(Fault::StackOverflow);
+                }
                 self.stack[self.stack_pointer] = self.program_counter;
+                self.stack_pointer += 1;
                 self.program_counter = addr;
             }
             Instruction::SkipRegEqImm { reg, imm } => {
                 if self.v[reg] == imm {
-                    self.program_counter += 2
+                    self.skip()
                 }
             }
             Instruction::SkipRegNeqImm { reg, imm } => {
                 if self.v[reg] != imm {
-                    self.program_counter += 2
+                    self.skip()
                 }
             }
             Instruction::SkipRegEqReg { reg_x, reg_y } => {
                 if self.v[reg_x] == self.v[reg_y] {
-                    self.program_counter += 2
+                    self.skip()
+                }
+            }
+            Instruction::StoreRegisterRange { reg_x, reg_y } => {
+                let range = self.memory_range(self.i as usize, reg_x.abs_diff(reg_y) + 1)?;
+                for (addr, reg) in range.zip(Self::register_range(reg_x, reg_y)) {
+                    self.memory[addr] = self.v[reg];
+                }
+            }
+            Instruction::LoadRegisterRange { reg_x, reg_y } => {
+                let range = self.memory_range(self.i as usize, reg_x.abs_diff(reg_y) + 1)?;
+                for (addr, reg) in range.zip(Self::register_range(reg_x, reg_y)) {
+                    self.v[reg] = self.memory[addr];
                 }
             }
             Instruction::StoreRegFromImm { reg, imm } => self.v[reg] = imm,
             Instruction::StoreRegFromReg { reg_x, reg_y } => self.v[reg_x] = self.v[reg_y],
             Instruction::AddRegImm { reg, imm } => self.v[reg] = self.v[reg].wrapping_add(imm),
             Instruction::AddRegReg { reg_x, reg_y } => {
-                // TODO: Check. I think I should upcast into an u16 and then downcast
                 let (v_x, carry) = self.v[reg_x].overflowing_add(self.v[reg_y]);
 
                 self.v[reg_x] = v_x;
@@ -231,42 +345,61 @@ impl Emulator {
             }
             Instruction::OrRegReg { reg_x, reg_y } => {
                 self.v[reg_x] |= self.v[reg_y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
             }
             Instruction::AndRegReg { reg_x, reg_y } => {
                 self.v[reg_x] &= self.v[reg_y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            Instruction::XorRegReg { reg_x, reg_y } => {
+                self.v[reg_x] ^= self.v[reg_y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
             }
-            Instruction::XorRegReg { reg_x, reg_y } => self.v[reg_x] ^= self.v[reg_y],
             Instruction::SubRegReg { reg_x, reg_y } => {
-                // TODO: Check. I think they mean only subtract if Vx > Vy
                 let (v_x, borrow) = self.v[reg_x].overflowing_sub(self.v[reg_y]);
                 self.v[reg_x] = v_x;
-                self.v[0xF] = if borrow { 1 } else { 0 };
+                // VF is NOT borrow, and is written last so that it wins when x is F.
+                self.v[0xF] = if borrow { 0 } else { 1 };
             }
-            Instruction::ShiftRight { reg } => {
-                self.v[0xF] == self.v[reg] & 1;
-                self.v[reg] >>= 1
+            Instruction::ShiftRight { reg_x, reg_y } => {
+                let source = if self.quirks.shift { reg_x } else { reg_y };
+                let flag = self.v[source] & 1;
+                self.v[reg_x] = self.v[source] >> 1;
+                self.v[0xF] = flag;
             }
             Instruction::SubnRegReg { reg_x, reg_y } => {
-                // TODO: Check. I think they mean only subtract if Vx > Vy
                 let (v_x, borrow) = self.v[reg_y].overflowing_sub(self.v[reg_x]);
                 self.v[reg_x] = v_x;
-                self.v[0xF] = if borrow { 1 } else { 0 };
+                self.v[0xF] = if borrow { 0 } else { 1 };
             }
-            Instruction::ShiftLeft { reg } => {
-                self.v[0xF] == self.v[reg] & 0x80;
-                self.v[reg] <<= 1
+            Instruction::ShiftLeft { reg_x, reg_y } => {
+                let source = if self.quirks.shift { reg_x } else { reg_y };
+                let flag = self.v[source] >> 7;
+                self.v[reg_x] = self.v[source] << 1;
+                self.v[0xF] = flag;
             }
             Instruction::SkipRegNeqReg { reg_x, reg_y } => {
                 if self.v[reg_x] != self.v[reg_y] {
-                    self.program_counter += 2
+                    self.skip()
                 }
             }
             Instruction::SetI { addr } => self.i = addr,
             Instruction::JumpWithOffset { addr } => {
-                self.program_counter = (self.v[0] as u16) + addr;
+                let reg = if self.quirks.jump_with_vx {
+                    ((addr >> 8) & 0xF) as usize
+                } else {
+                    0
+                };
+                self.program_counter = (self.v[reg] as u16) + addr;
             }
             Instruction::Rand { reg, mask } => {
-                self.v[reg] = self.rng.random_range(0..255) & mask;
+                self.v[reg] = self.rng.next_byte() & mask;
             }
 
             Instruction::Draw {
@@ -274,31 +407,62 @@ impl Emulator {
                 reg_y,
                 nibble,
             } => {
-                // We're assuming a specific size of the screen
-                let v_x = self.v[reg_x] & 0x3F;
-                let v_y = self.v[reg_y] & 0x1F;
-                let rows = nibble as usize;
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        // Retry the draw until the next vertical blank.
+                        self.program_counter = self.program_counter.wrapping_sub(2);
+                        return Ok(StepOutcome::WaitingForVblank);
+                    }
+                    self.vblank = false;
+                }
+                // The starting position always wraps, the sprite itself may be clipped.
+                let v_x = self.v[reg_x] as usize % self.display.width();
+                let v_y = self.v[reg_y] as usize % self.display.height();
+                let clip = self.quirks.clipping;
                 let mut flip = false;
-                for row in 0..rows {
-                    flip |= self.display.draw_byte(
-                        self.memory[self.i as usize + row],
-                        v_x as usize,
-                        v_y as usize + row,
-                    )
+                // Every selected plane consumes its own copy of the sprite, one after the other.
+                let sprite_len = if nibble == 0 { 32 } else { nibble as usize };
+                let planes = self.display.planes().count_ones() as usize;
+                let mut addr = self
+                    .memory_range(self.i as usize, sprite_len * planes)?
+                    .start;
+                for plane in 0..Display::PLANES {
+                    if self.display.planes() & (1 << plane) == 0 {
+                        continue;
+                    }
+                    if nibble == 0 {
+                        for row in 0..16 {
+                            let word =
+                                u16::from_be_bytes([self.memory[addr], self.memory[addr + 1]]);
+                            flip |= self.display.draw_word(word, v_x, v_y + row, clip, plane);
+                            addr += 2;
+                        }
+                    } else {
+                        for row in 0..nibble as usize {
+                            flip |= self.display.draw_byte(
+                                self.memory[addr],
+                                v_x,
+                                v_y + row,
+                                clip,
+                                plane,
+                            );
+                            addr += 1;
+                        }
+                    }
                 }
                 self.v[0xF] = if flip { 1 } else { 0 };
             }
             Instruction::SkipIfKey { reg } => {
-                if self.keypad[self.v[reg] as usize] {
-                    self.program_counter += 2
+                if self.keypad[(self.v[reg] & 0xF) as usize] {
+                    self.skip()
                 }
             }
             Instruction::SkipIfNotKey { reg } => {
-                if !self.keypad[self.v[reg] as usize] {
-                    self.program_counter += 2
+                if !self.keypad[(self.v[reg] & 0xF) as usize] {
+                    self.skip()
                 }
             }
-            Instruction::LoadDelayTimer { reg } => self.v[reg] = self.delay_timer,
+            Instruction::LoadDelayTimer { reg } => self.v[reg] = self.timers.delay(),
             Instruction::WaitKeyPress { reg } => {
                 let mut key_pressed = false;
                 for (idx, key) in self.keypad.iter().enumerate() {
@@ -308,35 +472,695 @@ impl Emulator {
                         break;
                     }
                 }
-                // TODO: Find a better solution
-                // super hacky, rewind the program ccounter by two
+                // Execute the instruction again until a key is pressed.
                 if !key_pressed {
-                    self.program_counter -= 2;
+                    self.program_counter = self.program_counter.wrapping_sub(2);
+                    return Ok(StepOutcome::WaitingForKey);
                 }
             }
-            Instruction::SetDelayTimer { reg } => self.delay_timer = self.v[reg],
-            Instruction::SetSoundTimer { reg } => self.sound_timer = self.v[reg],
-            Instruction::AddI { reg } => self.i += self.v[reg] as u16,
+            Instruction::LongSetI { addr } => {
+                self.i = addr;
+                self.program_counter = self.program_counter.wrapping_add(2);
+            }
+            Instruction::SelectPlanes { planes } => self.display.select_planes(planes),
+            Instruction::LoadAudioPattern => {
+                let range = self.memory_range(self.i as usize, 16)?;
+                self.audio_pattern.copy_from_slice(&self.memory[range]);
+            }
+            Instruction::SetPitch { reg } => self.pitch = self.v[reg],
+            Instruction::SetDelayTimer { reg } => self.timers.set_delay(self.v[reg]),
+            Instruction::SetSoundTimer { reg } => self.timers.set_sound(self.v[reg]),
+            Instruction::AddI { reg } => {
+                let addr = self.i as usize + self.v[reg] as usize;
+                if addr > u16::MAX as usize {
+                    return Err(Fault::IndexOverflow { addr });
+                }
+                self.i = addr as u16;
+            }
             Instruction::SetIToSprite { reg } => {
-                // TODO: Check for errors.
-                self.i = self.v[reg] as u16;
+                self.i = (self.font_address + 5 * (self.v[reg] & 0xF) as usize) as u16;
+            }
+            Instruction::SetIToBigSprite { reg } => {
+                let big_font_address = self.font_address + FONTS.len();
+                self.i = (big_font_address + 10 * (self.v[reg] & 0xF) as usize) as u16;
             }
             Instruction::StoreBCD { reg } => {
                 let v_x = self.v[reg];
-                self.memory[self.i as usize] = (v_x >> 2) & 1;
-                self.memory[self.i as usize] = (v_x >> 1) & 1;
-                self.memory[self.i as usize] = (v_x) & 1
+                let range = self.memory_range(self.i as usize, 3)?;
+                self.memory[range].copy_from_slice(&[v_x / 100, (v_x / 10) % 10, v_x % 10]);
             }
             Instruction::StoreRegisters { reg } => {
-                for offset in 0..=reg {
-                    self.memory[(self.i as usize) + offset] = self.v[offset]
-                }
+                let range = self.memory_range(self.i as usize, reg + 1)?;
+                self.memory[range].copy_from_slice(&self.v[..=reg]);
+                self.i = self.i.wrapping_add(self.quirks.load_store.offset(reg));
             }
             Instruction::LoadRegisters { reg } => {
-                for offset in 0..=reg {
-                    self.v[offset] = self.memory[(self.i as usize) + offset]
-                }
+                let range = self.memory_range(self.i as usize, reg + 1)?;
+                self.v[..=reg].copy_from_slice(&self.memory[range]);
+                self.i = self.i.wrapping_add(self.quirks.load_store.offset(reg));
             }
+            Instruction::StoreFlags { reg } => self.flags[..=reg].copy_from_slice(&self.v[..=reg]),
+            Instruction::LoadFlags { reg } => self.v[..=reg].copy_from_slice(&self.flags[..=reg]),
+        }
+        Ok(StepOutcome::Executed)
+    }
+    /// The registers touched by 5xy2/5xy3, in the order they are laid out in memory.
+    fn register_range(reg_x: usize, reg_y: usize) -> Box<dyn Iterator<Item = usize>> {
+        if reg_x <= reg_y {
+            Box::new(reg_x..=reg_y)
+        } else {
+            Box::new((reg_y..=reg_x).rev())
         }
     }
 }
+
+/// Configures and creates an [`Emulator`].
+///
+/// ```no_run
+/// use capp8_core::{emulator::EmulatorBuilder, quirks::Quirks};
+///
+/// let rom = std::fs::read("game.ch8").unwrap();
+/// let emulator = EmulatorBuilder::new()
+///     .quirks(Quirks::SUPER_CHIP)
+///     .seed(42)
+///     .build(&rom)
+///     .unwrap();
+/// ```
+pub struct EmulatorBuilder {
+    load_address: u16,
+    font_address: u16,
+    memory_size: usize,
+    quirks: Quirks,
+    seed: Option<u64>,
+    random_source: Option<Box<dyn RandomSource>>,
+    clock_speed: u32,
+    timer_rate: u32,
+}
+
+impl EmulatorBuilder {
+    /// Where most programs expect to be loaded.
+    pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+    /// Where programs for the ETI-660 expect to be loaded.
+    pub const ETI_660_LOAD_ADDRESS: u16 = 0x600;
+    pub const DEFAULT_CLOCK_SPEED: u32 = 700;
+
+    pub fn new() -> Self {
+        Self {
+            load_address: Self::DEFAULT_LOAD_ADDRESS,
+            font_address: FONT_ADDRESS as u16,
+            memory_size: Emulator::MEMORY_SIZE,
+            quirks: Quirks::default(),
+            seed: None,
+            random_source: None,
+            clock_speed: Self::DEFAULT_CLOCK_SPEED,
+            timer_rate: Timers::DEFAULT_RATE,
+        }
+    }
+    /// Address the ROM is copied to, and where execution starts.
+    pub fn load_address(mut self, load_address: u16) -> Self {
+        self.load_address = load_address;
+        self
+    }
+    /// Address of the small font, the SUPER-CHIP large font is placed right after it.
+    pub fn font_address(mut self, font_address: u16) -> Self {
+        self.font_address = font_address;
+        self
+    }
+    /// Size of the address space, 4 KiB for the original machine and up to 64 KiB for XO-CHIP.
+    pub fn memory_size(mut self, memory_size: usize) -> Self {
+        self.memory_size = memory_size;
+        self
+    }
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+    /// Seed for Cxkk, a random one is used when not set.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    /// Use `random_source` instead of [`SplitMix64`]. It is seeded with
+    /// [`RandomSource::set_state`].
+    pub fn random_source(mut self, random_source: Box<dyn RandomSource>) -> Self {
+        self.random_source = Some(random_source);
+        self
+    }
+    /// Instructions per second the frontend should aim for.
+    pub fn clock_speed(mut self, clock_speed: u32) -> Self {
+        self.clock_speed = clock_speed;
+        self
+    }
+    /// How many times per second the timers tick, whether [`Emulator::step`] or
+    /// [`Emulator::run_frame`] advances them, 60 unless the platform differs.
+    pub fn timer_rate(mut self, timer_rate: u32) -> Self {
+        self.timer_rate = timer_rate;
+        self
+    }
+    pub fn build_from_file<P: AsRef<Path>>(self, rom_path: &P) -> Result<Emulator, LoadError> {
+        let mut file = OpenOptions::new().read(true).open(rom_path)?;
+        let mut rom: Vec<u8> = Vec::new();
+        file.read_to_end(&mut rom)?;
+        self.build(&rom)
+    }
+    pub fn build(self, rom: &[u8]) -> Result<Emulator, LoadError> {
+        let memory_size = self.memory_size;
+        let fonts_end = self.font_address as usize + FONTS.len() + BIG_FONTS.len();
+        let load_address = self.load_address as usize;
+        if memory_size > Emulator::MEMORY_SIZE
+            || fonts_end > memory_size
+            || load_address >= memory_size
+        {
+            return Err(LoadError::InvalidLayout { memory_size });
+        }
+        let capacity = memory_size - load_address;
+        if rom.len() > capacity {
+            return Err(LoadError::RomTooLarge {
+                size: rom.len(),
+                capacity,
+            });
+        }
+
+        let mut memory = vec![0; memory_size];
+        let font_address = self.font_address as usize;
+        let big_font_address = font_address + FONTS.len();
+        memory[font_address..big_font_address].copy_from_slice(&FONTS[..]);
+        memory[big_font_address..fonts_end].copy_from_slice(&BIG_FONTS[..]);
+        memory[load_address..load_address + rom.len()].copy_from_slice(rom);
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut rng = self
+            .random_source
+            .unwrap_or_else(|| Box::new(SplitMix64::new(seed)));
+        rng.set_state(seed);
+        Ok(Emulator {
+            v: [0; 16],
+            i: 0,
+            program_counter: self.load_address,
+            memory,
+            stack_pointer: 0,
+            stack: [0; 16],
+            timers: Timers::new(self.timer_rate),
+            display: Display::new(),
+            keypad: [false; 16],
+            flags: [0; 16],
+            halted: false,
+            audio_pattern: [0; 16],
+            pitch: 64,
+            rng,
+            seed,
+            quirks: self.quirks,
+            font_address,
+            load_address: self.load_address,
+            clock_speed: self.clock_speed,
+            rom_hash: Sha1::digest(rom).into(),
+            vblank: false,
+            tracer: None,
+            synth: Synth::default(),
+        })
+    }
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An [`EmulatorError`] that does not know yet which instruction caused it.
+enum Fault {
+    UnknownOpcode,
+    MachineCodeRoutine { addr: u16 },
+    StackOverflow,
+    StackUnderflow,
+    IndexOverflow { addr: usize },
+    MemoryOutOfBounds { addr: usize },
+}
+
+impl Fault {
+    fn at(self, pc: u16, opcode: u16) -> EmulatorError {
+        match self {
+            Fault::UnknownOpcode => EmulatorError::UnknownOpcode { pc, opcode },
+            Fault::MachineCodeRoutine { addr } => {
+                EmulatorError::MachineCodeRoutine { pc, opcode, addr }
+            }
+            Fault::StackOverflow => EmulatorError::StackOverflow { pc, opcode },
+            Fault::StackUnderflow => EmulatorError::StackUnderflow { pc, opcode },
+            Fault::IndexOverflow { addr } => EmulatorError::IndexOverflow { pc, opcode, addr },
+            Fault::MemoryOutOfBounds { addr } => {
+                EmulatorError::MemoryOutOfBounds { pc, opcode, addr }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::LoadStore;
+
+    /// Run the first `steps` instructions of `rom` with `quirks`.
+    fn run(rom: &[u8], quirks: Quirks, steps: usize) -> Emulator {
+        let mut emulator = EmulatorBuilder::new().quirks(quirks).build(rom).unwrap();
+        for _ in 0..steps {
+            emulator.step(Duration::ZERO).unwrap();
+        }
+        emulator
+    }
+
+    #[test]
+    fn shift_quirk_picks_the_shifted_register() {
+        // V1 := 5, V2 := 6, V1 >>= V2, V3 := 0x81, V3 <<= V2
+        let rom = [0x61, 0x05, 0x62, 0x06, 0x81, 0x26, 0x63, 0x81, 0x83, 0x2E];
+        let shifted = |shift| {
+            let quirks = Quirks {
+                shift,
+                ..Quirks::COSMAC_VIP
+            };
+            let (right, left) = (run(&rom, quirks, 3), run(&rom, quirks, 5));
+            (right.v[1], right.v[0xF], left.v[3], left.v[0xF])
+        };
+        assert_eq!(shifted(false), (3, 0, 12, 0));
+        assert_eq!(shifted(true), (2, 1, 2, 1));
+    }
+
+    #[test]
+    fn load_store_increment_quirk_moves_i() {
+        // I := 0x300, save V0 - V2, load V0 - V1
+        let rom = [0xA3, 0x00, 0xF2, 0x55, 0xF1, 0x65];
+        let i = |load_store| {
+            let quirks = Quirks {
+                load_store,
+                ..Quirks::COSMAC_VIP
+            };
+            run(&rom, quirks, 3).i
+        };
+        assert_eq!(i(LoadStore::Unchanged), 0x300);
+        assert_eq!(i(LoadStore::IncrementByX), 0x303);
+        assert_eq!(i(LoadStore::Increment), 0x305);
+    }
+
+    #[test]
+    fn jump_with_vx_quirk_picks_the_offset_register() {
+        // V0 := 1, V2 := 4, jump0 0x210
+        let rom = [0x60, 0x01, 0x62, 0x04, 0xB2, 0x10];
+        let pc = |jump_with_vx| {
+            let quirks = Quirks {
+                jump_with_vx,
+                ..Quirks::COSMAC_VIP
+            };
+            run(&rom, quirks, 3).program_counter
+        };
+        assert_eq!(pc(false), 0x211);
+        assert_eq!(pc(true), 0x214);
+    }
+
+    #[test]
+    fn vf_reset_quirk_clears_vf_after_logic() {
+        // VF := 5, V0 |= V1, VF := 5, V0 &= V1, VF := 5, V0 ^= V1
+        let rom = [
+            0x6F, 0x05, 0x80, 0x11, 0x6F, 0x05, 0x80, 0x12, 0x6F, 0x05, 0x80, 0x13,
+        ];
+        for steps in [2, 4, 6] {
+            let vf = |vf_reset| {
+                let quirks = Quirks {
+                    vf_reset,
+                    ..Quirks::COSMAC_VIP
+                };
+                run(&rom, quirks, steps).v[0xF]
+            };
+            assert_eq!((vf(false), vf(true)), (5, 0), "{steps} steps");
+        }
+    }
+
+    #[test]
+    fn clipping_quirk_drops_pixels_past_the_edge() {
+        // V0 := 62, V1 := 31, I := 0x20A, sprite V0 V1 2, then the sprite: two full rows
+        let rom = [
+            0x60, 0x3E, 0x61, 0x1F, 0xA2, 0x0A, 0xD0, 0x12, 0x00, 0x00, 0xFF, 0xFF,
+        ];
+        let pixels = |clipping| {
+            let quirks = Quirks {
+                clipping,
+                ..Quirks::XO_CHIP
+            };
+            let emulator = run(&rom, quirks, 4);
+            let display = &emulator.display;
+            [
+                display[(63, 31)],
+                display[(0, 31)],
+                display[(63, 0)],
+                display[(0, 0)],
+            ]
+        };
+        assert_eq!(pixels(false), [1, 1, 1, 1]);
+        assert_eq!(pixels(true), [1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn display_wait_quirk_holds_draws_until_the_vblank() {
+        // sprite V0 V0 1, sprite V0 V0 1
+        let rom = [0xD0, 0x01, 0xD0, 0x01];
+        let mut waiting = EmulatorBuilder::new()
+            .quirks(Quirks::COSMAC_VIP)
+            .build(&rom)
+            .unwrap();
+        assert_eq!(
+            waiting.step(Duration::ZERO).unwrap(),
+            StepOutcome::WaitingForVblank
+        );
+        assert_eq!(waiting.program_counter, 0x200);
+        // Each frame ends with a vertical blank, so one draw fits in the next one.
+        assert_eq!(waiting.run_frame(10).unwrap(), 0);
+        assert_eq!(waiting.run_frame(10).unwrap(), 1);
+        assert_eq!(waiting.program_counter, 0x202);
+
+        let quirks = Quirks {
+            display_wait: false,
+            ..Quirks::COSMAC_VIP
+        };
+        let free = run(&rom, quirks, 2);
+        assert_eq!(free.program_counter, 0x204);
+    }
+
+    #[test]
+    fn superchip_switches_resolution_and_scrolls() {
+        let mut rom = vec![
+            0x00, 0xFF, // hires
+            0xA2, 0x10, // I := 0x210
+            0xD0, 0x00, // sprite V0 V0 0
+            0x00, 0xC4, // scroll-down 4
+            0x00, 0xFB, // scroll-right
+            0x00, 0xFC, // scroll-left
+            0x00, 0xFE, // lores
+            0x00, 0x00,
+        ];
+        // A 16x16 sprite lighting the left and right columns.
+        rom.extend([0x80, 0x01].repeat(16));
+        let mut emulator = run(&rom, Quirks::SUPER_CHIP, 1);
+        assert_eq!(emulator.display.width(), 128);
+
+        emulator.step(Duration::ZERO).unwrap();
+        emulator.step(Duration::ZERO).unwrap();
+        let display = &emulator.display;
+        assert_eq!(
+            [display[(0, 0)], display[(15, 15)], display[(16, 0)]],
+            [1, 1, 0]
+        );
+        assert_eq!(display[(0, 16)], 0);
+
+        emulator.step(Duration::ZERO).unwrap();
+        let display = &emulator.display;
+        assert_eq!(
+            [display[(0, 3)], display[(0, 4)], display[(15, 19)]],
+            [0, 1, 1]
+        );
+        emulator.step(Duration::ZERO).unwrap();
+        assert_eq!([emulator.display[(0, 4)], emulator.display[(4, 4)]], [0, 1]);
+        emulator.step(Duration::ZERO).unwrap();
+        assert_eq!([emulator.display[(0, 4)], emulator.display[(4, 4)]], [1, 0]);
+
+        emulator.step(Duration::ZERO).unwrap();
+        assert!(!emulator.display.is_hires());
+        assert!(emulator.display.pixel.iter().all(|&pixel| pixel == 0));
+    }
+
+    #[test]
+    fn superchip_big_font_and_flags() {
+        let rom = [
+            0x60, 0x07, // V0 := 7
+            0xF0, 0x30, // i := bighex V0
+            0x61, 0x09, // V1 := 9
+            0xF1, 0x75, // saveflags V1
+            0x60, 0x00, // V0 := 0
+            0x61, 0x00, // V1 := 0
+            0xF1, 0x85, // loadflags V1
+        ];
+        let emulator = run(&rom, Quirks::SUPER_CHIP, 2);
+        let big_font = FONT_ADDRESS + FONTS.len();
+        assert_eq!(emulator.i as usize, big_font + 70);
+        assert_eq!(
+            emulator.memory[emulator.i as usize..][..10],
+            BIG_FONTS[70..80]
+        );
+
+        let emulator = run(&rom, Quirks::SUPER_CHIP, 7);
+        assert_eq!(emulator.v[..2], [7, 9]);
+        assert_eq!(emulator.flags[..3], [7, 9, 0]);
+    }
+
+    #[test]
+    fn xochip_long_i_is_skipped_whole() {
+        let rom = [
+            0x30, 0x00, // if V0 != 0 then
+            0xF0, 0x00, 0x12, 0x34, // i := long 0x1234
+            0xF0, 0x00, 0xAB, 0xCD, // i := long 0xABCD
+        ];
+        let emulator = run(&rom, Quirks::XO_CHIP, 1);
+        assert_eq!(emulator.program_counter, 0x206);
+        let emulator = run(&rom, Quirks::XO_CHIP, 2);
+        assert_eq!((emulator.program_counter, emulator.i), (0x20A, 0xABCD));
+    }
+
+    #[test]
+    fn xochip_saves_and_loads_register_ranges_both_ways() {
+        let rom = [
+            0x61, 0x01, 0x62, 0x02, 0x63, 0x03, // V1 := 1, V2 := 2, V3 := 3
+            0xA3, 0x00, 0x51, 0x32, // i := 0x300, save V1 - V3
+            0xA3, 0x10, 0x53, 0x12, // i := 0x310, save V3 - V1
+            0xA3, 0x00, 0x54, 0x63, // i := 0x300, load V4 - V6
+            0x5A, 0x83, // load VA - V8
+        ];
+        let emulator = run(&rom, Quirks::XO_CHIP, 9);
+        assert_eq!(emulator.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(emulator.memory[0x310..0x313], [3, 2, 1]);
+        assert_eq!(emulator.v[4..7], [1, 2, 3]);
+        assert_eq!(emulator.i, 0x300);
+        let emulator = run(&rom, Quirks::XO_CHIP, 10);
+        assert_eq!(emulator.v[8..11], [3, 2, 1]);
+    }
+
+    #[test]
+    fn xochip_draws_on_the_selected_planes() {
+        let rom = [
+            0xA2, 0x0C, // i := 0x20C
+            0xF2, 0x01, // plane 2
+            0xD0, 0x01, // sprite V0 V0 1
+            0xF3, 0x01, // plane 3
+            0xD0, 0x01, // sprite V0 V0 1
+            0x00, 0x00, // padding
+            0x80, 0x40, // one sprite per plane
+        ];
+        let emulator = run(&rom, Quirks::XO_CHIP, 3);
+        assert_eq!(emulator.display.planes(), 0b10);
+        assert_eq!([emulator.display[(0, 0)], emulator.display[(1, 0)]], [2, 0]);
+        // The first plane draws the first byte and the second plane the next one.
+        let emulator = run(&rom, Quirks::XO_CHIP, 5);
+        assert_eq!([emulator.display[(0, 0)], emulator.display[(1, 0)]], [3, 2]);
+        assert_eq!(emulator.v[0xF], 0);
+    }
+
+    #[test]
+    fn xochip_loads_the_audio_pattern_and_pitch() {
+        let mut rom = vec![
+            0xA2, 0x08, // i := 0x208
+            0xF0, 0x02, // audio
+            0x60, 0x70, // V0 := 0x70
+            0xF0, 0x3A, // pitch := V0
+        ];
+        let pattern: Vec<u8> = (0..16).map(|byte| byte * 17).collect();
+        rom.extend(&pattern);
+        let emulator = run(&rom, Quirks::XO_CHIP, 4);
+        assert_eq!(emulator.audio_pattern(), &pattern[..]);
+        assert_eq!(emulator.pitch(), 0x70);
+    }
+
+    /// The error the first faulting instruction stops `emulator` with.
+    fn fault(emulator: &mut Emulator) -> EmulatorError {
+        loop {
+            if let Err(error) = emulator.run_frame(10) {
+                return error;
+            }
+        }
+    }
+
+    #[test]
+    fn bad_programs_fault_instead_of_panicking() {
+        let emulator = |rom: &[u8]| Emulator::from_bytes(rom).unwrap();
+        let faults = [
+            (
+                // call 0x200, forever
+                emulator(&[0x22, 0x00]),
+                EmulatorError::StackOverflow {
+                    pc: 0x200,
+                    opcode: 0x2200,
+                },
+            ),
+            (
+                emulator(&[0x00, 0xEE]),
+                EmulatorError::StackUnderflow {
+                    pc: 0x200,
+                    opcode: 0x00EE,
+                },
+            ),
+            (
+                // i := long 0xFFFF, V0 := 1, i += V0
+                emulator(&[0xF0, 0x00, 0xFF, 0xFF, 0x60, 0x01, 0xF0, 0x1E]),
+                EmulatorError::IndexOverflow {
+                    pc: 0x206,
+                    opcode: 0xF01E,
+                    addr: 0x10000,
+                },
+            ),
+            (
+                // i := long 0xFFFF, sprite V0 V0 2
+                emulator(&[0xF0, 0x00, 0xFF, 0xFF, 0xD0, 0x02]),
+                EmulatorError::MemoryOutOfBounds {
+                    pc: 0x204,
+                    opcode: 0xD002,
+                    addr: 0x10000,
+                },
+            ),
+            (
+                // i := 0xFFE in 4 KiB, save V0 - V2
+                EmulatorBuilder::new()
+                    .memory_size(0x1000)
+                    .build(&[0xAF, 0xFE, 0xF2, 0x55])
+                    .unwrap(),
+                EmulatorError::MemoryOutOfBounds {
+                    pc: 0x202,
+                    opcode: 0xF255,
+                    addr: 0x1000,
+                },
+            ),
+            (
+                emulator(&[0xE0, 0x00]),
+                EmulatorError::UnknownOpcode {
+                    pc: 0x200,
+                    opcode: 0xE000,
+                },
+            ),
+            (
+                emulator(&[0x01, 0x23]),
+                EmulatorError::MachineCodeRoutine {
+                    pc: 0x200,
+                    opcode: 0x0123,
+                    addr: 0x123,
+                },
+            ),
+        ];
+        for (mut emulator, expected) in faults {
+            assert_eq!(fault(&mut emulator), expected);
+        }
+    }
+
+    #[test]
+    fn waits_at_the_end_of_memory_without_overflowing() {
+        for (rom, outcome) in [
+            ([0xF0, 0x0A], StepOutcome::WaitingForKey),
+            ([0xD0, 0x01], StepOutcome::WaitingForVblank),
+        ] {
+            let mut emulator = EmulatorBuilder::new()
+                .load_address(0xFFFE)
+                .build(&rom)
+                .unwrap();
+            assert_eq!(emulator.step(Duration::ZERO).unwrap(), outcome);
+            assert_eq!(emulator.program_counter, 0xFFFE);
+        }
+    }
+
+    #[test]
+    fn builder_lays_out_memory() {
+        // i := hex V0, at 0x600 with the font at 0x100 in 4 KiB.
+        let mut emulator = EmulatorBuilder::new()
+            .load_address(EmulatorBuilder::ETI_660_LOAD_ADDRESS)
+            .font_address(0x100)
+            .memory_size(0x1000)
+            .build(&[0xF0, 0x29])
+            .unwrap();
+        assert_eq!(emulator.memory.len(), 0x1000);
+        assert_eq!(emulator.program_counter, 0x600);
+        assert_eq!(emulator.memory[0x600..0x602], [0xF0, 0x29]);
+        assert_eq!(emulator.memory[0x100..0x150], FONTS);
+        assert_eq!(emulator.memory[0x150..0x150 + BIG_FONTS.len()], BIG_FONTS);
+        assert!(
+            emulator.memory[FONT_ADDRESS..0x100]
+                .iter()
+                .all(|&byte| byte == 0)
+        );
+        emulator.step(Duration::ZERO).unwrap();
+        assert_eq!(emulator.i, 0x100);
+    }
+
+    #[test]
+    fn to_builder_starts_over_the_same_way() {
+        // V0 := random 0xFF, then loop
+        let rom = [0xC0, 0xFF, 0x13, 0x02];
+        let builder = || {
+            EmulatorBuilder::new()
+                .load_address(0x300)
+                .font_address(0x100)
+                .memory_size(0x1000)
+                .quirks(Quirks::SUPER_CHIP)
+                .clock_speed(1000)
+                .timer_rate(50)
+        };
+        let mut emulator = builder().build(&rom).unwrap();
+        let fresh = emulator.save_state();
+        emulator.run_frame(10).unwrap();
+
+        let restarted = emulator.to_builder().build(&rom).unwrap();
+        assert_eq!(restarted.save_state(), fresh);
+        assert_eq!(restarted.seed(), emulator.seed());
+        assert_eq!(restarted.clock_speed(), 1000);
+        assert_eq!(restarted.timers().rate(), 50);
+    }
+
+    #[test]
+    fn builder_rejects_what_does_not_fit() {
+        let small = || EmulatorBuilder::new().memory_size(0x1000);
+        assert!(matches!(
+            small().build(&[0; 0xE01]),
+            Err(LoadError::RomTooLarge {
+                size: 0xE01,
+                capacity: 0xE00
+            })
+        ));
+        assert!(small().build(&[0; 0xE00]).is_ok());
+        for builder in [
+            EmulatorBuilder::new().memory_size(Emulator::MEMORY_SIZE + 1),
+            small().load_address(0x1000),
+            small().font_address(0xFF0),
+        ] {
+            assert!(matches!(
+                builder.build(&[]),
+                Err(LoadError::InvalidLayout { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn frames_run_instructions_then_tick_the_timers() {
+        // 200: 6005  V0 := 5
+        // 202: F015  delay timer := V0
+        // 204: 7101  V1 += 1
+        // 206: 1204  loop
+        let rom = [0x60, 0x05, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04];
+        let mut emulator = Emulator::from_bytes(&rom).unwrap();
+        assert_eq!(emulator.run_frame(10).unwrap(), 10);
+        assert_eq!((emulator.v[1], emulator.delay_timer()), (4, 4));
+        assert_eq!(emulator.run_frame(0).unwrap(), 0);
+        assert_eq!(emulator.delay_timer(), 3);
+
+        // Timers ticking at 30 Hz tick every other frame.
+        let mut emulator = EmulatorBuilder::new().timer_rate(30).build(&rom).unwrap();
+        emulator.run_frame(2).unwrap();
+        assert_eq!(emulator.delay_timer(), 5);
+        emulator.run_frame(0).unwrap();
+        assert_eq!(emulator.delay_timer(), 4);
+
+        // 00FD ends the frame early.
+        let mut emulator = Emulator::from_bytes(&[0x00, 0xFD]).unwrap();
+        assert_eq!(emulator.run_frame(10).unwrap(), 0);
+        assert!(emulator.is_halted());
+    }
+}
@@ -0,0 +1,281 @@
+use std::fmt;
+
+/// Why [`Emulator::step`](crate::emulator::Emulator::step) could not execute an instruction.
+///
+/// Every variant records the address of the faulting instruction (`pc`) and its raw
+/// `opcode`, so a bad ROM can be reported instead of bringing the host process down.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    /// The opcode does not match any supported instruction.
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// 0nnn asked for a native machine code routine, which cannot be emulated.
+    MachineCodeRoutine { pc: u16, opcode: u16, addr: u16 },
+    /// A call was made with all 16 stack levels in use.
+    StackOverflow { pc: u16, opcode: u16 },
+    /// A return was made with an empty stack.
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// Fx1E pushed I past the end of the address space.
+    IndexOverflow { pc: u16, opcode: u16, addr: usize },
+    /// The instruction read or wrote outside of memory.
+    MemoryOutOfBounds { pc: u16, opcode: u16, addr: usize },
+}
+
+impl EmulatorError {
+    /// The address of the instruction that faulted.
+    pub fn pc(&self) -> u16 {
+        match *self {
+            EmulatorError::UnknownOpcode { pc, .. }
+            | EmulatorError::MachineCodeRoutine { pc, .. }
+            | EmulatorError::StackOverflow { pc, .. }
+            | EmulatorError::StackUnderflow { pc, .. }
+            | EmulatorError::IndexOverflow { pc, .. }
+            | EmulatorError::MemoryOutOfBounds { pc, .. } => pc,
+        }
+    }
+
+    /// The raw opcode of the instruction that faulted.
+    pub fn opcode(&self) -> u16 {
+        match *self {
+            EmulatorError::UnknownOpcode { opcode, .. }
+            | EmulatorError::MachineCodeRoutine { opcode, .. }
+            | EmulatorError::StackOverflow { opcode, .. }
+            | EmulatorError::StackUnderflow { opcode, .. }
+            | EmulatorError::IndexOverflow { opcode, .. }
+            | EmulatorError::MemoryOutOfBounds { opcode, .. } => opcode,
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EmulatorError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {opcode:04X} at {pc:#05X}")
+            }
+            EmulatorError::MachineCodeRoutine { pc, opcode, addr } => write!(
+                f,
+                "opcode {opcode:04X} at {pc:#05X} calls the machine code routine at {addr:#05X}"
+            ),
+            EmulatorError::StackOverflow { pc, opcode } => {
+                write!(f, "stack overflow executing {opcode:04X} at {pc:#05X}")
+            }
+            EmulatorError::StackUnderflow { pc, opcode } => {
+                write!(f, "stack underflow executing {opcode:04X} at {pc:#05X}")
+            }
+            EmulatorError::IndexOverflow { pc, opcode, addr } => write!(
+                f,
+                "opcode {opcode:04X} at {pc:#05X} moved I out of the address space ({addr:#X})"
+            ),
+            EmulatorError::MemoryOutOfBounds { pc, opcode, addr } => write!(
+                f,
+                "opcode {opcode:04X} at {pc:#05X} accessed memory out of bounds at {addr:#X}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmulatorError {}
+
+/// Why an [`Emulator`](crate::emulator::Emulator) could not be created.
+#[derive(Debug)]
+pub enum LoadError {
+    /// The ROM file could not be read.
+    Io(std::io::Error),
+    /// The ROM does not fit between the load address and the end of memory.
+    RomTooLarge { size: usize, capacity: usize },
+    /// The fonts or the load address do not fit in the configured memory.
+    InvalidLayout { memory_size: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "could not read the ROM: {error}"),
+            LoadError::RomTooLarge { size, capacity } => write!(
+                f,
+                "the ROM is {size} bytes long, but only {capacity} bytes fit in memory"
+            ),
+            LoadError::InvalidLayout { memory_size } => write!(
+                f,
+                "the fonts and the load address do not fit in {memory_size} bytes of memory"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(error: std::io::Error) -> Self {
+        LoadError::Io(error)
+    }
+}
+
+/// Why a save state could not be restored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data does not start with the save state magic bytes.
+    NotASnapshot,
+    /// The save state has a format version other than [`VERSION`](crate::snapshot::VERSION).
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of a chunk.
+    Truncated,
+    /// A chunk required to restore the emulator is missing.
+    MissingChunk([u8; 4]),
+    /// A chunk does not have the expected size.
+    InvalidChunk([u8; 4]),
+    /// The save state was taken while running a different ROM.
+    RomMismatch,
+    /// The save state was taken with a different quirk profile.
+    QuirksMismatch,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::NotASnapshot => write!(f, "not a capp8 save state"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {version}")
+            }
+            SnapshotError::Truncated => write!(f, "the save state is truncated"),
+            SnapshotError::MissingChunk(tag) => write!(
+                f,
+                "the save state has no `{}` chunk",
+                String::from_utf8_lossy(tag)
+            ),
+            SnapshotError::InvalidChunk(tag) => write!(
+                f,
+                "the `{}` chunk of the save state is malformed",
+                String::from_utf8_lossy(tag)
+            ),
+            SnapshotError::RomMismatch => write!(f, "the save state belongs to another ROM"),
+            SnapshotError::QuirksMismatch => {
+                write!(f, "the save state was taken with different quirks")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// Why a movie could not be loaded or played back.
+#[derive(Debug)]
+pub enum MovieError {
+    Io(std::io::Error),
+    /// The ROM to play the movie on could not be loaded.
+    Load(LoadError),
+    /// The data does not start with the movie magic bytes.
+    NotAMovie,
+    /// The movie was written by a newer, incompatible version.
+    UnsupportedVersion(u16),
+    /// The data ends before the last frame.
+    Truncated,
+    /// The movie was recorded with a different ROM.
+    RomMismatch,
+    /// The movie was recorded with a different quirk profile.
+    QuirksMismatch,
+    /// The emulator was not seeded with the seed of the movie.
+    SeedMismatch,
+    /// The emulator state after `frame` does not match the recording.
+    Desync {
+        frame: usize,
+        expected: u64,
+        actual: u64,
+    },
+    /// The emulator faulted during playback.
+    Emulator(EmulatorError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Io(error) => write!(f, "could not access the movie: {error}"),
+            MovieError::Load(error) => write!(f, "{error}"),
+            MovieError::NotAMovie => write!(f, "not a capp8 movie"),
+            MovieError::UnsupportedVersion(version) => {
+                write!(f, "unsupported movie version {version}")
+            }
+            MovieError::Truncated => write!(f, "the movie is truncated"),
+            MovieError::RomMismatch => write!(f, "the movie was recorded with another ROM"),
+            MovieError::QuirksMismatch => {
+                write!(f, "the movie was recorded with different quirks")
+            }
+            MovieError::SeedMismatch => write!(f, "the movie was recorded with another seed"),
+            MovieError::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "desync after frame {frame}: expected state {expected:016x}, got {actual:016x}"
+            ),
+            MovieError::Emulator(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MovieError::Io(error) => Some(error),
+            MovieError::Load(error) => Some(error),
+            MovieError::Emulator(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for MovieError {
+    fn from(error: std::io::Error) -> Self {
+        MovieError::Io(error)
+    }
+}
+
+impl From<LoadError> for MovieError {
+    fn from(error: LoadError) -> Self {
+        MovieError::Load(error)
+    }
+}
+
+impl From<EmulatorError> for MovieError {
+    fn from(error: EmulatorError) -> Self {
+        MovieError::Emulator(error)
+    }
+}
+
+/// A line of an execution trace that could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParseError {
+    /// 1-based line number in the trace.
+    pub line: usize,
+    /// The field that is missing or malformed.
+    pub field: &'static str,
+}
+
+impl fmt::Display for TraceParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: missing or invalid {}", self.line, self.field)
+    }
+}
+
+impl std::error::Error for TraceParseError {}
+
+/// What happened during a successful [`Emulator::step`](crate::emulator::Emulator::step).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// Fx0A is blocking until a key is pressed.
+    WaitingForKey,
+    /// A draw is blocking until the next vertical blank (`display_wait` quirk).
+    WaitingForVblank,
+    /// The program stopped the interpreter with 00FD.
+    Halted,
+}
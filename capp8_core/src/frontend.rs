@@ -3,7 +3,7 @@ use std::time::Duration;
 pub trait Frontend {
     fn poll_keys(&mut self);
     fn render_display(&mut self);
-    fn play_sound(&self);
+    fn play_sound(&mut self);
     fn step(&mut self, duration: Duration);
     fn run(&mut self);
 }
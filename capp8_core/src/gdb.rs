@@ -0,0 +1,517 @@
+//! A GDB remote serial protocol stub, so debugger front-ends can attach over TCP.
+//!
+//! GDB has no CHIP-8 architecture, so the register file is described to front-ends with
+//! a target description (`qXfer:features:read:target.xml`): V0 to VF, I, PC, SP, DT and
+//! ST, in that order. I and PC are 16 bits wide and the rest 8, and multi-byte values are
+//! sent big-endian like everything else on the machine.
+//!
+//! Supported packets are `?`, `g`/`G`, `p`/`P`, `m`/`M`, `Z0`-`Z4`/`z0`-`z4`, `s`, `c`, `D`
+//! and `k`, plus the queries front-ends send while connecting. Continuing runs until a
+//! breakpoint or watchpoint is hit, the program exits with 00FD, it faults, or the
+//! front-end interrupts it with Ctrl-C.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+use crate::{
+    debugger::{Access, Debugger, Register, Stop, WatchKind},
+    error::EmulatorError,
+};
+
+/// How many instructions to run between checks for an interrupt from the front-end.
+const SLICE: usize = 10_000;
+
+pub struct GdbStub {
+    debugger: Debugger,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger }
+    }
+
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    pub fn debugger_mut(&mut self) -> &mut Debugger {
+        &mut self.debugger
+    }
+
+    pub fn into_inner(self) -> Debugger {
+        self.debugger
+    }
+
+    /// Wait for a front-end to connect on `addr`, then serve it until it detaches.
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        self.serve(stream)
+    }
+
+    /// Serve a connected front-end until it detaches, kills the program or disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            acks: true,
+        };
+        while let Some(packet) = connection.receive()? {
+            match packet.as_str() {
+                "D" => return connection.send("OK"),
+                "k" => return Ok(()),
+                "QStartNoAckMode" => {
+                    connection.send("OK")?;
+                    connection.acks = false;
+                }
+                _ => {
+                    let reply = self.handle(&packet, &mut connection)?;
+                    connection.send(&reply)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The reply to `packet`. Unsupported packets get the empty reply the protocol asks for.
+    fn handle(&mut self, packet: &str, connection: &mut Connection) -> io::Result<String> {
+        let command = packet.get(..1).unwrap_or_default();
+        let args = packet.get(1..).unwrap_or_default();
+        let reply = match command {
+            "?" => "S05".into(),
+            "g" => Register::ALL
+                .iter()
+                .map(|&register| self.encode_register(register))
+                .collect(),
+            "G" => self.write_registers(args),
+            "p" => parse_hex(args)
+                .and_then(|index| Register::ALL.get(index))
+                .map_or_else(|| "E01".into(), |&register| self.encode_register(register)),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.set_stop_point(command == "Z", args),
+            "s" | "c" => {
+                if !args.is_empty() {
+                    match parse_hex(args).and_then(|addr| u16::try_from(addr).ok()) {
+                        Some(addr) => self.debugger.set_register(Register::Pc, addr),
+                        None => return Ok("E01".into()),
+                    }
+                }
+                self.resume(command == "s", connection)?
+            }
+            "H" => "OK".into(),
+            "q" => query(args),
+            _ => String::new(),
+        };
+        Ok(reply)
+    }
+
+    fn encode_register(&self, register: Register) -> String {
+        let value = self.debugger.register(register);
+        match width(register) {
+            2 => format!("{value:04x}"),
+            _ => format!("{value:02x}"),
+        }
+    }
+
+    fn write_registers(&mut self, args: &str) -> String {
+        let Some(bytes) = decode_hex(args) else {
+            return "E01".into();
+        };
+        let needed: usize = Register::ALL.iter().map(|&register| width(register)).sum();
+        if bytes.len() < needed {
+            return "E01".into();
+        }
+        let mut bytes = bytes.iter();
+        for register in Register::ALL {
+            let value = bytes
+                .by_ref()
+                .take(width(register))
+                .fold(0, |value, &byte| value << 8 | byte as u16);
+            self.debugger.set_register(register, value);
+        }
+        "OK".into()
+    }
+
+    fn write_register(&mut self, args: &str) -> String {
+        let parsed = args.split_once('=').and_then(|(index, value)| {
+            let register = *Register::ALL.get(parse_hex(index)?)?;
+            Some((register, u16::try_from(parse_hex(value)?).ok()?))
+        });
+        match parsed {
+            Some((register, value)) => {
+                self.debugger.set_register(register, value);
+                "OK".into()
+            }
+            None => "E01".into(),
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> String {
+        match parse_range(args) {
+            Some((addr, len)) => {
+                let bytes = self.debugger.read_memory(addr, len);
+                if bytes.is_empty() && len > 0 {
+                    return "E01".into();
+                }
+                bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+            }
+            None => "E01".into(),
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> String {
+        let parsed = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_range(range)?;
+            let bytes = decode_hex(data).filter(|bytes| bytes.len() == len)?;
+            Some((addr, bytes))
+        });
+        match parsed {
+            Some((addr, bytes)) if self.debugger.write_memory(addr, &bytes) == bytes.len() => {
+                "OK".into()
+            }
+            _ => "E01".into(),
+        }
+    }
+
+    /// `Z`/`z` packets: type 0 and 1 are breakpoints, 2 to 4 write, read and access
+    /// watchpoints. For watchpoints the kind is the number of bytes watched.
+    fn set_stop_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next())
+        else {
+            return "E01".into();
+        };
+        let Some((addr, len)) = parse_range(&format!("{addr},{len}")) else {
+            return "E01".into();
+        };
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.debugger.add_breakpoint(addr);
+                } else {
+                    self.debugger.remove_breakpoint(addr);
+                }
+                return "OK".into();
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::ReadWrite,
+            _ => return String::new(),
+        };
+        let Some(last) = u16::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_sub(1))
+            .and_then(|len| addr.checked_add(len))
+        else {
+            return "E01".into();
+        };
+        if insert {
+            self.debugger.watch_memory(addr..=last, watch);
+        } else {
+            self.debugger.unwatch_memory(addr..=last, watch);
+        }
+        "OK".into()
+    }
+
+    /// Step or continue, returning the stop reply.
+    fn resume(&mut self, step: bool, connection: &mut Connection) -> io::Result<String> {
+        let result = if step {
+            self.debugger.step_into()
+        } else {
+            loop {
+                match self.debugger.resume(SLICE) {
+                    Ok(Stop::Limit) if connection.interrupted()? => return Ok("S02".into()),
+                    Ok(Stop::Limit) => continue,
+                    result => break result,
+                }
+            }
+        };
+        Ok(stop_reply(result))
+    }
+}
+
+/// The bytes [`GdbStub`] sends for `register`.
+fn width(register: Register) -> usize {
+    match register {
+        Register::I | Register::Pc => 2,
+        _ => 1,
+    }
+}
+
+fn stop_reply(result: Result<Stop, EmulatorError>) -> String {
+    match result {
+        Ok(Stop::Watchpoint { addr, access }) => {
+            let kind = match access {
+                Access::Read => "rwatch",
+                Access::Write => "watch",
+            };
+            format!("T05{kind}:{addr:x};")
+        }
+        Ok(Stop::Halted) => "W00".into(),
+        Ok(_) => "S05".into(),
+        // SIGILL for instructions that cannot run at all, SIGSEGV for bad stack and memory use.
+        Err(EmulatorError::UnknownOpcode { .. } | EmulatorError::MachineCodeRoutine { .. }) => {
+            "S04".into()
+        }
+        Err(_) => "S0b".into(),
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        "PacketSize=1000;QStartNoAckMode+;qXfer:features:read+".into()
+    } else if args == "Attached" {
+        "1".into()
+    } else if args == "C" {
+        "QC1".into()
+    } else if args == "fThreadInfo" {
+        "m1".into()
+    } else if args == "sThreadInfo" {
+        "l".into()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        let xml = target_description();
+        match range
+            .split_once(',')
+            .map(|(offset, len)| (parse_hex(offset), parse_hex(len)))
+        {
+            Some((Some(offset), Some(len))) => {
+                let chunk = xml.get(offset.min(xml.len())..).unwrap_or_default();
+                if chunk.len() > len {
+                    format!("m{}", &chunk[..len])
+                } else {
+                    format!("l{chunk}")
+                }
+            }
+            _ => "E01".into(),
+        }
+    } else {
+        String::new()
+    }
+}
+
+fn target_description() -> String {
+    let registers: String = Register::ALL
+        .iter()
+        .map(|&register| {
+            let name = register.to_string().to_lowercase();
+            let kind = match register {
+                Register::I => "data_ptr",
+                Register::Pc => "code_ptr",
+                _ => "int",
+            };
+            let bits = width(register) * 8;
+            format!("    <reg name=\"{name}\" bitsize=\"{bits}\" type=\"{kind}\"/>\n")
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+         <target version=\"1.0\">\n  <feature name=\"org.capp8.chip8\">\n{registers}  \
+         </feature>\n</target>\n"
+    )
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+/// An `addr,length` pair.
+fn parse_range(text: &str) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((u16::try_from(parse_hex(addr)?).ok()?, parse_hex(len)?))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, u8::wrapping_add)
+}
+
+/// Packet framing and acknowledgements over the front-end's connection.
+struct Connection {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// Whether packets are acknowledged, until the front-end turns that off.
+    acks: bool,
+}
+
+impl Connection {
+    /// The next packet with a good checksum, or `None` once the front-end disconnects.
+    /// Stray acknowledgements and interrupts are skipped.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut skipped = Vec::new();
+            if self.reader.read_until(b'$', &mut skipped)? == 0 || skipped.last() != Some(&b'$') {
+                return Ok(None);
+            }
+            let mut data = Vec::new();
+            self.reader.read_until(b'#', &mut data)?;
+            let mut sum = [0; 2];
+            if data.pop() != Some(b'#') || self.reader.read_exact(&mut sum).is_err() {
+                return Ok(None);
+            }
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let expected = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+            if !self.acks {
+                return Ok(Some(data));
+            }
+            if expected == Some(checksum(&data)) {
+                self.writer.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            self.writer.write_all(b"-")?;
+        }
+    }
+
+    /// Send `data` as a packet, resending until the front-end acknowledges it.
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum(data));
+        loop {
+            self.writer.write_all(packet.as_bytes())?;
+            if !self.acks {
+                return Ok(());
+            }
+            let mut ack = [0];
+            loop {
+                self.reader.read_exact(&mut ack)?;
+                match ack[0] {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    // Anything else, such as a late interrupt, is not an answer.
+                    _ => continue,
+                }
+            }
+        }
+    }
+
+    /// Whether the front-end has sent an interrupt (or hung up) since the last check,
+    /// without waiting for one.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.reader.get_ref().set_nonblocking(true)?;
+        let pending = self.reader.fill_buf().map(|buffer| buffer.first().copied());
+        self.reader.get_ref().set_nonblocking(false)?;
+        match pending {
+            Ok(Some(0x03)) => {
+                self.reader.consume(1);
+                Ok(true)
+            }
+            Ok(Some(_)) => Ok(false),
+            Ok(None) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+    use crate::emulator::Emulator;
+
+    // 200: 6001  V0 := 1
+    // 202: A300  I := 300
+    // 204: F055  store V0 at 300
+    // 206: 7001  V0 += 1
+    // 208: 1202  loop
+    const ROM: [u8; 10] = [0x60, 0x01, 0xA3, 0x00, 0xF0, 0x55, 0x70, 0x01, 0x12, 0x02];
+
+    /// Serve a stub for `rom` on a local port, returning a connected client.
+    fn connect(rom: &[u8]) -> (TcpStream, thread::JoinHandle<Debugger>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let emulator = Emulator::from_bytes(rom).unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut stub = GdbStub::new(Debugger::new(emulator));
+            stub.serve(stream).unwrap();
+            stub.into_inner()
+        });
+        (TcpStream::connect(addr).unwrap(), server)
+    }
+
+    fn exchange(client: &mut TcpStream, packet: &str) -> String {
+        write!(client, "${packet}#{:02x}", checksum(packet)).unwrap();
+        let mut reply = Vec::new();
+        let mut byte = [0];
+        client.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'+', "{packet} was not acknowledged");
+        while byte[0] != b'#' {
+            client.read_exact(&mut byte).unwrap();
+            reply.push(byte[0]);
+        }
+        let mut sum = [0; 2];
+        client.read_exact(&mut sum).unwrap();
+        client.write_all(b"+").unwrap();
+        let reply = String::from_utf8(reply).unwrap();
+        reply[1..reply.len() - 1].to_string()
+    }
+
+    #[test]
+    fn scripted_session() {
+        let (mut client, server) = connect(&ROM);
+        assert!(exchange(&mut client, "qSupported:swbreak+").contains("qXfer"));
+        assert_eq!(exchange(&mut client, "?"), "S05");
+        let xml = exchange(&mut client, "qXfer:features:read:target.xml:0,ffff");
+        assert!(xml.starts_with("l<?xml") && xml.contains("name=\"pc\" bitsize=\"16\""));
+
+        // V0-VF, then I, PC, SP, DT, ST.
+        let registers = exchange(&mut client, "g");
+        assert_eq!(registers, format!("{}00000200000000", "00".repeat(16)));
+        assert_eq!(exchange(&mut client, "s"), "S05");
+        assert_eq!(exchange(&mut client, "p0"), "01");
+        assert_eq!(exchange(&mut client, "p11"), "0202");
+
+        assert_eq!(exchange(&mut client, "Z0,206,2"), "OK");
+        assert_eq!(exchange(&mut client, "c"), "S05");
+        assert_eq!(exchange(&mut client, "p11"), "0206");
+        assert_eq!(exchange(&mut client, "m300,2"), "0100");
+        assert_eq!(exchange(&mut client, "z0,206,2"), "OK");
+
+        assert_eq!(exchange(&mut client, "Z2,300,1"), "OK");
+        assert_eq!(exchange(&mut client, "c"), "T05watch:300;");
+        assert_eq!(exchange(&mut client, "m300,1"), "02");
+        assert_eq!(exchange(&mut client, "M300,2:abcd"), "OK");
+        assert_eq!(exchange(&mut client, "P5=7f"), "OK");
+        assert_eq!(exchange(&mut client, "m10000,1"), "E01");
+        assert_eq!(exchange(&mut client, "mfffe,ffffffffffffffff"), "0000");
+        assert_eq!(exchange(&mut client, "Z2,ffff,1"), "OK");
+        assert_eq!(exchange(&mut client, "z2,ffff,1"), "OK");
+        assert_eq!(exchange(&mut client, "Z2,ffff,2"), "E01");
+        assert_eq!(exchange(&mut client, "Z2,300,0"), "E01");
+        assert_eq!(exchange(&mut client, "D"), "OK");
+
+        let debugger = server.join().unwrap();
+        assert_eq!(debugger.read_memory(0x300, 2), [0xAB, 0xCD]);
+        assert_eq!(debugger.register(Register::V(5)), 0x7F);
+    }
+
+    #[test]
+    fn interrupts_a_running_program() {
+        let (mut client, server) = connect(&[0x12, 0x00]);
+        write!(client, "$c#{:02x}", checksum("c")).unwrap();
+        let mut ack = [0];
+        client.read_exact(&mut ack).unwrap();
+        client.write_all(&[0x03]).unwrap();
+        let mut reply = [0; 7];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(&reply, b"$S02#b5");
+        client.write_all(b"+").unwrap();
+        write!(client, "$k#{:02x}", checksum("k")).unwrap();
+        server.join().unwrap();
+    }
+}
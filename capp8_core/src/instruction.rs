@@ -1,6 +1,11 @@
-/// The full set of CHIP-8 instruction.
+use std::fmt;
+
+use crate::opcode::Opcode;
+
+/// The full set of CHIP-8 instruction, plus the SUPER-CHIP 1.1 and XO-CHIP extensions.
 ///
 ///Taken from [here](http://devernay.free.fr/hacks/chip8/C8TECH10.HTM#0.0).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     /// 0nnn - SYS addr  
     /// Call a machine code routine at address `addr`. (Ignored on most modern interpreters.)
@@ -14,6 +19,34 @@ pub enum Instruction {
     /// Return from a subroutine.
     Return,
 
+    /// 00Cn - SCD nibble  
+    /// Scroll the display down by `rows` pixels. (SUPER-CHIP)
+    ScrollDown { rows: u8 },
+
+    /// 00Dn - SCU nibble  
+    /// Scroll the selected planes up by `rows` pixels. (XO-CHIP)
+    ScrollUp { rows: u8 },
+
+    /// 00FB - SCR  
+    /// Scroll the display right by 4 pixels. (SUPER-CHIP)
+    ScrollRight,
+
+    /// 00FC - SCL  
+    /// Scroll the display left by 4 pixels. (SUPER-CHIP)
+    ScrollLeft,
+
+    /// 00FD - EXIT  
+    /// Stop the interpreter. (SUPER-CHIP)
+    Exit,
+
+    /// 00FE - LOW  
+    /// Switch to the 64x32 low resolution mode. (SUPER-CHIP)
+    LowRes,
+
+    /// 00FF - HIGH  
+    /// Switch to the 128x64 high resolution mode. (SUPER-CHIP)
+    HighRes,
+
     /// 1nnn - JP addr  
     /// Jump to address `addr`.
     Jump { addr: u16 },
@@ -34,6 +67,16 @@ pub enum Instruction {
     /// Skip the next instruction if Vx == Vy.
     SkipRegEqReg { reg_x: usize, reg_y: usize },
 
+    /// 5xy2 - SAVE Vx - Vy  
+    /// Store registers Vx through Vy in memory starting at address I, without changing I.
+    /// The range is walked backwards when x > y. (XO-CHIP)
+    StoreRegisterRange { reg_x: usize, reg_y: usize },
+
+    /// 5xy3 - LOAD Vx - Vy  
+    /// Read registers Vx through Vy from memory starting at address I, without changing I.
+    /// The range is walked backwards when x > y. (XO-CHIP)
+    LoadRegisterRange { reg_x: usize, reg_y: usize },
+
     /// 6xkk - LD Vx, byte  
     /// Set Vx = `imm`.
     StoreRegFromImm { reg: usize, imm: u8 },
@@ -71,12 +114,14 @@ pub enum Instruction {
     SubnRegReg { reg_x: usize, reg_y: usize },
 
     /// 8xy6 - SHR Vx {, Vy}  
-    /// Set Vx = Vx >> 1. Store LSB in VF.
-    ShiftRight { reg: usize },
+    /// Set Vx = Vy >> 1. Store LSB in VF.
+    /// With the `shift` quirk, Vx is shifted in place and Vy is ignored.
+    ShiftRight { reg_x: usize, reg_y: usize },
 
     /// 8xyE - SHL Vx {, Vy}  
-    /// Set Vx = Vx << 1. Store MSB in VF.
-    ShiftLeft { reg: usize },
+    /// Set Vx = Vy << 1. Store MSB in VF.
+    /// With the `shift` quirk, Vx is shifted in place and Vy is ignored.
+    ShiftLeft { reg_x: usize, reg_y: usize },
 
     /// 9xy0 - SNE Vx, Vy  
     /// Skip the next instruction if Vx != Vy.
@@ -88,6 +133,7 @@ pub enum Instruction {
 
     /// Bnnn - JP V0, addr  
     /// Jump to address `addr + V0`.
+    /// With the `jump_with_vx` quirk, jump to `addr + Vx`, where x is the high nibble of `addr`.
     JumpWithOffset { addr: u16 },
 
     /// Cxkk - RND Vx, byte  
@@ -96,12 +142,25 @@ pub enum Instruction {
 
     /// Dxyn - DRW Vx, Vy, nibble  
     /// Display n-byte sprite starting at memory\[I\] at (Vx, Vy). Set VF = collision.
+    /// When `nibble` is 0, draw a 16x16 sprite made of 32 bytes instead. (SUPER-CHIP)
     Draw {
         reg_x: usize,
         reg_y: usize,
         nibble: u8,
     },
 
+    /// F000 nnnn - LD I, long nnnn  
+    /// Set I = the 16 bit `addr` stored in the following word. (XO-CHIP)
+    LongSetI { addr: u16 },
+
+    /// Fn01 - PLANE n  
+    /// Select the bitplanes affected by drawing, clearing and scrolling. (XO-CHIP)
+    SelectPlanes { planes: u8 },
+
+    /// F002 - AUDIO  
+    /// Load the 16 byte audio pattern buffer from memory starting at address I. (XO-CHIP)
+    LoadAudioPattern,
+
     /// Ex9E - SKP Vx  
     /// Skip the next instruction if key with the value of Vx is pressed.
     SkipIfKey { reg: usize },
@@ -134,6 +193,14 @@ pub enum Instruction {
     /// Set I = location of sprite for digit Vx.
     SetIToSprite { reg: usize },
 
+    /// Fx30 - LD HF, Vx  
+    /// Set I = location of the large 8x10 sprite for digit Vx. (SUPER-CHIP)
+    SetIToBigSprite { reg: usize },
+
+    /// Fx3A - PITCH Vx  
+    /// Set the playback rate of the audio pattern buffer to Vx. (XO-CHIP)
+    SetPitch { reg: usize },
+
     /// Fx33 - LD B, Vx  
     /// Store BCD representation of Vx in memory at I, I+1, I+2.
     StoreBCD { reg: usize },
@@ -145,4 +212,431 @@ pub enum Instruction {
     /// Fx65 - LD Vx, \[I\]  
     /// Read registers V0 through Vx from memory starting at address I.
     LoadRegisters { reg: usize },
+
+    /// Fx75 - LD R, Vx  
+    /// Store registers V0 through Vx in the RPL user flags. (SUPER-CHIP)
+    StoreFlags { reg: usize },
+
+    /// Fx85 - LD Vx, R  
+    /// Read registers V0 through Vx from the RPL user flags. (SUPER-CHIP)
+    LoadFlags { reg: usize },
+}
+
+impl Instruction {
+    /// Decode a 16 bit opcode. `next_word` is the word that follows it in memory, which is
+    /// only read by F000 nnnn. Returns `None` for opcodes that are not instructions.
+    pub fn decode(opcode: u16, next_word: u16) -> Option<Self> {
+        let opcode = Opcode::from(opcode);
+        let instruction = match opcode.nibbles() {
+            (0, 0, 0xE, 0) => Self::ClearScreen,
+
+            (0, 0, 0xE, 0xE) => Self::Return,
+
+            (0, 0, 0xC, rows) => Self::ScrollDown { rows },
+            (0, 0, 0xD, rows) => Self::ScrollUp { rows },
+            (0, 0, 0xF, 0xB) => Self::ScrollRight,
+            (0, 0, 0xF, 0xC) => Self::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Self::Exit,
+            (0, 0, 0xF, 0xE) => Self::LowRes,
+            (0, 0, 0xF, 0xF) => Self::HighRes,
+
+            (0, _, _, _) => Self::Sys {
+                addr: opcode.addr(),
+            },
+
+            (1, _, _, _) => Self::Jump {
+                addr: opcode.addr(),
+            },
+            (2, _, _, _) => Self::Call {
+                addr: opcode.addr(),
+            },
+            (3, _, _, _) => Self::SkipRegEqImm {
+                reg: opcode.x(),
+                imm: opcode.byte(),
+            },
+
+            (4, _, _, _) => Self::SkipRegNeqImm {
+                reg: opcode.x(),
+                imm: opcode.byte(),
+            },
+            (5, _, _, 0) => Self::SkipRegEqReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (5, _, _, 2) => Self::StoreRegisterRange {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (5, _, _, 3) => Self::LoadRegisterRange {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (6, _, _, _) => Self::StoreRegFromImm {
+                reg: opcode.x(),
+                imm: opcode.byte(),
+            },
+            (7, _, _, _) => Self::AddRegImm {
+                reg: opcode.x(),
+                imm: opcode.byte(),
+            },
+            (8, _, _, 0) => Self::StoreRegFromReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 1) => Self::OrRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 2) => Self::AndRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 3) => Self::XorRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 4) => Self::AddRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 5) => Self::SubRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 6) => Self::ShiftRight {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 7) => Self::SubnRegReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (8, _, _, 0xE) => Self::ShiftLeft {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (9, _, _, 0) => Self::SkipRegNeqReg {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+            },
+            (0xA, _, _, _) => Self::SetI {
+                addr: opcode.addr(),
+            },
+            (0xB, _, _, _) => Self::JumpWithOffset {
+                addr: opcode.addr(),
+            },
+            (0xC, _, _, _) => Self::Rand {
+                reg: opcode.x(),
+                mask: opcode.byte(),
+            },
+            (0xD, _, _, _) => Self::Draw {
+                reg_x: opcode.x(),
+                reg_y: opcode.y(),
+                nibble: opcode.nibble(),
+            },
+            (0xE, _, 9, 0xE) => Self::SkipIfKey { reg: opcode.x() },
+            (0xE, _, 0xA, 1) => Self::SkipIfNotKey { reg: opcode.x() },
+            (0xF, 0, 0, 0) => Self::LongSetI { addr: next_word },
+            (0xF, planes, 0, 1) => Self::SelectPlanes { planes },
+            (0xF, 0, 0, 2) => Self::LoadAudioPattern,
+            (0xF, _, 0, 7) => Self::LoadDelayTimer { reg: opcode.x() },
+            (0xF, _, 0, 0xA) => Self::WaitKeyPress { reg: opcode.x() },
+            (0xF, _, 1, 5) => Self::SetDelayTimer { reg: opcode.x() },
+            (0xF, _, 1, 8) => Self::SetSoundTimer { reg: opcode.x() },
+            (0xF, _, 1, 0xE) => Self::AddI { reg: opcode.x() },
+            (0xF, _, 2, 9) => Self::SetIToSprite { reg: opcode.x() },
+            (0xF, _, 3, 0) => Self::SetIToBigSprite { reg: opcode.x() },
+            (0xF, _, 3, 3) => Self::StoreBCD { reg: opcode.x() },
+            (0xF, _, 3, 0xA) => Self::SetPitch { reg: opcode.x() },
+            (0xF, _, 5, 5) => Self::StoreRegisters { reg: opcode.x() },
+            (0xF, _, 6, 5) => Self::LoadRegisters { reg: opcode.x() },
+            (0xF, _, 7, 5) => Self::StoreFlags { reg: opcode.x() },
+            (0xF, _, 8, 5) => Self::LoadFlags { reg: opcode.x() },
+
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Encode back into the opcode `decode` reads. For F000 nnnn only the first word is
+    /// returned; the address goes in the word after it.
+    pub fn encode(&self) -> u16 {
+        let xy = |x: usize, y: usize, n: u16| ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | n;
+        let xkk = |x: usize, kk: u8| ((x as u16 & 0xF) << 8) | kk as u16;
+        let fx = |x: usize, kk: u16| 0xF000 | ((x as u16 & 0xF) << 8) | kk;
+        match *self {
+            Self::Sys { addr } => addr & 0xFFF,
+            Self::ClearScreen => 0x00E0,
+            Self::Return => 0x00EE,
+            Self::ScrollDown { rows } => 0x00C0 | (rows as u16 & 0xF),
+            Self::ScrollUp { rows } => 0x00D0 | (rows as u16 & 0xF),
+            Self::ScrollRight => 0x00FB,
+            Self::ScrollLeft => 0x00FC,
+            Self::Exit => 0x00FD,
+            Self::LowRes => 0x00FE,
+            Self::HighRes => 0x00FF,
+            Self::Jump { addr } => 0x1000 | (addr & 0xFFF),
+            Self::Call { addr } => 0x2000 | (addr & 0xFFF),
+            Self::SkipRegEqImm { reg, imm } => 0x3000 | xkk(reg, imm),
+            Self::SkipRegNeqImm { reg, imm } => 0x4000 | xkk(reg, imm),
+            Self::SkipRegEqReg { reg_x, reg_y } => 0x5000 | xy(reg_x, reg_y, 0),
+            Self::StoreRegisterRange { reg_x, reg_y } => 0x5000 | xy(reg_x, reg_y, 2),
+            Self::LoadRegisterRange { reg_x, reg_y } => 0x5000 | xy(reg_x, reg_y, 3),
+            Self::StoreRegFromImm { reg, imm } => 0x6000 | xkk(reg, imm),
+            Self::AddRegImm { reg, imm } => 0x7000 | xkk(reg, imm),
+            Self::StoreRegFromReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 0),
+            Self::OrRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 1),
+            Self::AndRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 2),
+            Self::XorRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 3),
+            Self::AddRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 4),
+            Self::SubRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 5),
+            Self::ShiftRight { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 6),
+            Self::SubnRegReg { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 7),
+            Self::ShiftLeft { reg_x, reg_y } => 0x8000 | xy(reg_x, reg_y, 0xE),
+            Self::SkipRegNeqReg { reg_x, reg_y } => 0x9000 | xy(reg_x, reg_y, 0),
+            Self::SetI { addr } => 0xA000 | (addr & 0xFFF),
+            Self::JumpWithOffset { addr } => 0xB000 | (addr & 0xFFF),
+            Self::Rand { reg, mask } => 0xC000 | xkk(reg, mask),
+            Self::Draw {
+                reg_x,
+                reg_y,
+                nibble,
+            } => 0xD000 | xy(reg_x, reg_y, nibble as u16 & 0xF),
+            Self::SkipIfKey { reg } => 0xE09E | xkk(reg, 0),
+            Self::SkipIfNotKey { reg } => 0xE0A1 | xkk(reg, 0),
+            Self::LongSetI { .. } => 0xF000,
+            Self::SelectPlanes { planes } => fx(planes as usize, 0x01),
+            Self::LoadAudioPattern => 0xF002,
+            Self::LoadDelayTimer { reg } => fx(reg, 0x07),
+            Self::WaitKeyPress { reg } => fx(reg, 0x0A),
+            Self::SetDelayTimer { reg } => fx(reg, 0x15),
+            Self::SetSoundTimer { reg } => fx(reg, 0x18),
+            Self::AddI { reg } => fx(reg, 0x1E),
+            Self::SetIToSprite { reg } => fx(reg, 0x29),
+            Self::SetIToBigSprite { reg } => fx(reg, 0x30),
+            Self::StoreBCD { reg } => fx(reg, 0x33),
+            Self::SetPitch { reg } => fx(reg, 0x3A),
+            Self::StoreRegisters { reg } => fx(reg, 0x55),
+            Self::LoadRegisters { reg } => fx(reg, 0x65),
+            Self::StoreFlags { reg } => fx(reg, 0x75),
+            Self::LoadFlags { reg } => fx(reg, 0x85),
+        }
+    }
+
+    /// How many bytes the instruction takes up in memory.
+    pub fn size(&self) -> u16 {
+        match self {
+            Self::LongSetI { .. } => 4,
+            _ => 2,
+        }
+    }
+
+    /// The address the instruction refers to, if any: a jump or call target, or the
+    /// address loaded into I.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Self::Sys { addr }
+            | Self::Jump { addr }
+            | Self::Call { addr }
+            | Self::SetI { addr }
+            | Self::JumpWithOffset { addr }
+            | Self::LongSetI { addr } => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// Render the instruction in the given assembly syntax, with addresses in hex.
+    pub fn mnemonic(&self, syntax: Syntax) -> String {
+        self.render(syntax, &|addr| format!("{addr:#05X}"))
+    }
+
+    /// Render the instruction, spelling the address it refers to with `addr`.
+    pub(crate) fn render(&self, syntax: Syntax, addr: &dyn Fn(u16) -> String) -> String {
+        match syntax {
+            Syntax::Cowgod => self.render_cowgod(addr),
+            Syntax::Octo => self.render_octo(addr),
+        }
+    }
+
+    fn render_cowgod(&self, addr: &dyn Fn(u16) -> String) -> String {
+        let v = |reg: usize| format!("V{reg:X}");
+        match *self {
+            Self::Sys { addr: a } => format!("SYS {}", addr(a)),
+            Self::ClearScreen => "CLS".to_string(),
+            Self::Return => "RET".to_string(),
+            Self::ScrollDown { rows } => format!("SCD {rows}"),
+            Self::ScrollUp { rows } => format!("SCU {rows}"),
+            Self::ScrollRight => "SCR".to_string(),
+            Self::ScrollLeft => "SCL".to_string(),
+            Self::Exit => "EXIT".to_string(),
+            Self::LowRes => "LOW".to_string(),
+            Self::HighRes => "HIGH".to_string(),
+            Self::Jump { addr: a } => format!("JP {}", addr(a)),
+            Self::Call { addr: a } => format!("CALL {}", addr(a)),
+            Self::SkipRegEqImm { reg, imm } => format!("SE {}, {imm:#04X}", v(reg)),
+            Self::SkipRegNeqImm { reg, imm } => format!("SNE {}, {imm:#04X}", v(reg)),
+            Self::SkipRegEqReg { reg_x, reg_y } => format!("SE {}, {}", v(reg_x), v(reg_y)),
+            Self::StoreRegisterRange { reg_x, reg_y } => {
+                format!("SAVE {} - {}", v(reg_x), v(reg_y))
+            }
+            Self::LoadRegisterRange { reg_x, reg_y } => {
+                format!("LOAD {} - {}", v(reg_x), v(reg_y))
+            }
+            Self::StoreRegFromImm { reg, imm } => format!("LD {}, {imm:#04X}", v(reg)),
+            Self::AddRegImm { reg, imm } => format!("ADD {}, {imm:#04X}", v(reg)),
+            Self::StoreRegFromReg { reg_x, reg_y } => format!("LD {}, {}", v(reg_x), v(reg_y)),
+            Self::OrRegReg { reg_x, reg_y } => format!("OR {}, {}", v(reg_x), v(reg_y)),
+            Self::AndRegReg { reg_x, reg_y } => format!("AND {}, {}", v(reg_x), v(reg_y)),
+            Self::XorRegReg { reg_x, reg_y } => format!("XOR {}, {}", v(reg_x), v(reg_y)),
+            Self::AddRegReg { reg_x, reg_y } => format!("ADD {}, {}", v(reg_x), v(reg_y)),
+            Self::SubRegReg { reg_x, reg_y } => format!("SUB {}, {}", v(reg_x), v(reg_y)),
+            Self::ShiftRight { reg_x, reg_y } => format!("SHR {}, {}", v(reg_x), v(reg_y)),
+            Self::SubnRegReg { reg_x, reg_y } => format!("SUBN {}, {}", v(reg_x), v(reg_y)),
+            Self::ShiftLeft { reg_x, reg_y } => format!("SHL {}, {}", v(reg_x), v(reg_y)),
+            Self::SkipRegNeqReg { reg_x, reg_y } => format!("SNE {}, {}", v(reg_x), v(reg_y)),
+            Self::SetI { addr: a } => format!("LD I, {}", addr(a)),
+            Self::JumpWithOffset { addr: a } => format!("JP V0, {}", addr(a)),
+            Self::Rand { reg, mask } => format!("RND {}, {mask:#04X}", v(reg)),
+            Self::Draw {
+                reg_x,
+                reg_y,
+                nibble,
+            } => format!("DRW {}, {}, {nibble}", v(reg_x), v(reg_y)),
+            Self::LongSetI { addr: a } => format!("LD I, long {}", addr(a)),
+            Self::SelectPlanes { planes } => format!("PLANE {planes}"),
+            Self::LoadAudioPattern => "AUDIO".to_string(),
+            Self::SkipIfKey { reg } => format!("SKP {}", v(reg)),
+            Self::SkipIfNotKey { reg } => format!("SKNP {}", v(reg)),
+            Self::LoadDelayTimer { reg } => format!("LD {}, DT", v(reg)),
+            Self::WaitKeyPress { reg } => format!("LD {}, K", v(reg)),
+            Self::SetDelayTimer { reg } => format!("LD DT, {}", v(reg)),
+            Self::SetSoundTimer { reg } => format!("LD ST, {}", v(reg)),
+            Self::AddI { reg } => format!("ADD I, {}", v(reg)),
+            Self::SetIToSprite { reg } => format!("LD F, {}", v(reg)),
+            Self::SetIToBigSprite { reg } => format!("LD HF, {}", v(reg)),
+            Self::SetPitch { reg } => format!("PITCH {}", v(reg)),
+            Self::StoreBCD { reg } => format!("LD B, {}", v(reg)),
+            Self::StoreRegisters { reg } => format!("LD [I], {}", v(reg)),
+            Self::LoadRegisters { reg } => format!("LD {}, [I]", v(reg)),
+            Self::StoreFlags { reg } => format!("LD R, {}", v(reg)),
+            Self::LoadFlags { reg } => format!("LD {}, R", v(reg)),
+        }
+    }
+
+    /// Octo spells skips as the condition under which the next instruction runs, so the
+    /// comparisons come out inverted.
+    fn render_octo(&self, addr: &dyn Fn(u16) -> String) -> String {
+        let v = |reg: usize| format!("v{reg:x}");
+        match *self {
+            // Octo has no mnemonic for machine code calls, so emit the raw bytes.
+            Self::Sys { addr: a } => format!("{:#04x} {:#04x}", a >> 8, a & 0xFF),
+            Self::ClearScreen => "clear".to_string(),
+            Self::Return => "return".to_string(),
+            Self::ScrollDown { rows } => format!("scroll-down {rows}"),
+            Self::ScrollUp { rows } => format!("scroll-up {rows}"),
+            Self::ScrollRight => "scroll-right".to_string(),
+            Self::ScrollLeft => "scroll-left".to_string(),
+            Self::Exit => "exit".to_string(),
+            Self::LowRes => "lores".to_string(),
+            Self::HighRes => "hires".to_string(),
+            Self::Jump { addr: a } => format!("jump {}", addr(a)),
+            Self::Call { addr: a } => format!(":call {}", addr(a)),
+            Self::SkipRegEqImm { reg, imm } => format!("if {} != {imm:#04x} then", v(reg)),
+            Self::SkipRegNeqImm { reg, imm } => format!("if {} == {imm:#04x} then", v(reg)),
+            Self::SkipRegEqReg { reg_x, reg_y } => {
+                format!("if {} != {} then", v(reg_x), v(reg_y))
+            }
+            Self::StoreRegisterRange { reg_x, reg_y } => {
+                format!("save {} - {}", v(reg_x), v(reg_y))
+            }
+            Self::LoadRegisterRange { reg_x, reg_y } => {
+                format!("load {} - {}", v(reg_x), v(reg_y))
+            }
+            Self::StoreRegFromImm { reg, imm } => format!("{} := {imm:#04x}", v(reg)),
+            Self::AddRegImm { reg, imm } => format!("{} += {imm:#04x}", v(reg)),
+            Self::StoreRegFromReg { reg_x, reg_y } => format!("{} := {}", v(reg_x), v(reg_y)),
+            Self::OrRegReg { reg_x, reg_y } => format!("{} |= {}", v(reg_x), v(reg_y)),
+            Self::AndRegReg { reg_x, reg_y } => format!("{} &= {}", v(reg_x), v(reg_y)),
+            Self::XorRegReg { reg_x, reg_y } => format!("{} ^= {}", v(reg_x), v(reg_y)),
+            Self::AddRegReg { reg_x, reg_y } => format!("{} += {}", v(reg_x), v(reg_y)),
+            Self::SubRegReg { reg_x, reg_y } => format!("{} -= {}", v(reg_x), v(reg_y)),
+            Self::ShiftRight { reg_x, reg_y } => format!("{} >>= {}", v(reg_x), v(reg_y)),
+            Self::SubnRegReg { reg_x, reg_y } => format!("{} =- {}", v(reg_x), v(reg_y)),
+            Self::ShiftLeft { reg_x, reg_y } => format!("{} <<= {}", v(reg_x), v(reg_y)),
+            Self::SkipRegNeqReg { reg_x, reg_y } => {
+                format!("if {} == {} then", v(reg_x), v(reg_y))
+            }
+            Self::SetI { addr: a } => format!("i := {}", addr(a)),
+            Self::JumpWithOffset { addr: a } => format!("jump0 {}", addr(a)),
+            Self::Rand { reg, mask } => format!("{} := random {mask:#04x}", v(reg)),
+            Self::Draw {
+                reg_x,
+                reg_y,
+                nibble,
+            } => format!("sprite {} {} {nibble}", v(reg_x), v(reg_y)),
+            Self::LongSetI { addr: a } => format!("i := long {}", addr(a)),
+            Self::SelectPlanes { planes } => format!("plane {planes}"),
+            Self::LoadAudioPattern => "audio".to_string(),
+            Self::SkipIfKey { reg } => format!("if {} -key then", v(reg)),
+            Self::SkipIfNotKey { reg } => format!("if {} key then", v(reg)),
+            Self::LoadDelayTimer { reg } => format!("{} := delay", v(reg)),
+            Self::WaitKeyPress { reg } => format!("{} := key", v(reg)),
+            Self::SetDelayTimer { reg } => format!("delay := {}", v(reg)),
+            Self::SetSoundTimer { reg } => format!("buzzer := {}", v(reg)),
+            Self::AddI { reg } => format!("i += {}", v(reg)),
+            Self::SetIToSprite { reg } => format!("i := hex {}", v(reg)),
+            Self::SetIToBigSprite { reg } => format!("i := bighex {}", v(reg)),
+            Self::SetPitch { reg } => format!("pitch := {}", v(reg)),
+            Self::StoreBCD { reg } => format!("bcd {}", v(reg)),
+            Self::StoreRegisters { reg } => format!("save {}", v(reg)),
+            Self::LoadRegisters { reg } => format!("load {}", v(reg)),
+            Self::StoreFlags { reg } => format!("saveflags {}", v(reg)),
+            Self::LoadFlags { reg } => format!("loadflags {}", v(reg)),
+        }
+    }
+}
+
+/// Renders the instruction in Cowgod's syntax, as in the variant docs.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.mnemonic(Syntax::Cowgod))
+    }
+}
+
+/// The assembly syntaxes instructions can be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// The uppercase mnemonics of Cowgod's technical reference, e.g. `LD V0, 0x05`.
+    #[default]
+    Cowgod,
+    /// The syntax of John Earnest's Octo assembler, e.g. `v0 := 0x05`.
+    Octo,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_round_trips() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(opcode, 0x1234) {
+                assert_eq!(instruction.encode(), opcode, "{instruction}");
+            }
+        }
+        assert_eq!(
+            Instruction::decode(0xF000, 0x1234),
+            Some(Instruction::LongSetI { addr: 0x1234 })
+        );
+    }
+
+    #[test]
+    fn renders_both_syntaxes() {
+        let draw = Instruction::decode(0xD125, 0).unwrap();
+        assert_eq!(draw.to_string(), "DRW V1, V2, 5");
+        assert_eq!(draw.mnemonic(Syntax::Octo), "sprite v1 v2 5");
+        let skip = Instruction::decode(0x3A0F, 0).unwrap();
+        assert_eq!(skip.to_string(), "SE VA, 0x0F");
+        assert_eq!(skip.mnemonic(Syntax::Octo), "if va != 0x0f then");
+    }
 }
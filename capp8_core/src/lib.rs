@@ -1,6 +1,19 @@
+pub mod audio;
+pub mod debugger;
+pub mod disasm;
 pub mod display;
 pub mod emulator;
+pub mod error;
 pub mod fonts;
 pub mod frontend;
+pub mod gdb;
 pub mod instruction;
+pub mod movie;
 pub mod opcode;
+pub mod palette;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod snapshot;
+pub mod timer;
+pub mod trace;
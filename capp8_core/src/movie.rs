@@ -0,0 +1,324 @@
+//! Input movies: per-frame keypad recordings that replay deterministically.
+//!
+//! A movie stores everything needed to reproduce a run besides the ROM itself: the ROM
+//! hash, the seed of the random source, the quirk profile, and for every frame the
+//! keypad state and the number of instructions run. Every [`CHECKPOINT_INTERVAL`] frames the
+//! recorder also stores a hash of the whole emulator state, which playback compares
+//! against to detect desyncs as soon as they happen.
+//!
+//! A frame is one call to [`Emulator::run_frame`]. The file layout, all little endian, is:
+//!
+//! ```text
+//! "C8MV" version:u16 rom_hash:[u8; 20] seed:u64 quirks:u8
+//! frame_count:u32 (keypad:u16 instructions:u32) * frame_count
+//! checkpoint_count:u32 (frame:u32 state_hash:u64) * checkpoint_count
+//! ```
+
+use std::{fs, path::Path};
+
+use sha1::{Digest, Sha1};
+
+use crate::{
+    emulator::{Emulator, EmulatorBuilder},
+    error::{EmulatorError, MovieError},
+    quirks::Quirks,
+};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+/// Version 1 movies stored the time each frame took, back when a frame was one
+/// instruction, and cannot be played back anymore.
+pub const VERSION: u16 = 2;
+/// How many frames pass between two state hashes.
+pub const CHECKPOINT_INTERVAL: usize = 60;
+
+/// The input of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MovieFrame {
+    pub keypad: [bool; 16],
+    /// The most instructions the frame ran, the speed at the time.
+    pub instructions: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    pub seed: u64,
+    pub quirks: Quirks,
+    pub frames: Vec<MovieFrame>,
+    /// `(frame, state hash)` pairs, the hash being taken after the frame ran.
+    pub checkpoints: Vec<(usize, u64)>,
+}
+
+/// A hash of the whole emulator state, as captured by [`Emulator::save_state`].
+pub fn state_hash(emulator: &Emulator) -> u64 {
+    let digest = Sha1::digest(emulator.save_state());
+    u64::from_le_bytes(digest[..8].try_into().expect("SHA-1 is 20 bytes long"))
+}
+
+impl Movie {
+    /// An empty movie for a run starting from the current state of `emulator`.
+    pub fn new(emulator: &Emulator) -> Self {
+        Self {
+            rom_hash: emulator.rom_hash(),
+            seed: emulator.seed(),
+            quirks: emulator.quirks(),
+            frames: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    /// Apply the seed and quirks the movie was recorded with.
+    pub fn configure(&self, builder: EmulatorBuilder) -> EmulatorBuilder {
+        builder.seed(self.seed).quirks(self.quirks)
+    }
+
+    /// Check that `emulator` runs the ROM the movie was recorded with, the same way.
+    pub fn check(&self, emulator: &Emulator) -> Result<(), MovieError> {
+        if emulator.rom_hash() != self.rom_hash {
+            Err(MovieError::RomMismatch)
+        } else if emulator.quirks() != self.quirks {
+            Err(MovieError::QuirksMismatch)
+        } else if emulator.seed() != self.seed {
+            Err(MovieError::SeedMismatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_hash);
+        out.extend_from_slice(&self.seed.to_le_bytes());
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            let keypad = frame
+                .keypad
+                .iter()
+                .enumerate()
+                .fold(0u16, |bits, (key, &down)| bits | ((down as u16) << key));
+            out.extend_from_slice(&keypad.to_le_bytes());
+            out.extend_from_slice(&frame.instructions.to_le_bytes());
+        }
+        out.extend_from_slice(&(self.checkpoints.len() as u32).to_le_bytes());
+        for &(frame, hash) in &self.checkpoints {
+            out.extend_from_slice(&(frame as u32).to_le_bytes());
+            out.extend_from_slice(&hash.to_le_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieError> {
+        let mut reader = Reader(bytes.strip_prefix(MAGIC).ok_or(MovieError::NotAMovie)?);
+        let version = u16::from_le_bytes(reader.take()?);
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = reader.take()?;
+        let seed = u64::from_le_bytes(reader.take()?);
+        let quirks = Quirks::from_bits(reader.take::<1>()?[0]);
+
+        let frame_count = u32::from_le_bytes(reader.take()?);
+        let mut frames = Vec::new();
+        for _ in 0..frame_count {
+            let keypad = u16::from_le_bytes(reader.take()?);
+            let instructions = u32::from_le_bytes(reader.take()?);
+            frames.push(MovieFrame {
+                keypad: std::array::from_fn(|key| keypad & (1 << key) != 0),
+                instructions,
+            });
+        }
+
+        let checkpoint_count = u32::from_le_bytes(reader.take()?);
+        let mut checkpoints = Vec::new();
+        for _ in 0..checkpoint_count {
+            let frame = u32::from_le_bytes(reader.take()?) as usize;
+            checkpoints.push((frame, u64::from_le_bytes(reader.take()?)));
+        }
+
+        Ok(Self {
+            rom_hash,
+            seed,
+            quirks,
+            frames,
+            checkpoints,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MovieError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], MovieError> {
+        let (head, rest) = self.0.split_at_checked(N).ok_or(MovieError::Truncated)?;
+        self.0 = rest;
+        Ok(head.try_into().expect("split at N"))
+    }
+}
+
+/// Drives an emulator while recording its input into a [`Movie`].
+pub struct MovieRecorder {
+    movie: Movie,
+}
+
+impl MovieRecorder {
+    /// Start recording from the current state of `emulator`, which should be freshly built
+    /// for the movie to be replayable.
+    pub fn new(emulator: &Emulator) -> Self {
+        Self {
+            movie: Movie::new(emulator),
+        }
+    }
+
+    /// Run one frame of up to `instructions` instructions with the given input and record
+    /// it, returning how many instructions were executed.
+    pub fn record_frame(
+        &mut self,
+        emulator: &mut Emulator,
+        keypad: [bool; 16],
+        instructions: u32,
+    ) -> Result<u32, EmulatorError> {
+        emulator.set_keypad(keypad);
+        let executed = emulator.run_frame(instructions)?;
+        self.movie.frames.push(MovieFrame {
+            keypad,
+            instructions,
+        });
+        let frame = self.movie.frames.len();
+        if frame.is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.movie.checkpoints.push((frame, state_hash(emulator)));
+        }
+        Ok(executed)
+    }
+
+    pub fn frames(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds the input of a [`Movie`] to an emulator, checking for desyncs along the way.
+pub struct MoviePlayer {
+    movie: Movie,
+    frame: usize,
+    checkpoint: usize,
+}
+
+impl MoviePlayer {
+    /// Prepare to play `movie` on `emulator`, which must be freshly built with
+    /// [`Movie::configure`].
+    pub fn new(movie: Movie, emulator: &Emulator) -> Result<Self, MovieError> {
+        movie.check(emulator)?;
+        Ok(Self {
+            movie,
+            frame: 0,
+            checkpoint: 0,
+        })
+    }
+
+    /// The number of frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn len(&self) -> usize {
+        self.movie.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.movie.frames.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame == self.movie.frames.len()
+    }
+
+    /// Play the next frame, returning how many instructions it executed or `None` once
+    /// the movie is over.
+    pub fn play_frame(&mut self, emulator: &mut Emulator) -> Option<Result<u32, MovieError>> {
+        let input = *self.movie.frames.get(self.frame)?;
+        emulator.set_keypad(input.keypad);
+        let executed = match emulator.run_frame(input.instructions) {
+            Ok(executed) => executed,
+            Err(error) => return Some(Err(error.into())),
+        };
+        self.frame += 1;
+
+        let checkpoint = self.movie.checkpoints.get(self.checkpoint);
+        if let Some(&(frame, expected)) = checkpoint.filter(|(frame, _)| *frame == self.frame) {
+            self.checkpoint += 1;
+            let actual = state_hash(emulator);
+            if actual != expected {
+                return Some(Err(MovieError::Desync {
+                    frame,
+                    expected,
+                    actual,
+                }));
+            }
+        }
+        Some(Ok(executed))
+    }
+
+    /// Play the movie to the end without a frontend, returning the number of frames played.
+    pub fn play_to_end(&mut self, emulator: &mut Emulator) -> Result<usize, MovieError> {
+        while let Some(result) = self.play_frame(emulator) {
+            result?;
+        }
+        Ok(self.frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws a random byte wherever the pressed key says: C0FF A300 F10A D105 1200
+    const ROM: [u8; 10] = [0xC0, 0xFF, 0xA3, 0x00, 0xF1, 0x0A, 0xD1, 0x05, 0x12, 0x00];
+
+    fn record(frames: usize) -> (Movie, u64) {
+        let mut emulator = Emulator::builder().seed(3).build(&ROM).unwrap();
+        let mut recorder = MovieRecorder::new(&emulator);
+        for frame in 0..frames {
+            let mut keypad = [false; 16];
+            keypad[frame % 16] = frame % 3 == 0;
+            recorder.record_frame(&mut emulator, keypad, 10).unwrap();
+        }
+        (recorder.finish(), state_hash(&emulator))
+    }
+
+    #[test]
+    fn playback_reproduces_the_recording() {
+        let (movie, final_hash) = record(150);
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        assert_eq!(movie.checkpoints.len(), 2);
+
+        let mut emulator = movie.configure(Emulator::builder()).build(&ROM).unwrap();
+        let mut player = MoviePlayer::new(movie, &emulator).unwrap();
+        assert_eq!(player.play_to_end(&mut emulator).unwrap(), 150);
+        assert_eq!(state_hash(&emulator), final_hash);
+    }
+
+    #[test]
+    fn playback_detects_desyncs() {
+        let (movie, _) = record(60);
+        let mut emulator = movie.configure(Emulator::builder()).build(&ROM).unwrap();
+        let mut player = MoviePlayer::new(movie, &emulator).unwrap();
+        emulator.v[0xE] = 1;
+        assert!(matches!(
+            player.play_to_end(&mut emulator),
+            Err(MovieError::Desync { frame: 60, .. })
+        ));
+    }
+}
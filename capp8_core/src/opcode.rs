@@ -23,6 +23,10 @@ impl Opcode {
     pub fn byte(&self) -> u8 {
         (self.n3 << 4) + self.n4
     }
+    /// The opcode as the 16 bit word it was decoded from.
+    pub fn raw(&self) -> u16 {
+        u16::from_be_bytes([(self.n1 << 4) + self.n2, self.byte()])
+    }
 }
 impl From<u16> for Opcode {
     fn from(value: u16) -> Self {
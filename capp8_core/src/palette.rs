@@ -0,0 +1,134 @@
+//! Colours for the display, shared by the frontends and their configuration.
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The colours of the four XO-CHIP plane combinations: off, plane 1, plane 2, both.
+///
+/// Parsed from a preset name such as `amber`, or from two to four comma separated hex
+/// colours like `#000000,#33ff66`. Colours left out are those of plane 1, so that a two
+/// colour palette shows every lit pixel the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Palette(pub [[u8; 3]; 4]);
+
+impl Palette {
+    /// Black and white, with greys for the second plane.
+    pub const GREY: Palette = Palette([
+        [0x00, 0x00, 0x00],
+        [0xFF, 0xFF, 0xFF],
+        [0xAA, 0xAA, 0xAA],
+        [0x55, 0x55, 0x55],
+    ]);
+    /// A monochrome monitor with amber phosphor.
+    pub const AMBER: Palette = Palette([
+        [0x00, 0x00, 0x00],
+        [0xFF, 0xB0, 0x00],
+        [0xB0, 0x78, 0x00],
+        [0x5A, 0x3C, 0x00],
+    ]);
+    /// The four greens of an early handheld LCD.
+    pub const GREEN: Palette = Palette([
+        [0x0F, 0x38, 0x0F],
+        [0x9B, 0xBC, 0x0F],
+        [0x8B, 0xAC, 0x0F],
+        [0x30, 0x62, 0x30],
+    ]);
+    /// The default colours of Octo.
+    pub const OCTO: Palette = Palette([
+        [0x99, 0x66, 0x00],
+        [0xFF, 0xCC, 0x00],
+        [0xFF, 0x66, 0x00],
+        [0x66, 0x22, 0x00],
+    ]);
+
+    pub const NAMED: [(&'static str, Palette); 4] = [
+        ("grey", Palette::GREY),
+        ("amber", Palette::AMBER),
+        ("green", Palette::GREEN),
+        ("octo", Palette::OCTO),
+    ];
+
+    /// A palette from two to four colours, see [`Palette`] for the ones left out.
+    pub fn from_colors(colors: &[[u8; 3]]) -> Option<Self> {
+        if !(2..=4).contains(&colors.len()) {
+            return None;
+        }
+        let color = |index: usize| colors.get(index).copied().unwrap_or(colors[1]);
+        Some(Palette([color(0), color(1), color(2), color(3)]))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREY
+    }
+}
+
+/// A colour as `#rrggbb` or `rrggbb`.
+pub fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, [r, g, b]) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "#{r:02x}{g:02x}{b:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase();
+        if let Some((_, palette)) = Palette::NAMED.iter().find(|(known, _)| *known == name) {
+            return Ok(*palette);
+        }
+        let colors = s
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Option<Vec<_>>>();
+        colors
+            .as_deref()
+            .and_then(Palette::from_colors)
+            .ok_or_else(|| {
+                format!(
+                    "unknown palette {s}, expected grey, amber, green, octo or two to four \
+                     colours like #000000,#ffffff"
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_and_colors() {
+        assert_eq!("Amber".parse(), Ok(Palette::AMBER));
+        assert_eq!(Palette::OCTO.to_string().parse(), Ok(Palette::OCTO));
+        assert_eq!(
+            "#102030, 33ff66".parse(),
+            Ok(Palette([
+                [0x10, 0x20, 0x30],
+                [0x33, 0xFF, 0x66],
+                [0x33, 0xFF, 0x66],
+                [0x33, 0xFF, 0x66],
+            ]))
+        );
+        assert!("#000000".parse::<Palette>().is_err());
+        assert!("#000000,#fffff".parse::<Palette>().is_err());
+        assert!("sepia".parse::<Palette>().is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::{fmt, str::FromStr};
+
+/// Behaviours that differ between CHIP-8 interpreters.
+///
+/// Each flag describes how an ambiguous opcode is executed. The names follow the
+/// ones used by the [Timendus test suite](https://github.com/Timendus/chip8-test-suite),
+/// so the `5-quirks.ch8` report can be read against this struct directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vx in place and ignore Vy.
+    /// When disabled, Vy is shifted and the result stored in Vx.
+    pub shift: bool,
+    /// Where Fx55/Fx65 leave I.
+    pub load_store: LoadStore,
+    /// Bnnn jumps to `nnn + Vx` (where x is the high nibble of `nnn`) instead of `nnn + V0`.
+    pub jump_with_vx: bool,
+    /// 8xy1/8xy2/8xy3 reset VF to 0.
+    pub vf_reset: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around.
+    pub clipping: bool,
+    /// Dxyn waits for the next vertical blank, limiting drawing to once per frame.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter running on the RCA COSMAC VIP.
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        load_store: LoadStore::Increment,
+        jump_with_vx: false,
+        vf_reset: true,
+        clipping: true,
+        display_wait: true,
+    };
+
+    /// CHIP-48 on the HP-48 calculators, whose Fx55/Fx65 leave I one short of where the
+    /// original does.
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        load_store: LoadStore::IncrementByX,
+        jump_with_vx: true,
+        vf_reset: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// SUPER-CHIP 1.1, as implemented by modern interpreters.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        load_store: LoadStore::Unchanged,
+        jump_with_vx: true,
+        vf_reset: false,
+        clipping: true,
+        display_wait: false,
+    };
+
+    /// XO-CHIP, as implemented by Octo.
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        load_store: LoadStore::Increment,
+        jump_with_vx: false,
+        vf_reset: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    /// The names of the flags on the command line and in configuration files, in
+    /// declaration order. [`Quirks::load_store`] takes two: enabling either picks that
+    /// increment, disabling either leaves I unchanged.
+    pub const NAMES: [&'static str; 7] = [
+        "shift",
+        "load-store-increment",
+        "load-store-increment-by-x",
+        "jump-with-vx",
+        "vf-reset",
+        "clipping",
+        "display-wait",
+    ];
+
+    /// Set the flag called `name`, one of [`Quirks::NAMES`]. Returns `false` for other
+    /// names.
+    pub fn set_flag(&mut self, name: &str, enabled: bool) -> bool {
+        let flag = match name {
+            "shift" => &mut self.shift,
+            "load-store-increment" | "load-store-increment-by-x" => {
+                self.load_store = match (name, enabled) {
+                    (_, false) => LoadStore::Unchanged,
+                    ("load-store-increment", true) => LoadStore::Increment,
+                    _ => LoadStore::IncrementByX,
+                };
+                return true;
+            }
+            "jump-with-vx" => &mut self.jump_with_vx,
+            "vf-reset" => &mut self.vf_reset,
+            "clipping" => &mut self.clipping,
+            "display-wait" => &mut self.display_wait,
+            _ => return false,
+        };
+        *flag = enabled;
+        true
+    }
+
+    /// Pack the flags into a byte, in declaration order starting from the lowest bit, the
+    /// load/store increment taking bit 1 for [`LoadStore::Increment`] and bit 6 for
+    /// [`LoadStore::IncrementByX`].
+    pub fn to_bits(self) -> u8 {
+        [
+            self.shift,
+            self.load_store == LoadStore::Increment,
+            self.jump_with_vx,
+            self.vf_reset,
+            self.clipping,
+            self.display_wait,
+            self.load_store == LoadStore::IncrementByX,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (bit, &set)| bits | ((set as u8) << bit))
+    }
+
+    /// The inverse of [`Quirks::to_bits`].
+    pub fn from_bits(bits: u8) -> Self {
+        let flag = |bit: u8| bits & (1 << bit) != 0;
+        let load_store = match (flag(1), flag(6)) {
+            (_, true) => LoadStore::IncrementByX,
+            (true, false) => LoadStore::Increment,
+            (false, false) => LoadStore::Unchanged,
+        };
+        Quirks {
+            shift: flag(0),
+            load_store,
+            jump_with_vx: flag(2),
+            vf_reset: flag(3),
+            clipping: flag(4),
+            display_wait: flag(5),
+        }
+    }
+}
+
+/// Where Fx55/Fx65 leave I after storing or loading V0 to Vx.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoadStore {
+    /// Past the last register, at I + X + 1, as on the COSMAC VIP.
+    Increment,
+    /// At the last register, I + X, as on CHIP-48.
+    IncrementByX,
+    /// Where it was, as on SUPER-CHIP 1.1.
+    Unchanged,
+}
+
+impl LoadStore {
+    /// How far I moves after touching V0 to V`reg`.
+    pub fn offset(self, reg: usize) -> u16 {
+        match self {
+            LoadStore::Increment => reg as u16 + 1,
+            LoadStore::IncrementByX => reg as u16,
+            LoadStore::Unchanged => 0,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
+
+/// The platforms for which a named [`Quirks`] preset exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Platform {
+    #[default]
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks::COSMAC_VIP,
+            Platform::Chip48 => Quirks::CHIP_48,
+            Platform::SuperChip => Quirks::SUPER_CHIP,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    /// Short identifier used on the command line and in file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "chip8",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" | "cosmac-vip" => Ok(Platform::CosmacVip),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(format!("unknown platform `{s}`")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_distinct_and_survive_packing() {
+        for (index, platform) in Platform::ALL.into_iter().enumerate() {
+            let quirks = platform.quirks();
+            assert_eq!(Quirks::from_bits(quirks.to_bits()), quirks, "{platform}");
+            for other in &Platform::ALL[index + 1..] {
+                assert_ne!(other.quirks(), quirks, "{platform} and {other}");
+            }
+        }
+    }
+
+    #[test]
+    fn load_store_takes_two_names() {
+        let mut quirks = Quirks::SUPER_CHIP;
+        assert!(quirks.set_flag("load-store-increment-by-x", true));
+        assert_eq!(quirks.load_store, LoadStore::IncrementByX);
+        assert!(quirks.set_flag("load-store-increment", true));
+        assert_eq!(quirks.load_store, LoadStore::Increment);
+        assert!(quirks.set_flag("load-store-increment-by-x", false));
+        assert_eq!(quirks.load_store, LoadStore::Unchanged);
+        assert!(!quirks.set_flag("wrap", true));
+    }
+}
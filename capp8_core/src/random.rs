@@ -0,0 +1,77 @@
+/// Where Cxkk gets its random bytes from.
+///
+/// The whole state of a source must fit in a `u64`, so that it can be stored in save
+/// states and a run can be replayed bit for bit from the same seed and inputs.
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+    fn state(&self) -> u64;
+    fn set_state(&mut self, state: u64);
+}
+
+/// The default [`RandomSource`], Sebastiano Vigna's SplitMix64.
+///
+/// Every seed, including 0, gives a good sequence.
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = SplitMix64::new(42);
+        let mut b = SplitMix64::new(42);
+        let bytes: Vec<u8> = (0..64).map(|_| a.next_byte()).collect();
+        assert_eq!(bytes, (0..64).map(|_| b.next_byte()).collect::<Vec<_>>());
+
+        b.set_state(SplitMix64::new(42).state());
+        assert_eq!(b.next_byte(), bytes[0]);
+    }
+
+    #[test]
+    fn covers_every_byte() {
+        let mut rng = SplitMix64::new(0);
+        let mut seen = [false; 256];
+        for _ in 0..10_000 {
+            seen[rng.next_byte() as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+    }
+
+    #[test]
+    fn emulator_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<crate::emulator::Emulator>();
+    }
+}
@@ -0,0 +1,199 @@
+//! A memory-bounded history of save states for hold-to-rewind.
+//!
+//! Consecutive frames differ in a handful of bytes, so only the newest snapshot is kept
+//! whole. Every older frame is stored as the XOR of itself with the frame after it,
+//! run-length encoded so that the unchanged (zero) stretches take a couple of bytes.
+//! Stepping back XORs the newest snapshot with the latest delta.
+
+use std::collections::VecDeque;
+
+use crate::{emulator::Emulator, error::SnapshotError};
+
+pub struct Rewind {
+    /// The snapshot of the most recent frame.
+    current: Option<Vec<u8>>,
+    /// Compressed deltas, oldest first. Applying the last one to `current` yields the
+    /// frame before it.
+    deltas: VecDeque<Vec<u8>>,
+    /// Total size of `deltas`.
+    bytes: usize,
+    max_frames: usize,
+    max_bytes: usize,
+}
+
+impl Rewind {
+    /// Keep at most `max_frames` frames of history, using at most `max_bytes` for the deltas.
+    pub fn new(max_frames: usize, max_bytes: usize) -> Self {
+        Self {
+            current: None,
+            deltas: VecDeque::new(),
+            bytes: 0,
+            max_frames,
+            max_bytes,
+        }
+    }
+
+    /// How many frames can currently be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Memory used by the history, not counting the newest snapshot.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+        self.bytes = 0;
+    }
+
+    /// Record the current state of `emulator` as the newest frame.
+    pub fn push(&mut self, emulator: &Emulator) {
+        let state = emulator.save_state();
+        if let Some(previous) = self.current.replace(state) {
+            let delta = encode_delta(&previous, self.current.as_ref().expect("just replaced"));
+            self.bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        while self.deltas.len() > self.max_frames || self.bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.bytes -= oldest.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Go back up to `frames` frames, restoring `emulator` to the oldest one reached.
+    ///
+    /// Returns how many frames were actually rewound, which is less than `frames` once
+    /// the history runs out.
+    pub fn rewind(
+        &mut self,
+        emulator: &mut Emulator,
+        frames: usize,
+    ) -> Result<usize, SnapshotError> {
+        let mut rewound = 0;
+        while rewound < frames {
+            let (Some(current), Some(delta)) = (self.current.as_mut(), self.deltas.pop_back())
+            else {
+                break;
+            };
+            self.bytes -= delta.len();
+            apply_delta(current, &delta);
+            rewound += 1;
+        }
+        if rewound > 0 {
+            emulator.load_state(self.current.as_ref().expect("rewound at least one frame"))?;
+        }
+        Ok(rewound)
+    }
+}
+
+/// Encode `old` relative to `new` as `old.len()` (u32 LE) followed by runs of
+/// `(zero run length, literal length, literal bytes)`, lengths being LEB128 varints.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let len = old.len().max(new.len());
+    let xor: Vec<u8> = (0..len)
+        .map(|idx| old.get(idx).unwrap_or(&0) ^ new.get(idx).unwrap_or(&0))
+        .collect();
+
+    let mut out = (old.len() as u32).to_le_bytes().to_vec();
+    let mut idx = 0;
+    while idx < xor.len() {
+        let zeros = xor[idx..].iter().take_while(|&&byte| byte == 0).count();
+        idx += zeros;
+        let literal = xor[idx..].iter().take_while(|&&byte| byte != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literal);
+        out.extend_from_slice(&xor[idx..idx + literal]);
+        idx += literal;
+    }
+    out
+}
+
+/// Turn `state` into the older state `delta` was encoded against.
+fn apply_delta(state: &mut Vec<u8>, delta: &[u8]) {
+    let old_len = u32::from_le_bytes(delta[..4].try_into().expect("delta has a header")) as usize;
+    let mut rest = &delta[4..];
+    let mut idx = 0;
+    state.resize(state.len().max(old_len), 0);
+    while !rest.is_empty() {
+        idx += read_varint(&mut rest);
+        let literal = read_varint(&mut rest);
+        for (byte, xor) in state[idx..idx + literal].iter_mut().zip(&rest[..literal]) {
+            *byte ^= xor;
+        }
+        rest = &rest[literal..];
+        idx += literal;
+    }
+    state.truncate(old_len);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = bytes.split_first() {
+        *bytes = rest;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // Counts up in V0 and draws it: 7001 F029 00E0 D015 1200
+    const ROM: [u8; 10] = [0x70, 0x01, 0xF0, 0x29, 0x00, 0xE0, 0xD0, 0x15, 0x12, 0x00];
+
+    #[test]
+    fn rewinds_to_earlier_frames() {
+        let mut emulator = Emulator::builder().seed(7).build(&ROM).unwrap();
+        let mut rewind = Rewind::new(100, usize::MAX);
+        let mut states = Vec::new();
+        for _ in 0..20 {
+            emulator.step(Duration::from_millis(17)).unwrap();
+            rewind.push(&emulator);
+            states.push(emulator.save_state());
+        }
+        assert_eq!(rewind.len(), 19);
+
+        assert_eq!(rewind.rewind(&mut emulator, 5).unwrap(), 5);
+        assert_eq!(emulator.save_state(), states[14]);
+        assert_eq!(rewind.rewind(&mut emulator, 100).unwrap(), 14);
+        assert_eq!(emulator.save_state(), states[0]);
+        assert_eq!(rewind.rewind(&mut emulator, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn respects_the_frame_budget() {
+        let mut emulator = Emulator::from_bytes(&ROM).unwrap();
+        let mut rewind = Rewind::new(3, usize::MAX);
+        for _ in 0..10 {
+            emulator.step(Duration::from_millis(17)).unwrap();
+            rewind.push(&emulator);
+        }
+        assert_eq!(rewind.len(), 3);
+        assert!(rewind.bytes() < 1024);
+    }
+}
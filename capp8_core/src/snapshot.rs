@@ -0,0 +1,306 @@
+//! Versioned binary save states.
+//!
+//! A snapshot starts with the magic `C8SS` and a little endian `u16` format version,
+//! followed by a sequence of chunks. Every chunk is a four byte tag, a little endian
+//! `u32` payload length and the payload, so a reader can skip the chunks it does not
+//! know about. Multi-byte values are little endian.
+//!
+//! | Tag    | Payload                                                          |
+//! |--------|------------------------------------------------------------------|
+//! | `ROMH` | SHA-1 of the ROM image                                           |
+//! | `QURK` | [`Quirks::to_bits`], bits 1 and 6 for the load/store increment   |
+//! | `CPU ` | V0-VF, I (u16), PC (u16), SP (u8), halted (u8), vblank (u8)      |
+//! | `STAK` | the 16 stack slots (u16 each)                                    |
+//! | `TIMR` | delay timer, sound timer, time since the last tick in ns (u64)   |
+//! | `MEMO` | the whole address space                                          |
+//! | `DISP` | hires (u8), selected planes (u8), 128x64 pixel colour indices    |
+//! | `KEYS` | keypad state, bit n set when key n is down (u16)                 |
+//! | `SCHP` | the 16 RPL user flags                                            |
+//! | `XOCH` | audio pattern buffer (16 bytes), pitch (u8)                      |
+//! | `RAND` | state of the random source (u64)                                 |
+//!
+//! The ROM hash and quirk profile are checked on load, so a state can only be restored
+//! into an emulator running the same program the same way.
+
+use std::time::Duration;
+
+use crate::{display::Display, emulator::Emulator, error::SnapshotError, quirks::Quirks};
+
+const MAGIC: &[u8; 4] = b"C8SS";
+/// Bumped whenever an existing chunk changes layout.
+pub const VERSION: u16 = 1;
+
+const ROM_HASH: [u8; 4] = *b"ROMH";
+const QUIRKS: [u8; 4] = *b"QURK";
+const CPU: [u8; 4] = *b"CPU ";
+const STACK: [u8; 4] = *b"STAK";
+const TIMERS: [u8; 4] = *b"TIMR";
+const MEMORY: [u8; 4] = *b"MEMO";
+const DISPLAY: [u8; 4] = *b"DISP";
+const KEYPAD: [u8; 4] = *b"KEYS";
+const SUPER_CHIP: [u8; 4] = *b"SCHP";
+const XO_CHIP: [u8; 4] = *b"XOCH";
+const RANDOM: [u8; 4] = *b"RAND";
+
+impl Emulator {
+    /// Capture the whole machine state. See the [module documentation](self) for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            self.memory.len() + Display::HIRES_WIDTH * Display::HIRES_HEIGHT + 256,
+        );
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+
+        write_chunk(&mut out, ROM_HASH, &self.rom_hash);
+        write_chunk(&mut out, QUIRKS, &[self.quirks.to_bits()]);
+
+        let mut cpu = self.v.to_vec();
+        cpu.extend_from_slice(&self.i.to_le_bytes());
+        cpu.extend_from_slice(&self.program_counter.to_le_bytes());
+        cpu.extend_from_slice(&[
+            self.stack_pointer as u8,
+            self.halted as u8,
+            self.vblank as u8,
+        ]);
+        write_chunk(&mut out, CPU, &cpu);
+
+        let stack: Vec<u8> = self
+            .stack
+            .iter()
+            .flat_map(|addr| addr.to_le_bytes())
+            .collect();
+        write_chunk(&mut out, STACK, &stack);
+
+        let mut timers = vec![self.timers.delay(), self.timers.sound()];
+        let since_tick = self.timers.since_tick().as_nanos() as u64;
+        timers.extend_from_slice(&since_tick.to_le_bytes());
+        write_chunk(&mut out, TIMERS, &timers);
+
+        write_chunk(&mut out, MEMORY, &self.memory);
+
+        let mut display = vec![self.display.hires as u8, self.display.planes];
+        display.extend_from_slice(&self.display.pixel);
+        write_chunk(&mut out, DISPLAY, &display);
+
+        let keypad = self
+            .keypad
+            .iter()
+            .enumerate()
+            .fold(0u16, |bits, (key, &down)| bits | ((down as u16) << key));
+        write_chunk(&mut out, KEYPAD, &keypad.to_le_bytes());
+
+        write_chunk(&mut out, SUPER_CHIP, &self.flags);
+
+        let mut xo_chip = self.audio_pattern.to_vec();
+        xo_chip.push(self.pitch);
+        write_chunk(&mut out, XO_CHIP, &xo_chip);
+
+        write_chunk(&mut out, RANDOM, &self.rng.state().to_le_bytes());
+
+        out
+    }
+
+    /// Restore a state produced by [`Emulator::save_state`].
+    ///
+    /// The state is only applied if it is valid as a whole and was taken with the same ROM
+    /// and quirks; otherwise the emulator is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), SnapshotError> {
+        let chunks = Chunks::parse(state)?;
+
+        if chunks.get(ROM_HASH)? != self.rom_hash {
+            return Err(SnapshotError::RomMismatch);
+        }
+        if Quirks::from_bits(chunks.get_sized::<1>(QUIRKS)?[0]) != self.quirks {
+            return Err(SnapshotError::QuirksMismatch);
+        }
+
+        let cpu = chunks.get_sized::<23>(CPU)?;
+        let stack = chunks.get_sized::<32>(STACK)?;
+        let timers = chunks.get_sized::<10>(TIMERS)?;
+        let memory = chunks.get(MEMORY)?;
+        if memory.len() != self.memory.len() {
+            return Err(SnapshotError::InvalidChunk(MEMORY));
+        }
+        let display =
+            chunks.get_sized::<{ 2 + Display::HIRES_WIDTH * Display::HIRES_HEIGHT }>(DISPLAY)?;
+        let keypad = chunks.get_sized::<2>(KEYPAD)?;
+        let flags = chunks.get_sized::<16>(SUPER_CHIP)?;
+        let xo_chip = chunks.get_sized::<17>(XO_CHIP)?;
+        let random = chunks.get_sized::<8>(RANDOM)?;
+        if cpu[20] as usize > self.stack.len() {
+            return Err(SnapshotError::InvalidChunk(CPU));
+        }
+        // Both the plane mask and the pixels are colour indices into four colours.
+        if display[1..].iter().any(|&byte| byte > 0b11) {
+            return Err(SnapshotError::InvalidChunk(DISPLAY));
+        }
+
+        self.v.copy_from_slice(&cpu[..16]);
+        self.i = u16::from_le_bytes([cpu[16], cpu[17]]);
+        self.program_counter = u16::from_le_bytes([cpu[18], cpu[19]]);
+        self.stack_pointer = cpu[20] as usize;
+        self.halted = cpu[21] != 0;
+        self.vblank = cpu[22] != 0;
+        for (slot, addr) in self.stack.iter_mut().zip(stack.chunks_exact(2)) {
+            *slot = u16::from_le_bytes([addr[0], addr[1]]);
+        }
+        self.timers.set_delay(timers[0]);
+        self.timers.set_sound(timers[1]);
+        self.timers
+            .set_since_tick(Duration::from_nanos(u64::from_le_bytes(
+                timers[2..]
+                    .try_into()
+                    .expect("timers chunk size was checked"),
+            )));
+        self.memory.copy_from_slice(memory);
+        self.display.hires = display[0] != 0;
+        self.display.planes = display[1];
+        self.display.pixel.copy_from_slice(&display[2..]);
+        let keypad = u16::from_le_bytes(keypad);
+        for (key, down) in self.keypad.iter_mut().enumerate() {
+            *down = keypad & (1 << key) != 0;
+        }
+        self.flags = flags;
+        self.audio_pattern.copy_from_slice(&xo_chip[..16]);
+        self.pitch = xo_chip[16];
+        self.rng.set_state(u64::from_le_bytes(random));
+        Ok(())
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, tag: [u8; 4], payload: &[u8]) {
+    out.extend_from_slice(&tag);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+}
+
+/// The chunks of a snapshot, in file order.
+struct Chunks<'a>(Vec<([u8; 4], &'a [u8])>);
+
+impl<'a> Chunks<'a> {
+    fn parse(state: &'a [u8]) -> Result<Self, SnapshotError> {
+        let rest = state
+            .strip_prefix(MAGIC)
+            .ok_or(SnapshotError::NotASnapshot)?;
+        let (version, mut rest) = rest.split_at_checked(2).ok_or(SnapshotError::Truncated)?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        // There is no older format to migrate from yet.
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let mut chunks = Vec::new();
+        while !rest.is_empty() {
+            let (header, payload) = rest.split_at_checked(8).ok_or(SnapshotError::Truncated)?;
+            let tag = header[..4].try_into().expect("header is 8 bytes long");
+            let len = u32::from_le_bytes(header[4..].try_into().expect("header is 8 bytes long"));
+            let (payload, next) = payload
+                .split_at_checked(len as usize)
+                .ok_or(SnapshotError::Truncated)?;
+            chunks.push((tag, payload));
+            rest = next;
+        }
+        Ok(Self(chunks))
+    }
+
+    fn get(&self, tag: [u8; 4]) -> Result<&'a [u8], SnapshotError> {
+        self.0
+            .iter()
+            .find(|(chunk_tag, _)| *chunk_tag == tag)
+            .map(|(_, payload)| *payload)
+            .ok_or(SnapshotError::MissingChunk(tag))
+    }
+
+    fn get_sized<const N: usize>(&self, tag: [u8; 4]) -> Result<[u8; N], SnapshotError> {
+        self.get(tag)?
+            .try_into()
+            .map_err(|_| SnapshotError::InvalidChunk(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Draws the digit in V0 forever: 6005 F029 D015 1206
+    const ROM: [u8; 8] = [0x60, 0x05, 0xF0, 0x29, 0xD0, 0x15, 0x12, 0x06];
+
+    #[test]
+    fn round_trip() {
+        let mut emulator = Emulator::builder().seed(1).build(&ROM).unwrap();
+        for _ in 0..4 {
+            emulator.step(Duration::from_millis(20)).unwrap();
+        }
+        let state = emulator.save_state();
+
+        let mut restored = Emulator::builder().seed(1).build(&ROM).unwrap();
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+    }
+
+    #[test]
+    fn rejects_invalid_display_contents() {
+        let state = Emulator::from_bytes(&ROM).unwrap().save_state();
+        let display = state
+            .windows(4)
+            .position(|tag| tag == DISPLAY)
+            .expect("the state has a display chunk")
+            + 8;
+        // The selected planes, then the first and the last pixel.
+        for offset in [1, 2, 1 + Display::HIRES_WIDTH * Display::HIRES_HEIGHT] {
+            let mut corrupt = state.clone();
+            corrupt[display + offset] = 4;
+            let mut emulator = Emulator::from_bytes(&ROM).unwrap();
+            assert_eq!(
+                emulator.load_state(&corrupt),
+                Err(SnapshotError::InvalidChunk(DISPLAY))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_rom_and_quirks() {
+        let state = Emulator::from_bytes(&ROM).unwrap().save_state();
+
+        let mut other_rom = Emulator::from_bytes(&ROM[..6]).unwrap();
+        assert_eq!(
+            other_rom.load_state(&state),
+            Err(SnapshotError::RomMismatch)
+        );
+        let mut other_quirks = Emulator::builder()
+            .quirks(Quirks::XO_CHIP)
+            .build(&ROM)
+            .unwrap();
+        assert_eq!(
+            other_quirks.load_state(&state),
+            Err(SnapshotError::QuirksMismatch)
+        );
+        assert_eq!(
+            other_quirks.load_state(&state[..state.len() - 1]),
+            Err(SnapshotError::Truncated)
+        );
+        for version in [0, VERSION + 1] {
+            let mut state = state.clone();
+            state[4..6].copy_from_slice(&u16::to_le_bytes(version));
+            assert_eq!(
+                other_rom.load_state(&state),
+                Err(SnapshotError::UnsupportedVersion(version))
+            );
+        }
+
+        // CHIP-48 differs from SUPER-CHIP in the load/store increment only.
+        let state = Emulator::builder()
+            .quirks(Quirks::CHIP_48)
+            .build(&ROM)
+            .unwrap()
+            .save_state();
+        let mut super_chip = Emulator::builder()
+            .quirks(Quirks::SUPER_CHIP)
+            .build(&ROM)
+            .unwrap();
+        assert_eq!(
+            super_chip.load_state(&state),
+            Err(SnapshotError::QuirksMismatch)
+        );
+    }
+}
@@ -1 +1,201 @@
+//! The delay and sound timers.
+//!
+//! The timers count down at a fixed rate, 60 Hz on every common platform. Time is kept
+//! on a virtual clock advanced by the caller, in whole nanoseconds or whole display frames,
+//! scaled by the rate so that rates which do not divide a second evenly never drift: a
+//! second of time always makes exactly `rate` ticks, however it is split up.
 
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+/// The length of a second on the virtual clock, which counts in both nanoseconds and frames.
+const SECOND: u128 = NANOS_PER_SECOND * Timers::FRAME_RATE as u128;
+/// Sound events kept for [`Timers::events`], the oldest being dropped first when nobody
+/// takes them.
+const MAX_EVENTS: usize = 16;
+
+/// A change in whether the sound timer is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundEvent {
+    Started,
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+pub struct Timers {
+    delay: u8,
+    sound: u8,
+    /// Ticks per second.
+    rate: u32,
+    /// Time since the last tick, in nanoseconds times [`Timers::FRAME_RATE`] times `rate`.
+    phase: u128,
+    events: VecDeque<SoundEvent>,
+}
+
+impl Timers {
+    pub const DEFAULT_RATE: u32 = 60;
+    /// Frames per second of the display, which [`Timers::advance_frame`] steps by.
+    pub const FRAME_RATE: u32 = 60;
+
+    /// Timers ticking `rate` times per second.
+    pub fn new(rate: u32) -> Self {
+        Self {
+            delay: 0,
+            sound: 0,
+            rate: rate.max(1),
+            phase: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    pub fn sound(&self) -> u8 {
+        self.sound
+    }
+
+    pub fn set_delay(&mut self, value: u8) {
+        self.delay = value;
+    }
+
+    pub fn set_sound(&mut self, value: u8) {
+        let was_sounding = self.is_sounding();
+        self.sound = value;
+        self.notify(was_sounding);
+    }
+
+    pub fn is_sounding(&self) -> bool {
+        self.sound > 0
+    }
+
+    /// Count both timers down by one.
+    pub fn tick(&mut self) {
+        let was_sounding = self.is_sounding();
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+        self.notify(was_sounding);
+    }
+
+    /// Move the clock on by `dt`, ticking once for every period completed. Returns the
+    /// number of ticks.
+    pub fn advance(&mut self, dt: Duration) -> u64 {
+        self.advance_phase(dt.as_nanos() * Self::FRAME_RATE as u128 * self.rate as u128)
+    }
+
+    /// Move the clock on by one display frame. At the default rate this is exactly one
+    /// tick; other rates tick more or less often, as they would over the same time.
+    pub fn advance_frame(&mut self) -> u64 {
+        self.advance_phase(NANOS_PER_SECOND * self.rate as u128)
+    }
+
+    fn advance_phase(&mut self, phase: u128) -> u64 {
+        self.phase += phase;
+        let ticks = self.phase / SECOND;
+        self.phase %= SECOND;
+        // Past 255 ticks both timers are at 0 whatever they started from.
+        for _ in 0..ticks.min(u8::MAX as u128 + 1) {
+            self.tick();
+        }
+        ticks as u64
+    }
+
+    /// How long ago the last tick was on the virtual clock, rounded down.
+    pub fn since_tick(&self) -> Duration {
+        Duration::from_nanos((self.phase / Self::FRAME_RATE as u128 / self.rate as u128) as u64)
+    }
+
+    /// Set the virtual clock to `since` after a tick, as when restoring a saved state.
+    pub fn set_since_tick(&mut self, since: Duration) {
+        self.phase = since.as_nanos() * Self::FRAME_RATE as u128 * self.rate as u128 % SECOND;
+    }
+
+    /// Take the sound events that happened since the last call, oldest first.
+    pub fn events(&mut self) -> impl Iterator<Item = SoundEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    fn notify(&mut self, was_sounding: bool) {
+        let event = match (was_sounding, self.is_sounding()) {
+            (false, true) => SoundEvent::Started,
+            (true, false) => SoundEvent::Stopped,
+            _ => return,
+        };
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_exactly_on_the_virtual_clock() {
+        let mut timers = Timers::default();
+        timers.set_delay(255);
+        let ticks: u64 = (0..1000)
+            .map(|_| timers.advance(Duration::from_millis(1)))
+            .sum();
+        assert_eq!((ticks, timers.delay()), (60, 195));
+        assert_eq!(timers.since_tick(), Duration::ZERO);
+
+        // Seven thirds of a second at 50 Hz make 116 ticks and two thirds of a period.
+        let mut timers = Timers::new(50);
+        timers.set_delay(255);
+        let third = Duration::from_nanos(333_333_333);
+        let ticks: u64 = (0..7).map(|_| timers.advance(third)).sum();
+        assert_eq!((ticks, timers.delay()), (116, 139));
+        assert_eq!(timers.since_tick(), Duration::from_nanos(13_333_331));
+
+        timers.set_since_tick(Duration::from_millis(5));
+        assert_eq!(timers.advance(Duration::from_millis(15)), 1);
+        assert_eq!(timers.since_tick(), Duration::ZERO);
+    }
+
+    #[test]
+    fn frames_tick_at_the_timer_rate() {
+        let mut timers = Timers::default();
+        assert!((0..60).all(|_| timers.advance_frame() == 1));
+
+        let mut timers = Timers::new(50);
+        timers.set_delay(255);
+        let ticks: u64 = (0..60).map(|_| timers.advance_frame()).sum();
+        assert_eq!((ticks, timers.delay()), (50, 205));
+        assert_eq!(timers.advance_frame(), 0);
+    }
+
+    #[test]
+    fn reports_sound_starting_and_stopping() {
+        let mut timers = Timers::default();
+        timers.set_sound(2);
+        timers.set_sound(3);
+        timers.tick();
+        assert!(timers.events().eq([SoundEvent::Started]));
+        timers.advance(Duration::from_secs(1));
+        assert_eq!(timers.sound(), 0);
+        assert!(timers.events().eq([SoundEvent::Stopped]));
+        timers.set_sound(1);
+        timers.set_sound(0);
+        assert!(
+            timers
+                .events()
+                .eq([SoundEvent::Started, SoundEvent::Stopped])
+        );
+        assert_eq!(timers.events().count(), 0);
+    }
+}
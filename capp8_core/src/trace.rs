@@ -0,0 +1,566 @@
+//! Per-instruction execution traces, for comparing runs against each other and against
+//! other emulators.
+//!
+//! Each executed instruction produces one line with the state *before* it ran:
+//!
+//! ```text
+//! PC=0200 OP=6001 V=00000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00 ; LD V0, 0x01
+//! ```
+//!
+//! All numbers are upper-case hex of a fixed width: PC, the raw opcode and I take four
+//! digits, V0 to VF two each, run together, and SP, DT and ST two. For `F000 nnnn` the
+//! opcode is the first word only. Everything after ` ; ` is the decoded instruction in
+//! Cowgod syntax and is informational only. Lines starting with `#` are comments; a
+//! trace written by [`Tracer::to_writer`] starts with [`HEADER`]. This format is stable:
+//! fields may only ever be added after ST.
+//!
+//! Instructions that block, like Fx0A waiting for a key or a draw waiting for vertical
+//! blank, are only traced once they complete.
+//!
+//! [`diff`] reads traces back to find where two runs part ways. Traces from other
+//! emulators can be compared as long as they write the `PC`, `OP`, `V`, `I`, `SP`, `DT`
+//! and `ST` fields above: field order and the case of hex digits do not matter, unknown
+//! fields are ignored and the ` ; ` comment is optional. Opcodes that capp8 does not
+//! decode are compared all the same and shown as `???`.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    debugger::Register, emulator::Emulator, error::TraceParseError, instruction::Instruction,
+};
+
+/// The first line of a trace file.
+pub const HEADER: &str = "# capp8 trace v1";
+/// How many agreeing instructions a [`Divergence`] keeps.
+const CONTEXT_BEFORE: usize = 8;
+/// How many instructions of each trace a [`Divergence`] keeps after the traces part ways.
+const CONTEXT_AFTER: usize = 3;
+
+/// The machine state just before an instruction ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    /// The raw opcode, the first word for instructions that take two.
+    pub opcode: u16,
+    /// The decoded instruction, `None` when a trace read back holds an opcode that is not
+    /// one.
+    pub instruction: Option<Instruction>,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl TraceRecord {
+    /// The state of `emulator` as `instruction`, fetched from `pc`, is about to run.
+    pub(crate) fn capture(
+        emulator: &Emulator,
+        pc: u16,
+        opcode: u16,
+        instruction: Instruction,
+    ) -> Self {
+        Self {
+            pc,
+            opcode,
+            instruction: Some(instruction),
+            v: emulator.v,
+            i: emulator.i,
+            sp: emulator.stack_pointer as u8,
+            dt: emulator.timers.delay(),
+            st: emulator.timers.sound(),
+        }
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        match register {
+            Register::V(reg) => self.v[reg & 0xF] as u16,
+            Register::I => self.i,
+            Register::Pc => self.pc,
+            Register::Sp => self.sp as u16,
+            Register::Dt => self.dt as u16,
+            Register::St => self.st as u16,
+        }
+    }
+
+    /// Where `self` and `other` disagree. The opcodes are only compared at the same PC.
+    pub fn differences(&self, other: &TraceRecord) -> Vec<Difference> {
+        let mut differences: Vec<Difference> = Register::ALL
+            .iter()
+            .filter(|&&register| self.register(register) != other.register(register))
+            .map(|&register| Difference::Register {
+                register,
+                first: self.register(register),
+                second: other.register(register),
+            })
+            .collect();
+        if self.pc == other.pc && self.opcode != other.opcode {
+            differences.push(Difference::Memory {
+                addr: self.pc,
+                first: self.opcode,
+                second: other.opcode,
+            });
+        }
+        differences
+    }
+
+    /// Read a trace line, `None` for blank lines and comments. `number` is only used to
+    /// report errors.
+    fn parse(number: usize, text: &str) -> Result<Option<TraceLine>, TraceParseError> {
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            return Ok(None);
+        }
+        let (fields, comment) = match text.split_once(';') {
+            Some((fields, comment)) => (fields, Some(comment.trim())),
+            None => (text, None),
+        };
+        let error = |field| TraceParseError {
+            line: number,
+            field,
+        };
+        let value = |key: &'static str| {
+            fields
+                .split_whitespace()
+                .filter_map(|token| token.split_once('='))
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value)
+                .ok_or(error(key))
+        };
+        let word = |key| value(key).and_then(|text| parse_hex(text, 4).ok_or(error(key)));
+        let byte = |key| value(key).and_then(|text| parse_hex(text, 2).ok_or(error(key)));
+
+        let v_text = value("V")?;
+        let mut v = [0; 16];
+        for (reg, v) in v.iter_mut().enumerate() {
+            let digits = v_text
+                .get(reg * 2..reg * 2 + 2)
+                .filter(|_| v_text.len() == 32);
+            *v = digits
+                .and_then(|digits| parse_hex(digits, 2))
+                .ok_or(error("V"))? as u8;
+        }
+        let opcode = word("OP")?;
+        // The second word of F000 nnnn is not in the trace, so its address reads back as 0.
+        // Other emulators may trace opcodes this one does not know, which still compare.
+        let instruction = Instruction::decode(opcode, 0);
+        let record = TraceRecord {
+            pc: word("PC")?,
+            opcode,
+            instruction,
+            v,
+            i: word("I")?,
+            sp: byte("SP")? as u8,
+            dt: byte("DT")? as u8,
+            st: byte("ST")? as u8,
+        };
+        Ok(Some(TraceLine {
+            line: number,
+            record,
+            disassembly: comment.map_or_else(|| disassembly(instruction), str::to_string),
+        }))
+    }
+
+    fn write_state(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PC={:04X} OP={:04X} V=", self.pc, self.opcode)?;
+        for v in self.v {
+            write!(f, "{v:02X}")?;
+        }
+        write!(
+            f,
+            " I={:04X} SP={:02X} DT={:02X} ST={:02X}",
+            self.i, self.sp, self.dt, self.st
+        )
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_state(f)?;
+        write!(f, " ; {}", disassembly(self.instruction))
+    }
+}
+
+/// The Cowgod syntax of `instruction`, `???` for an opcode that is not an instruction.
+fn disassembly(instruction: Option<Instruction>) -> String {
+    instruction.map_or_else(|| "???".to_string(), |instruction| instruction.to_string())
+}
+
+/// At most `digits` hex digits.
+fn parse_hex(text: &str, digits: usize) -> Option<u16> {
+    (text.len() <= digits)
+        .then(|| u16::from_str_radix(text, 16).ok())
+        .flatten()
+}
+
+/// A record read back from a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceLine {
+    /// 1-based line number in the trace.
+    pub line: usize,
+    pub record: TraceRecord,
+    /// The instruction as the trace spells it after ` ; `, or decoded from the opcode.
+    pub disassembly: String,
+}
+
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let record = &self.record;
+        write!(
+            f,
+            "{:>8}  {:04X}  {:04X}  {}",
+            self.line, record.pc, record.opcode, self.disassembly
+        )
+    }
+}
+
+/// One thing two trace records disagree on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    Register {
+        register: Register,
+        first: u16,
+        second: u16,
+    },
+    /// The opcode at `addr` differs, so the program in memory does.
+    Memory { addr: u16, first: u16, second: u16 },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Difference::Register {
+                register: register @ (Register::I | Register::Pc),
+                first,
+                second,
+            } => write!(f, "{register}: {first:04X} != {second:04X}"),
+            Difference::Register {
+                register,
+                first,
+                second,
+            } => write!(f, "{register}: {first:02X} != {second:02X}"),
+            Difference::Memory {
+                addr,
+                first,
+                second,
+            } => write!(f, "memory at {addr:04X}: {first:04X} != {second:04X}"),
+        }
+    }
+}
+
+/// Where two traces stop agreeing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// How many instructions the traces agree on.
+    pub index: usize,
+    /// The last instructions both traces ran. The last one is usually the culprit, since
+    /// records hold the state before their instruction runs.
+    pub before: Vec<TraceLine>,
+    /// The first record of each trace that disagrees, `None` if that trace ended first.
+    pub first: Option<TraceLine>,
+    pub second: Option<TraceLine>,
+    pub differences: Vec<Difference>,
+    /// What each trace runs next.
+    pub first_after: Vec<TraceLine>,
+    pub second_after: Vec<TraceLine>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "the traces agree on {} instructions, ending with:",
+            self.index
+        )?;
+        writeln!(f, "{:>8}  PC    OP    instruction", "line")?;
+        for line in &self.before {
+            writeln!(f, "{line}")?;
+        }
+        for (name, line, after) in [
+            ("first", &self.first, &self.first_after),
+            ("second", &self.second, &self.second_after),
+        ] {
+            match line {
+                Some(line) => {
+                    write!(f, "{name} trace, line {}: ", line.line)?;
+                    line.record.write_state(f)?;
+                    writeln!(f)?;
+                }
+                None => writeln!(f, "{name} trace: ends here")?,
+            }
+            for line in line.iter().chain(after) {
+                writeln!(f, "{line}")?;
+            }
+        }
+        if !self.differences.is_empty() {
+            writeln!(f, "differences:")?;
+        }
+        for difference in &self.differences {
+            writeln!(f, "  {difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Find the first record where the traces `first` and `second` disagree, `None` if they
+/// are the same. A trace that ends before the other one diverges there.
+pub fn diff(first: &str, second: &str) -> Result<Option<Divergence>, TraceParseError> {
+    let (mut first, mut second) = (records(first), records(second));
+    let mut before = VecDeque::with_capacity(CONTEXT_BEFORE + 1);
+    let mut index = 0;
+    loop {
+        let (first_line, second_line) = (first.next().transpose()?, second.next().transpose()?);
+        let differences = match (&first_line, &second_line) {
+            (None, None) => return Ok(None),
+            (Some(a), Some(b)) => a.record.differences(&b.record),
+            _ => Vec::new(),
+        };
+        match first_line {
+            Some(line) if differences.is_empty() && second_line.is_some() => {
+                before.push_back(line);
+                if before.len() > CONTEXT_BEFORE {
+                    before.pop_front();
+                }
+                index += 1;
+            }
+            first_line => {
+                return Ok(Some(Divergence {
+                    index,
+                    before: before.into(),
+                    first: first_line,
+                    second: second_line,
+                    differences,
+                    first_after: first.take(CONTEXT_AFTER).collect::<Result<_, _>>()?,
+                    second_after: second.take(CONTEXT_AFTER).collect::<Result<_, _>>()?,
+                }));
+            }
+        }
+    }
+}
+
+fn records(text: &str) -> impl Iterator<Item = Result<TraceLine, TraceParseError>> + '_ {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| TraceRecord::parse(index + 1, line).transpose())
+}
+
+enum Sink {
+    Callback(Box<dyn FnMut(&TraceRecord) + Send>),
+    Writer(Box<dyn Write + Send>),
+}
+
+/// Receives a [`TraceRecord`] for every instruction an [`Emulator`] executes, once
+/// installed with [`Emulator::set_tracer`].
+pub struct Tracer {
+    sink: Sink,
+    /// The first write error, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Call `callback` with every record.
+    pub fn new(callback: impl FnMut(&TraceRecord) + Send + 'static) -> Self {
+        Self {
+            sink: Sink::Callback(Box::new(callback)),
+            error: None,
+        }
+    }
+
+    /// Write the trace to `writer`, one line per record after [`HEADER`].
+    pub fn to_writer(writer: impl Write + Send + 'static) -> Self {
+        let mut tracer = Self {
+            sink: Sink::Writer(Box::new(writer)),
+            error: None,
+        };
+        tracer.write_line(HEADER);
+        tracer
+    }
+
+    /// Write the trace to a new file at `path`.
+    pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::to_writer(BufWriter::new(File::create(path)?)))
+    }
+
+    pub(crate) fn record(&mut self, record: &TraceRecord) {
+        match &mut self.sink {
+            Sink::Callback(callback) => callback(record),
+            Sink::Writer(_) => self.write_line(record),
+        }
+    }
+
+    fn write_line(&mut self, line: impl fmt::Display) {
+        if let (Sink::Writer(writer), None) = (&mut self.sink, &self.error) {
+            self.error = writeln!(writer, "{line}").err();
+        }
+    }
+
+    /// Flush the trace, reporting the first error writing it.
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        match &mut self.sink {
+            Sink::Writer(writer) => writer.flush(),
+            Sink::Callback(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::quirks::Quirks;
+
+    // 200: 6001  V0 := 1
+    // 202: 8006  V0 >>= V0
+    // 204: F000 0300  I := long 0x300
+    const ROM: [u8; 8] = [0x60, 0x01, 0x80, 0x06, 0xF0, 0x00, 0x03, 0x00];
+
+    /// A writer whose contents can still be read after the tracer owns it.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writes_the_stable_line_format() {
+        let output = Shared::default();
+        let mut emulator = Emulator::from_bytes(&ROM).unwrap();
+        emulator.set_tracer(Some(Tracer::to_writer(output.clone())));
+        for _ in 0..3 {
+            emulator.step(Duration::ZERO).unwrap();
+        }
+        emulator.take_tracer().unwrap().finish().unwrap();
+
+        let text = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let zeros = "00".repeat(15);
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], HEADER);
+        assert!(lines[1].starts_with(&format!(
+            "PC=0200 OP=6001 V=00{zeros} I=0000 SP=00 DT=00 ST=00 ; "
+        )));
+        assert!(lines[2].starts_with(&format!("PC=0202 OP=8006 V=01{zeros} I=0000")));
+        assert!(lines[3].starts_with(&format!("PC=0204 OP=F000 V={}01 I=0000", "00".repeat(15))));
+    }
+
+    #[test]
+    fn calls_back_for_executed_instructions_only() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&records);
+        // 200: F00A  wait for a key into V0
+        let mut emulator = Emulator::from_bytes(&[0xF0, 0x0A]).unwrap();
+        emulator.set_tracer(Some(Tracer::new(move |record| {
+            sink.lock().unwrap().push(*record)
+        })));
+        emulator.step(Duration::ZERO).unwrap();
+        assert!(records.lock().unwrap().is_empty());
+        emulator.set_keypad([true; 16]);
+        emulator.step(Duration::ZERO).unwrap();
+        let records = records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].opcode, 0xF00A);
+    }
+
+    /// The trace of `steps` instructions of `rom` run with `quirks`.
+    fn trace(rom: &[u8], quirks: Quirks, steps: usize) -> String {
+        let output = Shared::default();
+        let mut emulator = Emulator::builder().quirks(quirks).build(rom).unwrap();
+        emulator.set_tracer(Some(Tracer::to_writer(output.clone())));
+        for _ in 0..steps {
+            emulator.step(Duration::ZERO).unwrap();
+        }
+        String::from_utf8(output.0.lock().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn diff_finds_the_first_divergence() {
+        // 200: 6001  V0 := 1
+        // 202: 6102  V1 := 2
+        // 204: 8016  V0 >>= V1
+        // 206: 1206  loop
+        let rom = [0x60, 0x01, 0x61, 0x02, 0x80, 0x16, 0x12, 0x06];
+        let shift_vy = Quirks {
+            shift: false,
+            ..Quirks::default()
+        };
+        let shift_vx = Quirks {
+            shift: true,
+            ..Quirks::default()
+        };
+        let first = trace(&rom, shift_vx, 6);
+        assert_eq!(diff(&first, &first), Ok(None));
+
+        let divergence = diff(&first, &trace(&rom, shift_vy, 6)).unwrap().unwrap();
+        assert_eq!(divergence.index, 3);
+        assert_eq!(divergence.before.last().unwrap().record.pc, 0x204);
+        assert_eq!(
+            divergence.differences,
+            [
+                Difference::Register {
+                    register: Register::V(0),
+                    first: 0,
+                    second: 1
+                },
+                Difference::Register {
+                    register: Register::V(15),
+                    first: 1,
+                    second: 0
+                }
+            ]
+        );
+        assert_eq!(divergence.first_after.len(), 2);
+
+        // A trace that stops early diverges where it ends, and other emulators may
+        // write the fields in another order.
+        let other = "pc=0200 op=6001 st=00 dt=00 sp=00 i=0000 v=00000000000000000000000000000000";
+        let divergence = diff(&first, other).unwrap().unwrap();
+        assert_eq!((divergence.index, divergence.second), (1, None));
+        // Opcodes that do not decode are still traced.
+        let unknown = format!(
+            "PC=0200 OP=5001 V=00{zeros} I=0000 SP=00 DT=00 ST=00",
+            zeros = "00".repeat(15)
+        );
+        let divergence = diff(&first, &unknown).unwrap().unwrap();
+        assert_eq!(divergence.index, 0);
+        let second = divergence.second.unwrap();
+        assert_eq!(
+            (second.record.instruction, second.disassembly.as_str()),
+            (None, "???")
+        );
+        assert_eq!(
+            divergence.differences,
+            [Difference::Memory {
+                addr: 0x200,
+                first: 0x6001,
+                second: 0x5001
+            }]
+        );
+        assert_eq!(
+            diff(&first, "PC=0200 OP=6001"),
+            Err(TraceParseError {
+                line: 1,
+                field: "V"
+            })
+        );
+    }
+}
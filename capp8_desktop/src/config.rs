@@ -0,0 +1,389 @@
+//! The configuration file, `capp8/config.toml` in the user's configuration directory
+//! (`$XDG_CONFIG_HOME` or `~/.config` on Linux).
+//!
+//! `[defaults]` applies to every ROM, and a `[rom.<sha1>]` section, keyed by the SHA-1
+//! of the ROM image in hex as `capp8 info` prints it, overrides it for one ROM:
+//!
+//! ```toml
+//! [defaults]
+//! ips = 900
+//! scale = 12
+//! palette = "amber"
+//! waveform = "triangle"
+//! volume = 0.5
+//!
+//! [defaults.keys]
+//! # Keypad key = keyboard key: a letter, a digit, space, enter, tab or an arrow.
+//! 5 = "up"
+//! 8 = "down"
+//!
+//! [rom.b9bbc12cee3f7b9d3b1f69161f7d7a2d86953379]
+//! platform = "schip"
+//! quirks = { clipping = false }
+//! ```
+//!
+//! Every value is optional, and command line options override them in turn. Between the
+//! ROM section and the defaults come the settings the [ROM database](crate::database)
+//! recommends, read from `capp8/database` next to the configuration file unless a
+//! top-level `database = "<directory>"` says otherwise.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use capp8_core::audio::Waveform;
+use capp8_core::palette::Palette;
+use capp8_core::quirks::{Platform, Quirks};
+use serde::Deserialize;
+use serde::de::{self, Deserializer};
+
+use crate::database::{Database, DatabaseError};
+
+/// The pitches the beep may have, in Hz, about what can be heard.
+pub const FREQUENCIES: RangeInclusive<f32> = 20.0..=20_000.0;
+/// The volumes the beep may have.
+pub const VOLUMES: RangeInclusive<f32> = 0.0..=1.0;
+
+/// Keyboard keys that have a name of more than one character.
+const NAMED_KEYS: [&str; 7] = ["space", "enter", "tab", "up", "down", "left", "right"];
+
+/// How the desktop frontend runs a ROM. Unset values are left to the next layer: the
+/// ROM section, then the database, then the defaults, then the frontend's own.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// The platform whose quirks to start from.
+    #[serde(deserialize_with = "parsed")]
+    pub platform: Option<Platform>,
+    /// Quirks changed from those of the platform, by [`Quirks::NAMES`].
+    #[serde(deserialize_with = "quirk_names")]
+    pub quirks: BTreeMap<String, bool>,
+    /// Instructions per second.
+    #[serde(deserialize_with = "positive")]
+    pub ips: Option<u32>,
+    /// Window pixels per CHIP-8 pixel.
+    #[serde(deserialize_with = "positive")]
+    pub scale: Option<u16>,
+    /// Frames undone per frame while Backspace is held.
+    #[serde(deserialize_with = "positive")]
+    pub rewind_speed: Option<u16>,
+    #[serde(deserialize_with = "parsed")]
+    pub palette: Option<Palette>,
+    /// The shape of the beep: square, sine or triangle.
+    #[serde(deserialize_with = "parsed")]
+    pub waveform: Option<Waveform>,
+    /// The pitch of the beep in Hz, within [`FREQUENCIES`].
+    #[serde(deserialize_with = "frequency")]
+    pub frequency: Option<f32>,
+    /// The volume of the sound, within [`VOLUMES`].
+    #[serde(deserialize_with = "volume")]
+    pub volume: Option<f32>,
+    /// Keyboard keys by the keypad key they press, see [`is_key_name`].
+    #[serde(deserialize_with = "key_names")]
+    pub keys: BTreeMap<u8, String>,
+}
+
+impl Settings {
+    /// These settings, with the values they leave unset taken from `base`.
+    pub fn over(self, base: Settings) -> Settings {
+        let mut quirks = base.quirks;
+        quirks.extend(self.quirks);
+        let mut keys = base.keys;
+        keys.extend(self.keys);
+        Settings {
+            platform: self.platform.or(base.platform),
+            quirks,
+            ips: self.ips.or(base.ips),
+            scale: self.scale.or(base.scale),
+            rewind_speed: self.rewind_speed.or(base.rewind_speed),
+            palette: self.palette.or(base.palette),
+            waveform: self.waveform.or(base.waveform),
+            frequency: self.frequency.or(base.frequency),
+            volume: self.volume.or(base.volume),
+            keys,
+        }
+    }
+
+    /// The quirks of the platform, or `base` when none is set, with the changed ones
+    /// applied.
+    pub fn apply_quirks(&self, base: Quirks) -> Quirks {
+        let mut quirks = self.platform.map_or(base, Platform::quirks);
+        for (name, &enabled) in &self.quirks {
+            quirks.set_flag(name, enabled);
+        }
+        quirks
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The directory of the ROM database, if not the default one.
+    pub database: Option<PathBuf>,
+    pub defaults: Settings,
+    /// Settings for single ROMs, by the SHA-1 of the ROM in lowercase hex.
+    #[serde(rename = "rom")]
+    pub roms: BTreeMap<String, Settings>,
+}
+
+impl Config {
+    /// Where the configuration file is, `None` when the system has no configuration
+    /// directory.
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("capp8").join("config.toml"))
+    }
+
+    /// Read the configuration file, an empty configuration if there is none.
+    pub fn load() -> Result<Config, ConfigError> {
+        match Config::path() {
+            Some(path) if path.exists() => Config::load_from(&path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        text.parse()
+    }
+
+    /// Read the ROM database this configuration points at, an empty one if there is none.
+    pub fn database(&self) -> Result<Database, DatabaseError> {
+        let directory = self
+            .database
+            .clone()
+            .or_else(|| Config::path()?.parent().map(|dir| dir.join("database")));
+        directory.map_or_else(|| Ok(Database::default()), Database::load)
+    }
+
+    /// The settings for the ROM whose SHA-1 is `rom_hash`, with what `database` knows of
+    /// it under the ROM section.
+    pub fn settings(&self, rom_hash: &[u8; 20], database: &Database) -> Settings {
+        let hash = hash_hex(rom_hash);
+        let rom = self
+            .roms
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&hash))
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default();
+        let known = database
+            .get(rom_hash)
+            .map(|entry| entry.settings.clone())
+            .unwrap_or_default();
+        rom.over(known.over(self.defaults.clone()))
+    }
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(ConfigError::Parse)
+    }
+}
+
+/// A SHA-1 in lowercase hex, as the configuration and the database key ROMs.
+pub(crate) fn hash_hex(hash: &[u8; 20]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Whether the desktop frontend knows the keyboard key called `name`: a letter, a digit
+/// or one of `space`, `enter`, `tab`, `up`, `down`, `left` and `right`.
+pub fn is_key_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_ascii_alphanumeric(),
+        _ => NAMED_KEYS.contains(&name),
+    }
+}
+
+/// Why the configuration file could not be read.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "could not read the configuration: {error}"),
+            ConfigError::Parse(error) => write!(f, "invalid configuration: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(error) => Some(error),
+            ConfigError::Parse(error) => Some(error),
+        }
+    }
+}
+
+/// A value written as a string, parsed with `FromStr`.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err: Display>,
+{
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+/// A number of at least 1, as the command line options take.
+fn positive<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Copy + Into<u64>,
+{
+    let value = T::deserialize(deserializer)?;
+    if value.into() == 0 {
+        return Err(de::Error::custom("expected a positive number"));
+    }
+    Ok(Some(value))
+}
+
+fn frequency<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    within(deserializer, FREQUENCIES)
+}
+
+fn volume<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    within(deserializer, VOLUMES)
+}
+
+/// A number in `range`.
+fn within<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    range: RangeInclusive<f32>,
+) -> Result<Option<f32>, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if !range.contains(&value) {
+        return Err(de::Error::custom(format!(
+            "expected a number from {} to {}",
+            range.start(),
+            range.end()
+        )));
+    }
+    Ok(Some(value))
+}
+
+fn quirk_names<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, bool>, D::Error> {
+    let quirks = BTreeMap::<String, bool>::deserialize(deserializer)?;
+    match quirks
+        .keys()
+        .find(|name| !Quirks::NAMES.contains(&name.as_str()))
+    {
+        Some(name) => Err(de::Error::custom(format!(
+            "unknown quirk {name}, expected {}",
+            Quirks::NAMES.join(", ")
+        ))),
+        None => Ok(quirks),
+    }
+}
+
+/// The keypad keys, written in hex, and the keyboard keys pressing them.
+fn key_names<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<u8, String>, D::Error> {
+    let mut keys = BTreeMap::new();
+    for (keypad, name) in BTreeMap::<String, String>::deserialize(deserializer)? {
+        let index = u8::from_str_radix(&keypad, 16)
+            .ok()
+            .filter(|&index| index < 16)
+            .ok_or_else(|| de::Error::custom(format!("no keypad key {keypad}, expected 0 to F")))?;
+        let name = name.to_ascii_lowercase();
+        if !is_key_name(&name) {
+            return Err(de::Error::custom(format!("unknown keyboard key {name}")));
+        }
+        keys.insert(index, name);
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use capp8_core::emulator::Emulator;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [defaults]
+        ips = 900
+        palette = "amber"
+        rewind_speed = 4
+        waveform = "sine"
+        volume = 0.5
+        quirks = { clipping = false }
+        keys = { 5 = "Up", a = "z" }
+
+        [rom.HASH]
+        platform = "schip"
+        ips = 1200
+        frequency = 220
+        keys = { 5 = "w" }
+    "#;
+
+    #[test]
+    fn layers_rom_sections_over_the_defaults() {
+        // The section names may be in either case.
+        let hash = Emulator::from_bytes(&[0x12, 0x00]).unwrap().rom_hash();
+        let hex: String = hash.iter().map(|byte| format!("{byte:02X}")).collect();
+        let config: Config = CONFIG.replace("HASH", &hex).parse().unwrap();
+
+        let settings = config.settings(&hash, &Database::default());
+        assert_eq!(settings.platform, Some(Platform::SuperChip));
+        assert_eq!(
+            (settings.ips, settings.palette),
+            (Some(1200), Some(Palette::AMBER))
+        );
+        assert_eq!(
+            settings.apply_quirks(Quirks::default()),
+            Quirks {
+                clipping: false,
+                ..Quirks::SUPER_CHIP
+            }
+        );
+        assert_eq!(
+            (settings.waveform, settings.frequency, settings.volume),
+            (Some(Waveform::Sine), Some(220.0), Some(0.5))
+        );
+        assert_eq!(settings.keys[&5], "w");
+        assert_eq!(settings.keys[&0xA], "z");
+
+        let other = config.settings(&[0; 20], &Database::default());
+        assert_eq!((other.platform, other.ips), (None, Some(900)));
+        assert_eq!(other.rewind_speed, Some(4));
+        assert_eq!(other.keys[&5], "up");
+        let overridden = Settings {
+            ips: Some(60),
+            ..Settings::default()
+        }
+        .over(other);
+        assert_eq!((overridden.ips, overridden.scale), (Some(60), None));
+    }
+
+    #[test]
+    fn rejects_unknown_values() {
+        for invalid in [
+            "[defaults]\nquirks = { wrap = true }",
+            "[defaults]\nkeys = { 16 = \"a\" }",
+            "[defaults]\nkeys = { 1 = \"f1\" }",
+            "[defaults]\nplatform = \"nes\"",
+            "[defaults]\nspeed = 700",
+            "[defaults]\nips = 0",
+            "[defaults]\nscale = 0",
+            "[rom.00]\nrewind_speed = 0",
+            "[defaults]\nwaveform = \"saw\"",
+            "[defaults]\nfrequency = 5",
+            "[defaults]\nvolume = 1.5",
+        ] {
+            assert!(invalid.parse::<Config>().is_err(), "{invalid}");
+        }
+        assert_eq!("".parse::<Config>().unwrap(), Config::default());
+    }
+}
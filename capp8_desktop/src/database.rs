@@ -0,0 +1,448 @@
+//! A local copy of the community [CHIP-8 database](https://github.com/chip-8/chip-8-database),
+//! which knows the platform, speed, colours and keys of thousands of ROMs by their SHA-1.
+//!
+//! Copy the `database` directory of that repository to `capp8/database` in the
+//! configuration directory, or point `database` in the configuration file at it. Only
+//! `programs.json` and `platforms.json` are read.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use capp8_core::palette::{self, Palette};
+use capp8_core::quirks::{LoadStore, Platform as Preset, Quirks};
+use serde::Deserialize;
+
+use crate::config::{Settings, hash_hex};
+
+/// The keyboard keys given to the inputs the database names, the others being ignored.
+const INPUT_KEYS: [(&str, &str); 6] = [
+    ("up", "up"),
+    ("down", "down"),
+    ("left", "left"),
+    ("right", "right"),
+    ("a", "space"),
+    ("b", "enter"),
+];
+
+/// The most instructions per frame taken from the database, as fast as the window runs.
+const MAX_TICKRATE: u32 = 1000;
+
+/// What the database knows about one ROM image.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    /// The database's id of the platform the ROM was written for, such as `superchip`.
+    pub platform: String,
+    /// The quirks, speed, palette and keys it recommends. The quirks are the preset of the
+    /// closest platform capp8 has, with only what differs from it in the changed ones.
+    pub settings: Settings,
+}
+
+/// The ROMs of the database by SHA-1.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    entries: HashMap<String, Entry>,
+}
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: BTreeMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    /// Quirks that differ from those of the platform, by platform.
+    #[serde(default)]
+    quirky_platforms: HashMap<String, DatabaseQuirks>,
+    /// Instructions per frame.
+    tickrate: Option<u32>,
+    colors: Option<Colors>,
+    /// Keypad keys by input name.
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Platform {
+    id: String,
+    default_tickrate: Option<u32>,
+    #[serde(default)]
+    quirks: DatabaseQuirks,
+}
+
+/// Quirks as the database names them, unset ones being left as they are.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatabaseQuirks {
+    shift: Option<bool>,
+    memory_increment_by_x: Option<bool>,
+    memory_leave_i_unchanged: Option<bool>,
+    wrap: Option<bool>,
+    jump: Option<bool>,
+    vblank: Option<bool>,
+    logic: Option<bool>,
+}
+
+impl DatabaseQuirks {
+    /// Set the quirks this has in `quirks`.
+    fn apply(&self, quirks: &mut Quirks) {
+        let flags = [
+            (&mut quirks.shift, self.shift),
+            (&mut quirks.jump_with_vx, self.jump),
+            (&mut quirks.vf_reset, self.logic),
+            (&mut quirks.clipping, self.wrap.map(|set| !set)),
+            (&mut quirks.display_wait, self.vblank),
+        ];
+        for (flag, value) in flags {
+            if let Some(value) = value {
+                *flag = value;
+            }
+        }
+        let load_store = quirks.load_store;
+        quirks.load_store = match (self.memory_leave_i_unchanged, self.memory_increment_by_x) {
+            (Some(true), _) => LoadStore::Unchanged,
+            (_, Some(true)) => LoadStore::IncrementByX,
+            (Some(false), _) if load_store == LoadStore::Unchanged => LoadStore::Increment,
+            (_, Some(false)) if load_store == LoadStore::IncrementByX => LoadStore::Increment,
+            _ => load_store,
+        };
+    }
+}
+
+/// The capp8 platform closest to the database's platform `id`.
+fn preset(id: &str) -> Preset {
+    match id {
+        "chip48" => Preset::Chip48,
+        "superchip1" | "superchip" | "megachip8" => Preset::SuperChip,
+        "xochip" => Preset::XoChip,
+        // The original interpreter and its variants.
+        _ => Preset::CosmacVip,
+    }
+}
+
+/// The quirks of `quirks` that differ from `base`, by [`Quirks::NAMES`].
+fn changed_quirks(base: Quirks, quirks: Quirks) -> BTreeMap<String, bool> {
+    let load_store = match quirks.load_store {
+        LoadStore::Increment => ("load-store-increment", true),
+        LoadStore::IncrementByX => ("load-store-increment-by-x", true),
+        LoadStore::Unchanged => ("load-store-increment", false),
+    };
+    [
+        (base.shift != quirks.shift, ("shift", quirks.shift)),
+        (base.load_store != quirks.load_store, load_store),
+        (
+            base.jump_with_vx != quirks.jump_with_vx,
+            ("jump-with-vx", quirks.jump_with_vx),
+        ),
+        (
+            base.vf_reset != quirks.vf_reset,
+            ("vf-reset", quirks.vf_reset),
+        ),
+        (
+            base.clipping != quirks.clipping,
+            ("clipping", quirks.clipping),
+        ),
+        (
+            base.display_wait != quirks.display_wait,
+            ("display-wait", quirks.display_wait),
+        ),
+    ]
+    .into_iter()
+    .filter(|&(changed, _)| changed)
+    .map(|(_, (name, enabled))| (name.to_string(), enabled))
+    .collect()
+}
+
+impl Database {
+    /// Read the database in `directory`, an empty one if it does not exist.
+    pub fn load<P: AsRef<Path>>(directory: P) -> Result<Database, DatabaseError> {
+        let directory = directory.as_ref();
+        if !directory.exists() {
+            return Ok(Database::default());
+        }
+        let read = |name| fs::read_to_string(directory.join(name)).map_err(DatabaseError::Io);
+        Database::from_json(&read("programs.json")?, &read("platforms.json")?)
+    }
+
+    /// The database from the contents of `programs.json` and `platforms.json`.
+    pub fn from_json(programs: &str, platforms: &str) -> Result<Database, DatabaseError> {
+        let programs: Vec<Program> = serde_json::from_str(programs).map_err(DatabaseError::Json)?;
+        let platforms: Vec<Platform> =
+            serde_json::from_str(platforms).map_err(DatabaseError::Json)?;
+        let mut entries = HashMap::new();
+        for program in programs {
+            for (hash, rom) in program.roms {
+                let Some(entry) = rom.entry(&program.title, &platforms) else {
+                    continue;
+                };
+                entries.insert(hash.to_ascii_lowercase(), entry);
+            }
+        }
+        Ok(Database { entries })
+    }
+
+    /// The entry of the ROM whose SHA-1 is `rom_hash`.
+    pub fn get(&self, rom_hash: &[u8; 20]) -> Option<&Entry> {
+        self.entries.get(&hash_hex(rom_hash))
+    }
+
+    /// How many ROM images the database knows.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Rom {
+    /// The entry for the first platform of the ROM, `None` if it has none the database
+    /// describes.
+    fn entry(&self, title: &str, platforms: &[Platform]) -> Option<Entry> {
+        let platform = self
+            .platforms
+            .iter()
+            .find_map(|id| platforms.iter().find(|platform| &platform.id == id))?;
+        let preset = preset(&platform.id);
+        let mut quirks = preset.quirks();
+        platform.quirks.apply(&mut quirks);
+        if let Some(quirky) = self.quirky_platforms.get(&platform.id) {
+            quirky.apply(&mut quirks);
+        }
+        // The database is not ours to trust: a tick rate of 0 would stall the emulator, and
+        // a huge one freeze it.
+        let plausible = |tickrate: &u32| (1..=MAX_TICKRATE).contains(tickrate);
+        let tickrate = self
+            .tickrate
+            .filter(plausible)
+            .or(platform.default_tickrate.filter(plausible));
+        let palette = self.colors.as_ref().and_then(|colors| {
+            let pixels = colors
+                .pixels
+                .iter()
+                .map(|pixel| palette::parse_color(pixel));
+            Palette::from_colors(&pixels.collect::<Option<Vec<_>>>()?)
+        });
+        let keys = INPUT_KEYS
+            .iter()
+            .filter_map(|&(input, key)| {
+                let keypad = *self.keys.get(input).filter(|&&keypad| keypad < 16)?;
+                Some((keypad, key.to_string()))
+            })
+            .collect();
+        Some(Entry {
+            title: title.to_string(),
+            platform: platform.id.clone(),
+            settings: Settings {
+                platform: Some(preset),
+                quirks: changed_quirks(preset.quirks(), quirks),
+                ips: tickrate.map(|tickrate| tickrate * 60),
+                palette,
+                keys,
+                ..Settings::default()
+            },
+        })
+    }
+}
+
+/// Why the database could not be read.
+#[derive(Debug)]
+pub enum DatabaseError {
+    Io(io::Error),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Io(error) => write!(f, "could not read the ROM database: {error}"),
+            DatabaseError::Json(error) => write!(f, "invalid ROM database: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for DatabaseError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DatabaseError::Io(error) => Some(error),
+            DatabaseError::Json(error) => Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capp8_core::emulator::Emulator;
+
+    use super::*;
+
+    const PLATFORMS: &str = r#"[
+        {
+            "id": "originalChip8",
+            "name": "Original CHIP-8",
+            "defaultTickrate": 15,
+            "quirks": {
+                "shift": false, "memoryIncrementByX": false, "memoryLeaveIUnchanged": false,
+                "wrap": false, "jump": false, "vblank": true, "logic": true
+            }
+        },
+        {
+            "id": "superchip",
+            "name": "Modern SUPER-CHIP",
+            "defaultTickrate": 30,
+            "quirks": {
+                "shift": true, "memoryIncrementByX": false, "memoryLeaveIUnchanged": true,
+                "wrap": false, "jump": true, "vblank": false, "logic": false
+            }
+        },
+        {
+            "id": "chip48",
+            "name": "CHIP-48",
+            "defaultTickrate": 30,
+            "quirks": {
+                "shift": true, "memoryIncrementByX": true, "memoryLeaveIUnchanged": false,
+                "wrap": false, "jump": true, "vblank": false, "logic": false
+            }
+        }
+    ]"#;
+
+    const PROGRAMS: &str = r##"[
+        {
+            "title": "Jumper",
+            "authors": ["Someone"],
+            "roms": {
+                "HASH": {
+                    "file": "jumper.ch8",
+                    "platforms": ["megachip8", "superchip"],
+                    "quirkyPlatforms": { "superchip": { "wrap": true } },
+                    "tickrate": 100,
+                    "colors": { "pixels": ["#000000", "#ffcc00"], "buzzer": "#ff0000" },
+                    "keys": { "left": 7, "right": 9, "a": 5, "player2Up": 1 }
+                },
+                "0000000000000000000000000000000000000000": {
+                    "file": "jumper-vip.ch8",
+                    "platforms": ["originalChip8"]
+                },
+                "0101010101010101010101010101010101010101": {
+                    "file": "jumper-turbo.ch8",
+                    "platforms": ["originalChip8"],
+                    "tickrate": 4294967295
+                },
+                "0202020202020202020202020202020202020202": {
+                    "file": "jumper-stuck.ch8",
+                    "platforms": ["originalChip8"],
+                    "tickrate": 0
+                },
+                "0303030303030303030303030303030303030303": {
+                    "file": "jumper-hp48.ch8",
+                    "platforms": ["chip48"],
+                    "quirkyPlatforms": { "chip48": { "memoryIncrementByX": false } }
+                }
+            }
+        }
+    ]"##;
+
+    /// The test database, with the ROM `12 00` as Jumper.
+    fn database() -> (Database, [u8; 20]) {
+        let hash = Emulator::from_bytes(&[0x12, 0x00]).unwrap().rom_hash();
+        let programs = PROGRAMS.replace("HASH", &hash_hex(&hash).to_uppercase());
+        (Database::from_json(&programs, PLATFORMS).unwrap(), hash)
+    }
+
+    #[test]
+    fn finds_the_settings_of_known_roms() {
+        let (database, hash) = database();
+        assert_eq!(database.len(), 5);
+
+        let entry = database.get(&hash).unwrap();
+        assert_eq!(
+            (entry.title.as_str(), entry.platform.as_str()),
+            ("Jumper", "superchip")
+        );
+        let settings = &entry.settings;
+        assert_eq!(settings.platform, Some(Preset::SuperChip));
+        assert_eq!(settings.quirks, [("clipping".to_string(), false)].into());
+        assert_eq!(
+            settings.apply_quirks(Quirks::default()),
+            Quirks {
+                clipping: false,
+                ..Quirks::SUPER_CHIP
+            }
+        );
+        assert_eq!(settings.ips, Some(6000));
+        assert_eq!(settings.palette.unwrap().0[3], [0xFF, 0xCC, 0x00]);
+        let keys: Vec<_> = settings
+            .keys
+            .iter()
+            .map(|(&k, v)| (k, v.as_str()))
+            .collect();
+        assert_eq!(keys, [(5, "space"), (7, "left"), (9, "right")]);
+
+        let vip = database.get(&[0; 20]).unwrap();
+        assert_eq!(
+            vip.settings.apply_quirks(Quirks::XO_CHIP),
+            Quirks::COSMAC_VIP
+        );
+        assert_eq!((vip.settings.ips, vip.settings.palette), (Some(900), None));
+        assert!(vip.settings.quirks.is_empty());
+        assert!(database.get(&[9; 20]).is_none());
+
+        // Tick rates that would freeze or stall the emulator give way to the platform's.
+        assert_eq!(database.get(&[1; 20]).unwrap().settings.ips, Some(900));
+        assert_eq!(database.get(&[2; 20]).unwrap().settings.ips, Some(900));
+
+        let hp48 = &database.get(&[3; 20]).unwrap().settings;
+        assert_eq!(hp48.platform, Some(Preset::Chip48));
+        assert_eq!(
+            hp48.apply_quirks(Quirks::default()),
+            Quirks {
+                load_store: LoadStore::Increment,
+                ..Quirks::CHIP_48
+            }
+        );
+    }
+
+    #[test]
+    fn a_chosen_platform_overrides_the_database() {
+        let (database, hash) = database();
+        let command_line = |platform| Settings {
+            platform: Some(platform),
+            ..Settings::default()
+        };
+
+        let vip = database.get(&[0; 20]).unwrap().settings.clone();
+        let quirks = command_line(Preset::XoChip)
+            .over(vip)
+            .apply_quirks(Quirks::default());
+        assert_eq!(quirks, Quirks::XO_CHIP);
+
+        // What the ROM needs beyond its platform still applies.
+        let jumper = database.get(&hash).unwrap().settings.clone();
+        let quirks = command_line(Preset::Chip48)
+            .over(jumper)
+            .apply_quirks(Quirks::default());
+        assert_eq!(
+            quirks,
+            Quirks {
+                clipping: false,
+                ..Quirks::CHIP_48
+            }
+        );
+    }
+}
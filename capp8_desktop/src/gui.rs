@@ -1,31 +1,222 @@
 use std::ffi::OsStr;
-use std::path::Path;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
+use capp8_core::audio::{Tone, Waveform};
 use capp8_core::emulator::Emulator;
+use capp8_core::error::{EmulatorError, LoadError, MovieError};
 use capp8_core::frontend::Frontend;
+use capp8_core::movie::{Movie, MoviePlayer, MovieRecorder};
+use capp8_core::palette::Palette;
+use capp8_core::quirks::Quirks;
+use capp8_core::rewind::Rewind;
 use raylib::prelude::*;
-use raylib::{RaylibHandle, RaylibThread, ffi::KeyboardKey};
+use raylib::{RaylibHandle, RaylibThread, ffi, ffi::KeyboardKey};
+
+use crate::config::{Config, ConfigError, Settings};
+use crate::database::DatabaseError;
+
+/// How many save state slots the F-key hotkeys cycle through.
+const SAVE_SLOTS: u8 = 10;
+/// How long status messages stay on screen.
+const STATUS_DURATION: Duration = Duration::from_secs(2);
+/// How many frames of history are kept for rewinding, ten seconds at 60 FPS.
+const REWIND_FRAMES: usize = 10 * 60;
+/// Upper bound on the memory used by the rewind history.
+const REWIND_BYTES: usize = 32 * 1024 * 1024;
+/// The sample rate of the audio stream.
+const SAMPLE_RATE: u32 = 44_100;
+/// Samples per audio buffer, about 23 ms. Smaller buffers lower the latency of the beep
+/// but underrun when a frame takes long.
+const AUDIO_CHUNK: usize = 1024;
+/// The length of a frame, in which the timers tick once.
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+/// The most time caught up on at once, so that the program does not race after the
+/// window was dragged or the machine stalled.
+const MAX_LAG: Duration = Duration::from_millis(250);
+/// The clock speeds, in instructions per second, that the speed hotkeys step through.
+const SPEEDS: [u32; 10] = [60, 420, 700, 900, 1200, 1800, 6000, 12_000, 30_000, 60_000];
+/// The fastest clock speed the window runs at, so that a frame never takes too long.
+const MAX_SPEED: u32 = SPEEDS[SPEEDS.len() - 1];
+/// The size of the window in CHIP-8 pixels, the low resolution display.
+const WINDOW_PIXELS: (i32, i32) = (64, 32);
+/// Window pixels per CHIP-8 pixel unless configured otherwise.
+const DEFAULT_SCALE: u16 = 10;
+/// The CHIP-8 keypad mapped onto the left side of a QWERTY keyboard, indexed by key.
+const DEFAULT_KEYS: [KeyboardKey; 16] = [
+    KeyboardKey::KEY_X,
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_Q,
+    KeyboardKey::KEY_W,
+    KeyboardKey::KEY_E,
+    KeyboardKey::KEY_A,
+    KeyboardKey::KEY_S,
+    KeyboardKey::KEY_D,
+    KeyboardKey::KEY_Z,
+    KeyboardKey::KEY_C,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_R,
+    KeyboardKey::KEY_F,
+    KeyboardKey::KEY_V,
+];
+const LETTER_KEYS: [KeyboardKey; 26] = [
+    KeyboardKey::KEY_A,
+    KeyboardKey::KEY_B,
+    KeyboardKey::KEY_C,
+    KeyboardKey::KEY_D,
+    KeyboardKey::KEY_E,
+    KeyboardKey::KEY_F,
+    KeyboardKey::KEY_G,
+    KeyboardKey::KEY_H,
+    KeyboardKey::KEY_I,
+    KeyboardKey::KEY_J,
+    KeyboardKey::KEY_K,
+    KeyboardKey::KEY_L,
+    KeyboardKey::KEY_M,
+    KeyboardKey::KEY_N,
+    KeyboardKey::KEY_O,
+    KeyboardKey::KEY_P,
+    KeyboardKey::KEY_Q,
+    KeyboardKey::KEY_R,
+    KeyboardKey::KEY_S,
+    KeyboardKey::KEY_T,
+    KeyboardKey::KEY_U,
+    KeyboardKey::KEY_V,
+    KeyboardKey::KEY_W,
+    KeyboardKey::KEY_X,
+    KeyboardKey::KEY_Y,
+    KeyboardKey::KEY_Z,
+];
+const DIGIT_KEYS: [KeyboardKey; 10] = [
+    KeyboardKey::KEY_ZERO,
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_FIVE,
+    KeyboardKey::KEY_SIX,
+    KeyboardKey::KEY_SEVEN,
+    KeyboardKey::KEY_EIGHT,
+    KeyboardKey::KEY_NINE,
+];
+
+/// Why a [`DesktopFrontend`] could not start.
+#[derive(Debug)]
+pub enum DesktopError {
+    Load(LoadError),
+    Config(ConfigError),
+    Database(DatabaseError),
+}
+
+impl fmt::Display for DesktopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DesktopError::Load(error) => error.fmt(f),
+            DesktopError::Config(error) => error.fmt(f),
+            DesktopError::Database(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DesktopError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DesktopError::Load(error) => Some(error),
+            DesktopError::Config(error) => Some(error),
+            DesktopError::Database(error) => Some(error),
+        }
+    }
+}
+
+impl From<LoadError> for DesktopError {
+    fn from(error: LoadError) -> Self {
+        DesktopError::Load(error)
+    }
+}
+
+impl From<ConfigError> for DesktopError {
+    fn from(error: ConfigError) -> Self {
+        DesktopError::Config(error)
+    }
+}
+
+impl From<DatabaseError> for DesktopError {
+    fn from(error: DatabaseError) -> Self {
+        DesktopError::Database(error)
+    }
+}
 
 pub struct DesktopFrontend {
     emulator: Emulator,
+    rom_path: PathBuf,
+    /// The fault that stopped the emulator, shown on top of the last frame.
+    error: Option<EmulatorError>,
+    /// The save state slot used by the quick save and quick load hotkeys.
+    slot: u8,
+    /// A message shown at the bottom of the window until it expires.
+    status: Option<(String, Instant)>,
+    rewind: Rewind,
+    /// How many frames are undone per frame while rewinding.
+    rewind_speed: usize,
+    /// Time not yet run, less than a frame once caught up.
+    lag: Duration,
+    /// Sixtieths of an instruction owed to the next frame, so that clock speeds that are
+    /// not a multiple of 60 are spread evenly over the frames.
+    instruction_credit: u32,
+    /// The movie being recorded, if any.
+    recorder: Option<MovieRecorder>,
+    /// The movie being played back, if any. Live input is ignored meanwhile.
+    player: Option<MoviePlayer>,
+    /// The audio stream, `None` when there is no audio device.
+    speaker: Option<Speaker>,
+    /// The beep, played at no volume while muted.
+    tone: Tone,
+    muted: bool,
+    palette: Palette,
+    /// The keyboard key pressing each keypad key.
+    keys: [KeyboardKey; 16],
     raylib_handle: RaylibHandle,
     raylib_thread: RaylibThread,
 }
 impl DesktopFrontend {
-    pub fn new<P: AsRef<Path>>(rom_path: P, width: i32, height: i32) -> Self {
-        let emulator = Emulator::new(&rom_path).expect(
-            format!(
-                "Could not find the specified rom {}",
-                rom_path
-                    .as_ref()
-                    // .file_name()
-                    .to_str()
-                    // .and_then(OsStr::to_str)
-                    .unwrap()
-            )
-            .as_str(),
-        );
+    /// Open a window running the ROM at `rom_path`, set up by the configuration file and
+    /// the ROM database.
+    pub fn new<P: AsRef<Path>>(rom_path: P) -> Result<Self, DesktopError> {
+        let emulator = Emulator::new(&rom_path)?;
+        let config = Config::load()?;
+        let settings = config.settings(&emulator.rom_hash(), &config.database()?);
+        Ok(Self::with_emulator(emulator, rom_path, settings))
+    }
+    /// Like [`DesktopFrontend::new`], but with `quirks` whatever the configuration says.
+    pub fn with_quirks<P: AsRef<Path>>(rom_path: P, quirks: Quirks) -> Result<Self, DesktopError> {
+        let mut frontend = Self::new(rom_path)?;
+        frontend.emulator.set_quirks(quirks);
+        Ok(frontend)
+    }
+    /// Open a window running `emulator`, which was loaded from `rom_path`, set up by
+    /// `settings` rather than the configuration file. The ROM is loaded again to record
+    /// movies, and save states are kept next to it.
+    pub fn with_emulator<P: AsRef<Path>>(
+        mut emulator: Emulator,
+        rom_path: P,
+        settings: Settings,
+    ) -> Self {
+        emulator.set_quirks(settings.apply_quirks(emulator.quirks()));
+        let ips = settings.ips.unwrap_or(emulator.clock_speed());
+        emulator.set_clock_speed(ips.min(MAX_SPEED));
+        let mut keys = DEFAULT_KEYS;
+        for (&index, name) in &settings.keys {
+            if let (Some(slot), Some(key)) = (keys.get_mut(index as usize), keyboard_key(name)) {
+                *slot = key;
+            }
+        }
+        let scale = settings.scale.unwrap_or(DEFAULT_SCALE).max(1) as i32;
+        let default_tone = Tone::default();
+        let (width, height) = (WINDOW_PIXELS.0 * scale, WINDOW_PIXELS.1 * scale);
         let title = rom_path
             .as_ref()
             .file_stem()
@@ -40,64 +231,366 @@ impl DesktopFrontend {
 
         Self {
             emulator,
+            rom_path: rom_path.as_ref().to_path_buf(),
+            error: None,
+            slot: 0,
+            status: None,
+            rewind: Rewind::new(REWIND_FRAMES, REWIND_BYTES),
+            rewind_speed: settings.rewind_speed.unwrap_or(1).max(1) as usize,
+            lag: Duration::ZERO,
+            instruction_credit: 0,
+            recorder: None,
+            player: None,
+            speaker: Speaker::open(),
+            tone: Tone {
+                waveform: settings.waveform.unwrap_or(default_tone.waveform),
+                frequency: settings.frequency.unwrap_or(default_tone.frequency),
+                volume: settings.volume.unwrap_or(default_tone.volume),
+            },
+            muted: false,
+            palette: settings.palette.unwrap_or_default(),
+            keys,
             raylib_handle,
             raylib_thread,
         }
     }
+
+    /// Set how many frames are undone per frame while Backspace is held.
+    pub fn set_rewind_speed(&mut self, frames: usize) {
+        self.rewind_speed = frames.max(1);
+    }
+
+    /// Set the clock speed in instructions per second, at most [`MAX_SPEED`], which the
+    /// speed hotkeys change while running.
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.emulator
+            .set_clock_speed(instructions_per_second.min(MAX_SPEED));
+    }
+
+    /// Step to the next faster or slower entry of [`SPEEDS`].
+    fn change_speed(&mut self, faster: bool) {
+        let current = self.emulator.clock_speed();
+        let speed = if faster {
+            SPEEDS.into_iter().find(|&speed| speed > current)
+        } else {
+            SPEEDS.into_iter().rev().find(|&speed| speed < current)
+        };
+        if let Some(speed) = speed {
+            self.set_speed(speed);
+        }
+        let speed = self.emulator.clock_speed();
+        self.set_status(format!("{speed} instructions per second"));
+    }
+
+    /// How many instructions the next frame runs.
+    fn frame_instructions(&mut self) -> u32 {
+        self.instruction_credit = self
+            .instruction_credit
+            .saturating_add(self.emulator.clock_speed());
+        let instructions = self.instruction_credit / 60;
+        self.instruction_credit %= 60;
+        instructions
+    }
+
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.tone.waveform = waveform;
+    }
+
+    /// Set the pitch of the beep in Hz.
+    pub fn set_tone_frequency(&mut self, frequency: f32) {
+        self.tone.frequency = frequency;
+    }
+
+    /// Set the volume of the beep, from 0 to 1.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.tone.volume = volume;
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        if self.raylib_handle.is_window_fullscreen() != fullscreen {
+            self.raylib_handle.toggle_fullscreen();
+        }
+    }
+
+    fn rewind(&mut self) {
+        match self.rewind.rewind(&mut self.emulator, self.rewind_speed) {
+            Ok(0) => {}
+            Ok(_) => self.error = None,
+            Err(error) => self.set_status(format!("Could not rewind: {error}")),
+        }
+    }
+
+    /// Where the state for `slot` is stored, next to the ROM.
+    fn slot_path(&self, slot: u8) -> PathBuf {
+        self.rom_path.with_extension(format!("state{slot}"))
+    }
+
+    fn save_slot(&mut self, slot: u8) {
+        let message = match fs::write(self.slot_path(slot), self.emulator.save_state()) {
+            Ok(()) => format!("Saved slot {slot}"),
+            Err(error) => format!("Could not save slot {slot}: {error}"),
+        };
+        self.set_status(message);
+    }
+
+    fn load_slot(&mut self, slot: u8) {
+        let message = match fs::read(self.slot_path(slot)) {
+            Ok(state) => match self.emulator.load_state(&state) {
+                Ok(()) => {
+                    self.error = None;
+                    self.rewind.clear();
+                    format!("Loaded slot {slot}")
+                }
+                Err(error) => format!("Could not load slot {slot}: {error}"),
+            },
+            Err(error) => format!("Could not load slot {slot}: {error}"),
+        };
+        self.set_status(message);
+    }
+
+    /// Where the movie of the ROM is recorded to and played back from.
+    fn movie_path(&self) -> PathBuf {
+        self.rom_path.with_extension("c8m")
+    }
+
+    /// Restart the ROM the way it was started and record the input from now on, until
+    /// `stop_recording`.
+    pub fn start_recording(&mut self) {
+        let emulator = self.emulator.to_builder().build_from_file(&self.rom_path);
+        match emulator {
+            Ok(emulator) => {
+                self.restart(emulator);
+                self.recorder = Some(MovieRecorder::new(&self.emulator));
+                self.set_status("Recording".to_string());
+            }
+            Err(error) => self.set_status(format!("Could not restart the ROM: {error}")),
+        }
+    }
+
+    /// Stop recording and save the movie next to the ROM.
+    pub fn stop_recording(&mut self) {
+        let Some(recorder) = self.recorder.take() else {
+            return;
+        };
+        let frames = recorder.frames();
+        let message = match recorder.finish().save(self.movie_path()) {
+            Ok(()) => format!("Recorded {frames} frames"),
+            Err(error) => format!("Could not save the movie: {error}"),
+        };
+        self.set_status(message);
+    }
+
+    /// Restart the ROM the way the movie at `path` was recorded and play it back.
+    pub fn play_movie<P: AsRef<Path>>(&mut self, path: P) {
+        match self.load_movie(path) {
+            Ok((emulator, player)) => {
+                self.recorder = None;
+                self.restart(emulator);
+                self.player = Some(player);
+                self.set_status("Playing movie".to_string());
+            }
+            Err(error) => self.set_status(format!("Could not play the movie: {error}")),
+        }
+    }
+
+    fn load_movie<P: AsRef<Path>>(&self, path: P) -> Result<(Emulator, MoviePlayer), MovieError> {
+        let movie = Movie::load(path)?;
+        let emulator = movie
+            .configure(self.emulator.to_builder())
+            .build_from_file(&self.rom_path)?;
+        let player = MoviePlayer::new(movie, &emulator)?;
+        Ok((emulator, player))
+    }
+
+    /// Switch to `emulator`, keeping the speed.
+    fn restart(&mut self, mut emulator: Emulator) {
+        emulator.set_clock_speed(self.emulator.clock_speed());
+        self.emulator = emulator;
+        self.error = None;
+        self.rewind.clear();
+        self.player = None;
+    }
+
+    /// Play the next frame of the movie, stopping at its end or on a desync.
+    fn play_frame(&mut self) {
+        let Some(player) = self.player.as_mut() else {
+            return;
+        };
+        match player.play_frame(&mut self.emulator) {
+            Some(Ok(_)) => {}
+            None => {
+                let frames = player.frame();
+                self.player = None;
+                self.set_status(format!("Movie finished after {frames} frames"));
+            }
+            Some(Err(MovieError::Emulator(error))) => {
+                self.player = None;
+                self.error = Some(error);
+            }
+            Some(Err(error)) => {
+                self.player = None;
+                self.set_status(error.to_string());
+            }
+        }
+    }
+
+    /// Run a frame with the live input and add it to the movie being recorded.
+    fn record_frame(&mut self) {
+        let keypad = self.keypad();
+        let instructions = self.frame_instructions();
+        if let (Some(recorder), None) = (self.recorder.as_mut(), &self.error) {
+            self.error = recorder
+                .record_frame(&mut self.emulator, keypad, instructions)
+                .err();
+        }
+    }
+
+    /// Advance by one frame: the movie, the rewind or the program itself.
+    fn run_frame(&mut self) {
+        if self.player.is_some() {
+            self.play_frame();
+        } else if self.recorder.is_some() {
+            self.record_frame();
+        } else if self.raylib_handle.is_key_down(KeyboardKey::KEY_BACKSPACE) {
+            self.rewind();
+        } else {
+            self.poll_keys();
+            if self.error.is_none() {
+                let instructions = self.frame_instructions();
+                self.error = self.emulator.run_frame(instructions).err();
+                self.rewind.push(&self.emulator);
+            }
+        }
+    }
+
+    /// The state of the CHIP-8 keypad.
+    fn keypad(&self) -> [bool; 16] {
+        self.keys.map(|key| self.raylib_handle.is_key_down(key))
+    }
+
+    fn set_status(&mut self, message: String) {
+        self.status = Some((message, Instant::now()));
+    }
+
+    /// F5 saves to the current slot, F9 loads from it, F6 and F7 select the slot.
+    /// F2 starts and stops recording a movie, F3 plays it back. M mutes the sound, and
+    /// `-` and `=` slow the program down and speed it up.
+    fn handle_hotkeys(&mut self) {
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_MINUS) {
+            self.change_speed(false);
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_EQUAL) {
+            self.change_speed(true);
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_M) {
+            self.muted = !self.muted;
+            let message = if self.muted { "Muted" } else { "Sound on" };
+            self.set_status(message.to_string());
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F2) {
+            if self.recorder.is_some() {
+                self.stop_recording();
+            } else {
+                self.start_recording();
+            }
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F3) {
+            self.play_movie(self.movie_path());
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F5) {
+            self.save_slot(self.slot);
+        }
+        // Loading a state in the middle of a movie would break it.
+        let in_movie = self.recorder.is_some() || self.player.is_some();
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F9) && !in_movie {
+            self.load_slot(self.slot);
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F6) {
+            self.slot = (self.slot + SAVE_SLOTS - 1) % SAVE_SLOTS;
+            self.set_status(format!("Slot {}", self.slot));
+        }
+        if self.raylib_handle.is_key_pressed(KeyboardKey::KEY_F7) {
+            self.slot = (self.slot + 1) % SAVE_SLOTS;
+            self.set_status(format!("Slot {}", self.slot));
+        }
+    }
 }
 impl Frontend for DesktopFrontend {
     fn poll_keys(&mut self) {
-        self.emulator.set_keypad([
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_X),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_ONE),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_TWO),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_THREE),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_Q),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_W),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_E),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_A),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_S),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_D),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_Z),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_C),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_FOUR),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_R),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_F),
-            self.raylib_handle.is_key_down(KeyboardKey::KEY_V),
-        ]);
+        let keypad = self.keypad();
+        self.emulator.set_keypad(keypad);
     }
 
     fn render_display(&mut self) {
-        let x_scale = self.raylib_handle.get_screen_width() / 64;
-        let y_scale = self.raylib_handle.get_screen_height() / 32;
+        let display = self.emulator.display();
+        let (width, height) = (display.width(), display.height());
+        let x_scale = self.raylib_handle.get_screen_width() / width as i32;
+        let y_scale = self.raylib_handle.get_screen_height() / height as i32;
+        let palette = self.palette.0.map(|[r, g, b]| Color::new(r, g, b, 0xFF));
         let mut d = self.raylib_handle.begin_drawing(&self.raylib_thread);
-        for x in 0..64usize {
-            for y in 0..32usize {
+        for x in 0..width {
+            for y in 0..height {
                 d.draw_rectangle(
                     x as i32 * x_scale,
                     y as i32 * y_scale,
                     x_scale,
                     y_scale,
-                    if self.emulator.display()[(x, y)] {
-                        Color::WHITE
-                    } else {
-                        Color::BLACK
-                    },
+                    palette[display[(x, y)] as usize],
                 );
             }
         }
+        if let Some(error) = &self.error {
+            d.draw_text(&error.to_string(), 10, 10, 20, Color::RED);
+        }
+        let status = self.status.as_ref();
+        if let Some((message, _)) = status.filter(|(_, since)| since.elapsed() < STATUS_DURATION) {
+            let y = d.get_screen_height() - 30;
+            d.draw_text(message, 10, y, 20, Color::YELLOW);
+        }
     }
 
-    fn play_sound(&self) {}
+    /// Keeps the audio stream fed with the sound of the emulator.
+    fn play_sound(&mut self) {
+        let Some(speaker) = self.speaker.as_mut() else {
+            return;
+        };
+        // A stopped emulator keeps its sound timer, so it is silenced like when muted.
+        let silent = self.muted || self.error.is_some();
+        let volume = if silent { 0.0 } else { self.tone.volume };
+        self.emulator.set_tone(Tone {
+            volume,
+            ..self.tone
+        });
+        let mut samples = [0.0; AUDIO_CHUNK];
+        while speaker.wants_samples() {
+            self.emulator.render_audio(&mut samples, SAMPLE_RATE);
+            speaker.write(&samples);
+        }
+    }
 
+    /// Runs the whole frames that fit in `duration` and the time left over from earlier
+    /// steps, then draws once.
     fn step(&mut self, duration: Duration) {
-        self.poll_keys();
-        self.emulator.step(duration);
+        self.handle_hotkeys();
+        self.lag = (self.lag + duration).min(MAX_LAG);
+        while self.lag >= FRAME {
+            self.lag -= FRAME;
+            self.run_frame();
+        }
         self.render_display();
         self.play_sound();
     }
 
     fn run(&mut self) {
+        self.raylib_handle.set_target_fps(60);
         let mut last = Instant::now();
 
         while !self.raylib_handle.window_should_close() {
@@ -108,3 +601,73 @@ impl Frontend for DesktopFrontend {
         }
     }
 }
+
+/// The key called `name` in the configuration, see [`crate::config::is_key_name`].
+fn keyboard_key(name: &str) -> Option<KeyboardKey> {
+    let mut chars = name.chars();
+    let key = match (chars.next()?, chars.next()) {
+        (c @ 'a'..='z', None) => LETTER_KEYS[(c as u8 - b'a') as usize],
+        (c @ '0'..='9', None) => DIGIT_KEYS[(c as u8 - b'0') as usize],
+        _ => match name {
+            "space" => KeyboardKey::KEY_SPACE,
+            "enter" => KeyboardKey::KEY_ENTER,
+            "tab" => KeyboardKey::KEY_TAB,
+            "up" => KeyboardKey::KEY_UP,
+            "down" => KeyboardKey::KEY_DOWN,
+            "left" => KeyboardKey::KEY_LEFT,
+            "right" => KeyboardKey::KEY_RIGHT,
+            _ => return None,
+        },
+    };
+    Some(key)
+}
+
+/// A mono stream of 32-bit float samples on the default audio device.
+struct Speaker {
+    stream: ffi::AudioStream,
+}
+
+impl Speaker {
+    /// Open the audio device and start the stream, `None` if there is no device.
+    fn open() -> Option<Self> {
+        // SAFETY: the window is open, which is all raylib needs before the audio device,
+        // and the device is only opened here and closed when the speaker is dropped.
+        unsafe {
+            ffi::InitAudioDevice();
+            if !ffi::IsAudioDeviceReady() {
+                ffi::CloseAudioDevice();
+                return None;
+            }
+            ffi::SetAudioStreamBufferSizeDefault(AUDIO_CHUNK as i32);
+            let stream = ffi::LoadAudioStream(SAMPLE_RATE, 32, 1);
+            ffi::PlayAudioStream(stream);
+            Some(Self { stream })
+        }
+    }
+
+    /// Whether the stream has played a buffer that can be refilled.
+    fn wants_samples(&self) -> bool {
+        // SAFETY: the stream stays loaded as long as the speaker exists.
+        unsafe { ffi::IsAudioStreamProcessed(self.stream) }
+    }
+
+    /// Queue a buffer of samples, which must not be longer than [`AUDIO_CHUNK`].
+    fn write(&mut self, samples: &[f32]) {
+        // SAFETY: the stream holds 32-bit mono samples, so `samples` is read as
+        // `samples.len()` frames, and raylib copies them before returning.
+        unsafe {
+            ffi::UpdateAudioStream(self.stream, samples.as_ptr().cast(), samples.len() as i32)
+        }
+    }
+}
+
+impl Drop for Speaker {
+    fn drop(&mut self) {
+        // SAFETY: the stream and device were opened in `Speaker::open` and are not used
+        // after this.
+        unsafe {
+            ffi::UnloadAudioStream(self.stream);
+            ffi::CloseAudioDevice();
+        }
+    }
+}
@@ -1 +1,3 @@
+pub mod config;
+pub mod database;
 pub mod gui;
@@ -0,0 +1,181 @@
+//! Runs the [Timendus test suite](https://github.com/Timendus/chip8-test-suite) under
+//! every quirk profile and compares the final screens against golden images.
+//!
+//! Golden images are plain PBM files named `<rom>-<platform>.pbm`, so a failing case can
+//! be inspected with an image viewer or a text diff. ROMs that offer a menu read the
+//! choice from 0x1FF when it is set, which [`Case::select`] provides so that they run
+//! without input. Cases that cannot pass under a profile are marked with
+//! [`Case::known_failure`] and have no golden image.
+
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use capp8_core::{emulator::EmulatorBuilder, error::LoadError, quirks::Platform};
+
+use crate::{
+    dump,
+    headless::{HeadlessFrontend, StopCondition},
+    script::InputScript,
+};
+
+/// Where the test ROMs read which platform or test to run.
+pub const SELECT_ADDRESS: usize = 0x1FF;
+/// How long each case runs, twenty seconds at 60 FPS. Every test has settled by then.
+const FRAMES: u64 = 1200;
+
+/// One ROM run under one quirk profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Case {
+    /// File name of the ROM.
+    pub rom: &'static str,
+    pub platform: Platform,
+    /// The value written to [`SELECT_ADDRESS`] before running, if any.
+    pub select: Option<u8>,
+    pub script: InputScript,
+    /// Why the case cannot pass, if it cannot. It is still run, but not compared.
+    pub known_failure: Option<&'static str>,
+}
+
+impl Case {
+    /// The name of the golden image, without the extension.
+    pub fn name(&self) -> String {
+        let stem = self.rom.trim_end_matches(".ch8");
+        format!("{stem}-{}", self.platform)
+    }
+
+    /// Run the case on `rom`, returning the final screen as PBM.
+    pub fn run(&self, rom: &[u8]) -> Result<String, LoadError> {
+        let mut emulator = EmulatorBuilder::new()
+            .quirks(self.platform.quirks())
+            .seed(0)
+            .build(rom)?;
+        if let Some(select) = self.select {
+            emulator.memory_mut()[SELECT_ADDRESS] = select;
+        }
+        let mut frontend = HeadlessFrontend::new(emulator)
+            .script(self.script.clone())
+            .stop_when(StopCondition::Frames(FRAMES));
+        frontend.run_to_end();
+        Ok(dump::to_pbm(frontend.emulator().display()))
+    }
+}
+
+/// Every case of the suite.
+pub fn cases() -> Vec<Case> {
+    let mut cases = Vec::new();
+    for platform in Platform::ALL {
+        let case = |rom, select, script| Case {
+            rom,
+            platform,
+            select,
+            script,
+            known_failure: None,
+        };
+        for rom in [
+            "1-chip8-logo.ch8",
+            "2-ibm-logo.ch8",
+            "3-corax+.ch8",
+            "4-flags.ch8",
+        ] {
+            cases.push(case(rom, None, InputScript::new()));
+        }
+        let quirks = match platform {
+            Platform::CosmacVip => case("5-quirks.ch8", Some(1), InputScript::new()),
+            Platform::SuperChip => case("5-quirks.ch8", Some(2), InputScript::new()),
+            Platform::XoChip => case("5-quirks.ch8", Some(3), InputScript::new()),
+            // Legacy SUPER-CHIP is the closest entry, but it expects the quirks of
+            // SUPER-CHIP 1.1 rather than those of its predecessor.
+            Platform::Chip48 => Case {
+                known_failure: Some("the quirks test has no CHIP-48 entry"),
+                ..case("5-quirks.ch8", Some(4), InputScript::new())
+            },
+        };
+        cases.push(quirks);
+        // The Ex9E test, with 5 held down for a while.
+        let keys = InputScript::new().event(60, 5, true).event(120, 5, false);
+        cases.push(case("6-keypad.ch8", Some(1), keys));
+        // The beep test sounds while B is held.
+        let keys = InputScript::new().event(60, 0xB, true);
+        cases.push(case("7-beep.ch8", None, keys));
+        // Scrolling exists on SUPER-CHIP and XO-CHIP only, low resolution for both.
+        match platform {
+            Platform::SuperChip => cases.push(case("8-scrolling.ch8", Some(2), InputScript::new())),
+            Platform::XoChip => cases.push(case("8-scrolling.ch8", Some(4), InputScript::new())),
+            _ => {}
+        }
+    }
+    cases
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    /// The screen differs from the golden image.
+    Fail,
+    /// There is no golden image to compare with.
+    Missing,
+    /// The golden image was (re)written from this run.
+    Blessed,
+    /// The ROM could not be loaded.
+    Error,
+    /// The case ran, but is known not to pass, see [`Case::known_failure`].
+    KnownFailure,
+}
+
+/// The outcome of one case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub case: Case,
+    pub verdict: Verdict,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = match self.verdict {
+            Verdict::Pass => "pass",
+            Verdict::Fail => "FAIL",
+            Verdict::Missing => "missing golden image",
+            Verdict::Blessed => "blessed",
+            Verdict::Error => "could not load the ROM",
+            Verdict::KnownFailure => "known failure",
+        };
+        write!(f, "{:<24} {verdict}", self.case.name())?;
+        match self.case.known_failure {
+            Some(reason) if self.verdict == Verdict::KnownFailure => write!(f, ": {reason}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Run every case with ROMs from `roms`, comparing against the images in `golden`. With
+/// `bless`, the golden images are written instead, except for known failures.
+pub fn run_suite(roms: &Path, golden: &Path, bless: bool) -> io::Result<Vec<Report>> {
+    if bless {
+        fs::create_dir_all(golden)?;
+    }
+    let mut reports = Vec::new();
+    for case in cases() {
+        let image: PathBuf = golden.join(format!("{}.pbm", case.name()));
+        let screen = fs::read(roms.join(case.rom))
+            .ok()
+            .and_then(|rom| case.run(&rom).ok());
+        let verdict = match screen {
+            None => Verdict::Error,
+            Some(_) if case.known_failure.is_some() => Verdict::KnownFailure,
+            Some(screen) if bless => {
+                fs::write(&image, screen)?;
+                Verdict::Blessed
+            }
+            Some(screen) => match fs::read_to_string(&image) {
+                Ok(expected) if expected == screen => Verdict::Pass,
+                Ok(_) => Verdict::Fail,
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Verdict::Missing,
+                Err(error) => return Err(error),
+            },
+        };
+        reports.push(Report { case, verdict });
+    }
+    Ok(reports)
+}
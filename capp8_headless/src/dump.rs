@@ -0,0 +1,115 @@
+//! The display as PBM, PNG and text images, and recorded sound as WAV.
+//!
+//! PBM and text only show whether a pixel is lit on any plane. PNG keeps the XO-CHIP
+//! plane combinations apart, using the grey levels of the desktop palette.
+
+use std::io::{self, Write};
+
+use capp8_core::display::Display;
+
+/// Grey levels for the four plane combinations: off, plane 1, plane 2, both.
+const GREYS: [u8; 4] = [0x00, 0xFF, 0xAA, 0x55];
+
+/// A plain (`P1`) PBM image, one text row per display row. Lit pixels are 1, which PBM
+/// shows as black.
+pub fn to_pbm(display: &Display) -> String {
+    let mut pbm = format!("P1\n{} {}\n", display.width(), display.height());
+    for y in 0..display.height() {
+        let row: Vec<&str> = (0..display.width())
+            .map(|x| if display[(x, y)] != 0 { "1" } else { "0" })
+            .collect();
+        pbm.push_str(&row.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+/// One line per display row, `#` for lit pixels and `.` for dark ones.
+pub fn to_text(display: &Display) -> String {
+    let mut text = String::with_capacity((display.width() + 1) * display.height());
+    for y in 0..display.height() {
+        text.extend((0..display.width()).map(|x| if display[(x, y)] != 0 { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
+
+/// An 8-bit greyscale PNG with every pixel scaled up to a `scale` by `scale` square.
+pub fn write_png<W: Write>(display: &Display, scale: usize, writer: W) -> io::Result<()> {
+    let scale = scale.max(1);
+    let (width, height) = (display.width() * scale, display.height() * scale);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        pixels.extend((0..width).map(|x| GREYS[display[(x / scale, y / scale)] as usize & 3]));
+    }
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(io::Error::other)
+}
+
+/// A 16-bit mono PCM WAV file of `samples`, which are clamped to -1 to 1.
+pub fn write_wav<W: Write>(samples: &[f32], sample_rate: u32, mut writer: W) -> io::Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVEfmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel.
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    // Bytes per second, bytes per frame and bits per sample.
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dumps_lit_pixels() {
+        let mut display = Display::new();
+        display[(0, 0)] = 1;
+        display[(63, 31)] = 3;
+        let pbm = to_pbm(&display);
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("64 32"));
+        assert!(lines.next().unwrap().starts_with("1 0 0"));
+        assert!(lines.last().unwrap().ends_with("0 0 1"));
+
+        let text = to_text(&display);
+        assert_eq!(text.lines().count(), 32);
+        assert!(text.starts_with("#.."));
+        assert!(text.ends_with("..#\n"));
+
+        let mut png = Vec::new();
+        write_png(&display, 2, &mut png).unwrap();
+        assert_eq!(&png[1..4], b"PNG");
+    }
+
+    #[test]
+    fn writes_wav() {
+        let mut wav = Vec::new();
+        write_wav(&[0.0, 1.0, -2.0], 44_100, &mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &44_100u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &6u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80]);
+    }
+}
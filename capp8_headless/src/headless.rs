@@ -0,0 +1,407 @@
+use std::time::Duration;
+
+use capp8_core::{
+    emulator::Emulator,
+    error::{MovieError, StepOutcome},
+    frontend::Frontend,
+    instruction::Instruction,
+    movie::MoviePlayer,
+};
+use serde::Serialize;
+
+use crate::script::InputScript;
+
+/// How long a frame lasts, the timers ticking once per frame.
+pub const FRAME: Duration = Duration::from_nanos(16_666_667);
+
+/// When [`HeadlessFrontend::run_to_end`] stops, besides the program halting or faulting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopCondition {
+    /// After this many frames.
+    Frames(u64),
+    /// When the program jumps to the jump it is on, the usual way test ROMs end.
+    PcLoop,
+    /// When the byte at `addr` holds `value`.
+    Memory { addr: u16, value: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    Frames,
+    PcLoop,
+    Memory,
+    /// The program stopped the interpreter with 00FD.
+    Halted,
+    /// The emulator faulted.
+    Error,
+    /// The movie played to its end.
+    MovieEnd,
+    /// The emulator no longer matches the movie being played.
+    Desync,
+}
+
+/// How a run ended, serialised as the JSON result of `capp8 headless`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RunResult {
+    pub reason: StopReason,
+    /// The fault or the desync, when `reason` is [`StopReason::Error`] or
+    /// [`StopReason::Desync`].
+    pub error: Option<String>,
+    pub frames: u64,
+    pub instructions: u64,
+    /// Frames during which the sound timer was running.
+    pub sound_frames: u64,
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl RunResult {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("run results always serialise")
+    }
+}
+
+/// A [`Frontend`] without a window, input or sound, for running ROMs in tests and CI.
+///
+/// Every [`Frontend::step`] is one frame, running as many instructions as the emulator's
+/// clock speed fits in the duration. While playing a movie, it is one frame of the movie
+/// instead.
+///
+/// ```no_run
+/// use capp8_core::emulator::Emulator;
+/// use capp8_headless::headless::{HeadlessFrontend, StopCondition};
+///
+/// let emulator = Emulator::new(&"test.ch8").unwrap();
+/// let mut frontend = HeadlessFrontend::new(emulator)
+///     .stop_when(StopCondition::PcLoop)
+///     .stop_when(StopCondition::Frames(600));
+/// println!("{}", frontend.run_to_end().to_json());
+/// ```
+pub struct HeadlessFrontend {
+    emulator: Emulator,
+    conditions: Vec<StopCondition>,
+    script: InputScript,
+    movie: Option<MoviePlayer>,
+    keypad: [bool; 16],
+    frames: u64,
+    instructions: u64,
+    sound_frames: u64,
+    /// Time not yet spent running instructions.
+    budget: Duration,
+    /// Why the run stopped, and the fault if there was one.
+    stopped: Option<(StopReason, Option<String>)>,
+    /// The sample rate and the sound so far, when recording audio.
+    audio: Option<(u32, Vec<f32>)>,
+}
+
+impl HeadlessFrontend {
+    pub fn new(emulator: Emulator) -> Self {
+        Self {
+            emulator,
+            conditions: Vec::new(),
+            script: InputScript::new(),
+            movie: None,
+            keypad: [false; 16],
+            frames: 0,
+            instructions: 0,
+            sound_frames: 0,
+            budget: Duration::ZERO,
+            stopped: None,
+            audio: None,
+        }
+    }
+
+    /// Stop once `condition` holds. Without a [`StopCondition::Frames`] a program that
+    /// never halts runs forever.
+    pub fn stop_when(mut self, condition: StopCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn script(mut self, script: InputScript) -> Self {
+        self.script = script;
+        self
+    }
+
+    /// Play `player` instead of the script, stopping at the end of the movie or when it
+    /// desyncs. The stop conditions on the program are only checked between frames.
+    pub fn movie(mut self, player: MoviePlayer) -> Self {
+        self.movie = Some(player);
+        self
+    }
+
+    /// Record the sound of the run at `sample_rate`, for [`dump::write_wav`].
+    ///
+    /// [`dump::write_wav`]: crate::dump::write_wav
+    pub fn record_audio(mut self, sample_rate: u32) -> Self {
+        self.audio = Some((sample_rate, Vec::new()));
+        self
+    }
+
+    /// The recorded sound, empty unless [`HeadlessFrontend::record_audio`] was called.
+    pub fn audio(&self) -> &[f32] {
+        self.audio
+            .as_ref()
+            .map_or(&[], |(_, samples)| samples.as_slice())
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn into_inner(self) -> Emulator {
+        self.emulator
+    }
+
+    /// Why the run stopped, `None` while it has not.
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stopped.as_ref().map(|(reason, _)| *reason)
+    }
+
+    /// Run frames until the program halts, faults or a stop condition holds.
+    pub fn run_to_end(&mut self) -> RunResult {
+        while self.stopped.is_none() {
+            self.step(FRAME);
+        }
+        self.result()
+    }
+
+    pub fn result(&self) -> RunResult {
+        let emulator = &self.emulator;
+        let (reason, error) = self.stopped.clone().unwrap_or((StopReason::Frames, None));
+        RunResult {
+            reason,
+            error,
+            frames: self.frames,
+            instructions: self.instructions,
+            sound_frames: self.sound_frames,
+            pc: emulator.program_counter(),
+            i: emulator.index(),
+            v: *emulator.registers(),
+            delay_timer: emulator.delay_timer(),
+            sound_timer: emulator.sound_timer(),
+            width: emulator.display().width(),
+            height: emulator.display().height(),
+        }
+    }
+
+    /// The stop condition that holds right now, checked after every instruction.
+    fn instruction_condition(&self) -> Option<StopReason> {
+        let emulator = &self.emulator;
+        self.conditions
+            .iter()
+            .find_map(|condition| match *condition {
+                StopCondition::PcLoop => {
+                    let pc = emulator.program_counter() as usize;
+                    let word = emulator.memory().get(pc..pc + 2)?;
+                    let opcode = u16::from_be_bytes([word[0], word[1]]);
+                    let looping = Instruction::decode(opcode, 0)
+                        == Some(Instruction::Jump { addr: pc as u16 });
+                    looping.then_some(StopReason::PcLoop)
+                }
+                StopCondition::Memory { addr, value } => {
+                    let byte = emulator.memory().get(addr as usize)?;
+                    (*byte == value).then_some(StopReason::Memory)
+                }
+                StopCondition::Frames(_) => None,
+            })
+    }
+
+    /// Run instructions for `duration`, carrying the time left over to the next frame.
+    fn run_instructions(&mut self, duration: Duration) {
+        self.poll_keys();
+        let instruction_time = Duration::from_secs(1) / self.emulator.clock_speed().max(1);
+        self.budget += duration;
+        while self.budget >= instruction_time && self.stopped.is_none() {
+            self.budget -= instruction_time;
+            self.stopped = match self.emulator.step(instruction_time) {
+                Ok(StepOutcome::Halted) => Some((StopReason::Halted, None)),
+                Ok(outcome) => {
+                    if outcome == StepOutcome::Executed {
+                        self.instructions += 1;
+                    }
+                    self.instruction_condition().map(|reason| (reason, None))
+                }
+                Err(error) => Some((StopReason::Error, Some(error.to_string()))),
+            };
+        }
+    }
+
+    /// Play the next frame of the movie, returning `false` when it has no frames left.
+    fn play_movie_frame(&mut self) -> bool {
+        let Some(player) = self.movie.as_mut() else {
+            return false;
+        };
+        let Some(played) = player.play_frame(&mut self.emulator) else {
+            self.stopped = Some((StopReason::MovieEnd, None));
+            return false;
+        };
+        let finished = player.is_finished();
+        self.stopped = match played {
+            Ok(executed) => {
+                self.instructions += executed as u64;
+                if self.emulator.is_halted() {
+                    Some((StopReason::Halted, None))
+                } else if finished {
+                    Some((StopReason::MovieEnd, None))
+                } else {
+                    self.instruction_condition().map(|reason| (reason, None))
+                }
+            }
+            Err(error @ MovieError::Desync { .. }) => {
+                Some((StopReason::Desync, Some(error.to_string())))
+            }
+            Err(error) => Some((StopReason::Error, Some(error.to_string()))),
+        };
+        true
+    }
+}
+
+impl Frontend for HeadlessFrontend {
+    fn poll_keys(&mut self) {
+        self.script.apply(self.frames, &mut self.keypad);
+        self.emulator.set_keypad(self.keypad);
+    }
+
+    /// Nothing to draw to, the display is read from [`HeadlessFrontend::emulator`].
+    fn render_display(&mut self) {}
+
+    /// Renders the sound of the frame that just ran, when recording audio.
+    fn play_sound(&mut self) {
+        let Some((sample_rate, samples)) = self.audio.as_mut() else {
+            return;
+        };
+        // Rounding the total rather than each frame keeps the recording in sync.
+        let due = self.frames * *sample_rate as u64 / 60;
+        let start = samples.len();
+        samples.resize(due as usize, 0.0);
+        self.emulator
+            .render_audio(&mut samples[start..], *sample_rate);
+    }
+
+    fn step(&mut self, duration: Duration) {
+        if self.stopped.is_some() {
+            return;
+        }
+        if self.movie.is_none() {
+            self.run_instructions(duration);
+        } else if !self.play_movie_frame() {
+            return;
+        }
+        self.frames += 1;
+        if self.emulator.sound_timer() > 0 {
+            self.sound_frames += 1;
+        }
+        let frames = self.frames;
+        let out_of_frames = self
+            .conditions
+            .iter()
+            .any(|condition| matches!(*condition, StopCondition::Frames(limit) if frames >= limit));
+        if out_of_frames && self.stopped.is_none() {
+            self.stopped = Some((StopReason::Frames, None));
+        }
+        self.render_display();
+        self.play_sound();
+    }
+
+    fn run(&mut self) {
+        self.run_to_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use capp8_core::movie::MovieRecorder;
+
+    use super::*;
+
+    #[test]
+    fn stops_on_conditions() {
+        // 200: F00A  wait for a key into V0
+        // 202: A300  I := 300
+        // 204: F055  store V0 at 300
+        // 206: 1206  loop
+        let rom = [0xF0, 0x0A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06];
+        let emulator = Emulator::from_bytes(&rom).unwrap();
+        let mut frontend = HeadlessFrontend::new(emulator)
+            .script(InputScript::new().event(5, 7, true))
+            .stop_when(StopCondition::PcLoop)
+            .stop_when(StopCondition::Frames(60));
+        let result = frontend.run_to_end();
+        assert_eq!(result.reason, StopReason::PcLoop);
+        assert_eq!((result.frames, result.pc, result.v[0]), (6, 0x206, 7));
+        assert!(result.to_json().contains("\"reason\": \"pc_loop\""));
+
+        let emulator = Emulator::from_bytes(&rom).unwrap();
+        let mut frontend = HeadlessFrontend::new(emulator)
+            .script(InputScript::new().event(2, 3, true))
+            .stop_when(StopCondition::Memory {
+                addr: 0x300,
+                value: 3,
+            });
+        assert_eq!(frontend.run_to_end().reason, StopReason::Memory);
+
+        let emulator = Emulator::from_bytes(&rom).unwrap();
+        let mut frontend = HeadlessFrontend::new(emulator).stop_when(StopCondition::Frames(10));
+        let result = frontend.run_to_end();
+        assert_eq!((result.reason, result.frames), (StopReason::Frames, 10));
+    }
+
+    #[test]
+    fn records_the_beep() {
+        // 200: 6010  V0 := 10
+        // 202: F018  sound timer := V0
+        // 204: 1204  loop
+        let rom = [0x60, 0x10, 0xF0, 0x18, 0x12, 0x04];
+        let emulator = Emulator::from_bytes(&rom).unwrap();
+        let mut frontend = HeadlessFrontend::new(emulator)
+            .record_audio(6000)
+            .stop_when(StopCondition::Frames(60));
+        let result = frontend.run_to_end();
+        let audio = frontend.audio();
+        assert_eq!(audio.len(), 6000);
+        // Sixteen frames of sound, 1600 samples, then silence once it faded out.
+        assert_eq!(result.sound_frames, 16);
+        assert!(audio[..1500].iter().any(|&sample| sample > 0.2));
+        assert!(audio[2000..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn plays_movies() {
+        // 200: F00A  wait for a key into V0
+        // 202: 1200  loop
+        let rom = [0xF0, 0x0A, 0x12, 0x00];
+        let mut emulator = Emulator::builder().seed(1).build(&rom).unwrap();
+        let mut recorder = MovieRecorder::new(&emulator);
+        for frame in 0..90 {
+            let mut keypad = [false; 16];
+            keypad[frame % 16] = frame % 4 == 0;
+            recorder.record_frame(&mut emulator, keypad, 10).unwrap();
+        }
+        let movie = recorder.finish();
+
+        let play = |tamper: bool| {
+            let mut emulator = movie.configure(Emulator::builder()).build(&rom).unwrap();
+            let player = MoviePlayer::new(movie.clone(), &emulator).unwrap();
+            if tamper {
+                emulator.memory_mut()[0x300] = 1;
+            }
+            HeadlessFrontend::new(emulator)
+                .movie(player)
+                .stop_when(StopCondition::Frames(600))
+                .run_to_end()
+        };
+        let result = play(false);
+        assert_eq!((result.reason, result.frames), (StopReason::MovieEnd, 90));
+        assert_eq!(result.v, *emulator.registers());
+        let result = play(true);
+        assert_eq!((result.reason, result.frames), (StopReason::Desync, 60));
+        assert!(result.error.unwrap().contains("desync after frame 60"));
+    }
+}
@@ -0,0 +1,11 @@
+//! Running ROMs without a window, for ROM developers and CI.
+//!
+//! [`HeadlessFrontend`](headless::HeadlessFrontend) runs a ROM for a number of frames or
+//! until a [`StopCondition`](headless::StopCondition) holds, pressing keys as an
+//! [`InputScript`](script::InputScript) says, and [`dump`] writes the final display as
+//! PBM, PNG or text.
+
+pub mod conformance;
+pub mod dump;
+pub mod headless;
+pub mod script;
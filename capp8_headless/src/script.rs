@@ -0,0 +1,108 @@
+use std::fmt;
+
+/// Key presses and releases scheduled by frame number.
+///
+/// Scripts are text, one event per line: the frame, `press` or `release`, and the key as
+/// a hex digit. `#` starts a comment.
+///
+/// ```text
+/// # start the game, then hold 5 for ten frames
+/// 60 press 5
+/// 70 release 5
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InputScript {
+    /// `(frame, key, pressed)`, sorted by frame.
+    events: Vec<(u64, usize, bool)>,
+}
+
+impl InputScript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut script = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let error = |message: &str| ScriptError {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let (frame, action, key) = match words.as_slice() {
+                [] => continue,
+                [frame, action, key] => (frame, action, key),
+                _ => return Err(error("expected `<frame> press|release <key>`")),
+            };
+            let frame = frame.parse().map_err(|_| error("invalid frame number"))?;
+            let pressed = match *action {
+                "press" => true,
+                "release" => false,
+                _ => return Err(error("expected `press` or `release`")),
+            };
+            let key = usize::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| error("keys are hex digits from 0 to F"))?;
+            script = script.event(frame, key, pressed);
+        }
+        Ok(script)
+    }
+
+    /// Press (or release) `key` at the start of `frame`.
+    pub fn event(mut self, frame: u64, key: usize, pressed: bool) -> Self {
+        let at = self.events.partition_point(|&(other, ..)| other <= frame);
+        self.events.insert(at, (frame, key, pressed));
+        self
+    }
+
+    /// Update `keypad` with the events of `frame`.
+    pub fn apply(&self, frame: u64, keypad: &mut [bool; 16]) {
+        let start = self.events.partition_point(|&(other, ..)| other < frame);
+        for &(_, key, pressed) in self.events[start..]
+            .iter()
+            .take_while(|&&(other, ..)| other == frame)
+        {
+            keypad[key] = pressed;
+        }
+    }
+}
+
+/// A line of an [`InputScript`] that could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_applies_events() {
+        let script =
+            InputScript::parse("# title\n\n3 press a\n1 press 5 # early\n4 release A").unwrap();
+        let mut keypad = [false; 16];
+        script.apply(1, &mut keypad);
+        assert!(keypad[5]);
+        script.apply(3, &mut keypad);
+        assert!(keypad[0xA]);
+        script.apply(4, &mut keypad);
+        assert!(keypad[5] && !keypad[0xA]);
+
+        let error = InputScript::parse("1 press 5\n2 hold 5").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert!(InputScript::parse("1 press 10").is_err());
+    }
+}
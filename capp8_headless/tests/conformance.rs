@@ -0,0 +1,30 @@
+//! Runs the Timendus test suite shipped in `capp8_app/examples` against the golden images
+//! in `tests/golden`. Set `CAPP8_BLESS=1` to rewrite the images after an intended change.
+
+use std::{env, path::Path};
+
+use capp8_headless::conformance::{Verdict, run_suite};
+
+#[test]
+fn timendus_suite() {
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let roms = manifest.join("../capp8_app/examples");
+    let golden = manifest.join("tests/golden");
+    let bless = env::var_os("CAPP8_BLESS").is_some();
+    let reports = run_suite(&roms, &golden, bless).unwrap();
+    let (known, reports): (Vec<_>, Vec<_>) = reports
+        .into_iter()
+        .partition(|report| report.verdict == Verdict::KnownFailure);
+    for report in &reports {
+        println!("{report}");
+    }
+    for report in &known {
+        println!("{report}");
+    }
+    let failed: Vec<String> = reports
+        .iter()
+        .filter(|report| !matches!(report.verdict, Verdict::Pass | Verdict::Blessed))
+        .map(|report| report.case.name())
+        .collect();
+    assert!(failed.is_empty(), "failing cases: {failed:?}");
+}
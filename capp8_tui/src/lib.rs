@@ -0,0 +1,8 @@
+//! Running ROMs in a terminal, over SSH if need be.
+//!
+//! [`TuiFrontend`](tui::TuiFrontend) draws the display with half blocks or braille
+//! characters next to a panel of registers, reads the keypad from the keyboard and rings
+//! the terminal bell for the sound timer. [`render`] turns a display into terminal cells.
+
+pub mod render;
+pub mod tui;
@@ -0,0 +1,117 @@
+use capp8_core::display::Display;
+
+/// How pixels are packed into terminal cells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rendering {
+    /// `▀` with the top pixel as the foreground and the bottom one as the background, two
+    /// pixels per cell. Keeps the XO-CHIP colours.
+    #[default]
+    HalfBlock,
+    /// Braille patterns, eight pixels per cell in two columns of four. Small enough for
+    /// the high resolution screen to fit an 80x24 terminal, but only on or off.
+    Braille,
+}
+
+impl Rendering {
+    /// The other rendering, for the toggle hotkey.
+    pub fn toggled(self) -> Self {
+        match self {
+            Rendering::HalfBlock => Rendering::Braille,
+            Rendering::Braille => Rendering::HalfBlock,
+        }
+    }
+
+    /// How many pixels a cell holds across and down.
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            Rendering::HalfBlock => (1, 2),
+            Rendering::Braille => (2, 4),
+        }
+    }
+
+    /// The columns and rows `display` takes up.
+    pub fn size(self, display: &Display) -> (usize, usize) {
+        let (width, height) = self.cell_size();
+        (
+            display.width().div_ceil(width),
+            display.height().div_ceil(height),
+        )
+    }
+}
+
+/// The display as rows of `(top, bottom)` colour indices, one pair per cell.
+pub fn half_blocks(display: &Display) -> Vec<Vec<(u8, u8)>> {
+    (0..display.height())
+        .step_by(2)
+        .map(|y| {
+            (0..display.width())
+                .map(|x| {
+                    let bottom = if y + 1 < display.height() {
+                        display[(x, y + 1)]
+                    } else {
+                        0
+                    };
+                    (display[(x, y)], bottom)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// The bit of a braille pattern that stands for the dot at `(x, y)` in its cell.
+const BRAILLE_DOTS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+/// The display as lines of braille patterns, a pixel being lit on any plane. Empty cells
+/// are spaces, as some fonts draw the blank pattern narrower.
+pub fn braille(display: &Display) -> Vec<String> {
+    let (columns, rows) = Rendering::Braille.size(display);
+    (0..rows)
+        .map(|row| {
+            (0..columns)
+                .map(|column| {
+                    let mut pattern = 0;
+                    for (dx, dots) in BRAILLE_DOTS.iter().enumerate() {
+                        for (dy, dot) in dots.iter().enumerate() {
+                            let (x, y) = (column * 2 + dx, row * 4 + dy);
+                            if x < display.width() && y < display.height() && display[(x, y)] != 0 {
+                                pattern |= dot;
+                            }
+                        }
+                    }
+                    match pattern {
+                        0 => ' ',
+                        _ => char::from_u32(0x2800 + pattern as u32).unwrap_or(' '),
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_pixels_into_cells() {
+        let mut display = Display::new();
+        display[(0, 0)] = 1;
+        display[(1, 1)] = 3;
+        display[(1, 3)] = 2;
+        let blocks = half_blocks(&display);
+        assert_eq!(blocks.len(), 16);
+        assert_eq!(&blocks[0][..3], &[(1, 0), (0, 3), (0, 0)]);
+        assert_eq!(blocks[1][1], (0, 2));
+
+        let lines = braille(&display);
+        assert_eq!(lines.len(), 8);
+        assert_eq!(lines[0].chars().count(), 32);
+        // Dots 1, 5 and 8.
+        assert!(lines[0].starts_with("\u{2891} "));
+        assert!(lines[1].trim().is_empty());
+
+        display.set_hires(true);
+        assert_eq!(Rendering::HalfBlock.size(&display), (128, 32));
+        assert_eq!(Rendering::Braille.size(&display), (64, 16));
+    }
+}
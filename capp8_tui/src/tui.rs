@@ -0,0 +1,344 @@
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use capp8_core::emulator::Emulator;
+use capp8_core::error::EmulatorError;
+use capp8_core::frontend::Frontend;
+use capp8_core::instruction::Instruction;
+use capp8_core::timer::SoundEvent;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Colors, Print, ResetColor, SetColors};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::render::{self, Rendering};
+
+/// Colours for the four XO-CHIP plane combinations, named ones so that terminals without
+/// true colour show them too.
+const PALETTE: [Color; 4] = [Color::Black, Color::White, Color::Grey, Color::DarkGrey];
+/// The CHIP-8 keypad mapped onto the left side of a QWERTY keyboard, indexed by key.
+const KEYS: [char; 16] = [
+    'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+];
+/// How long a key counts as held after it was pressed, for terminals that do not report
+/// releases. Long enough to bridge the gaps between key repeats.
+const KEY_HOLD: Duration = Duration::from_millis(200);
+/// How often the screen is redrawn.
+const FRAME: Duration = Duration::from_nanos(16_666_667);
+/// The most time a single step catches up on, so that a stalled terminal does not make
+/// the program race afterwards.
+const MAX_STEP: Duration = Duration::from_millis(100);
+/// Columns between the display and the register panel.
+const PANEL_GAP: usize = 2;
+
+/// A [`Frontend`] drawing to the terminal, for machines without a window system.
+///
+/// Tab switches between half block and braille rendering, Esc or Ctrl-C quits.
+pub struct TuiFrontend {
+    emulator: Emulator,
+    rendering: Rendering,
+    /// When each key was last pressed or repeated, `None` once it is released.
+    held: [Option<Instant>; 16],
+    /// Whether the terminal reports key releases, so keys stay held until then.
+    release_events: bool,
+    /// The fault that stopped the emulator, shown in the panel.
+    error: Option<EmulatorError>,
+    /// Time not yet spent running instructions.
+    budget: Duration,
+    /// Set when the whole screen must be cleared before the next frame.
+    redraw: bool,
+    quit: bool,
+    /// The first terminal error, which ends the session.
+    failure: Option<io::Error>,
+    out: Stdout,
+}
+
+impl TuiFrontend {
+    pub fn new(emulator: Emulator) -> Self {
+        Self {
+            emulator,
+            rendering: Rendering::default(),
+            held: [None; 16],
+            release_events: false,
+            error: None,
+            budget: Duration::ZERO,
+            redraw: true,
+            quit: false,
+            failure: None,
+            out: io::stdout(),
+        }
+    }
+
+    pub fn rendering(mut self, rendering: Rendering) -> Self {
+        self.rendering = rendering;
+        self
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    /// Take over the terminal until the user quits, restoring it afterwards even when
+    /// drawing fails.
+    pub fn try_run(&mut self) -> io::Result<()> {
+        terminal::enable_raw_mode()?;
+        let result = self.session();
+        let restored = self.restore();
+        terminal::disable_raw_mode()?;
+        result.and(restored)
+    }
+
+    fn session(&mut self) -> io::Result<()> {
+        execute!(self.out, EnterAlternateScreen, Hide)?;
+        self.release_events = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        if self.release_events {
+            let flags = KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            execute!(self.out, PushKeyboardEnhancementFlags(flags))?;
+        }
+        let mut last = Instant::now();
+        while !self.quit {
+            let now = Instant::now();
+            self.step(now - last);
+            last = now;
+            if let Some(error) = self.failure.take() {
+                return Err(error);
+            }
+            thread::sleep(FRAME.saturating_sub(now.elapsed()));
+        }
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        if self.release_events {
+            execute!(self.out, PopKeyboardEnhancementFlags)?;
+        }
+        execute!(self.out, ResetColor, Show, LeaveAlternateScreen)
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind == KeyEventKind::Release {
+            if let Some(index) = keypad_index(key.code) {
+                self.held[index] = None;
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Tab if key.kind == KeyEventKind::Press => {
+                self.rendering = self.rendering.toggled();
+                self.redraw = true;
+            }
+            code => {
+                if let Some(index) = keypad_index(code) {
+                    self.held[index] = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    fn keypad(&self) -> [bool; 16] {
+        self.held.map(|pressed| {
+            pressed.is_some_and(|since| self.release_events || since.elapsed() < KEY_HOLD)
+        })
+    }
+
+    /// Run as many instructions as the clock speed fits in `duration`.
+    fn run_instructions(&mut self, duration: Duration) {
+        let instruction_time = Duration::from_secs(1) / self.emulator.clock_speed().max(1);
+        self.budget += duration.min(MAX_STEP);
+        while self.budget >= instruction_time && self.error.is_none() {
+            self.budget -= instruction_time;
+            self.error = self.emulator.step(instruction_time).err();
+        }
+    }
+
+    /// The lines of the register panel.
+    fn panel(&self) -> Vec<String> {
+        let emulator = &self.emulator;
+        let pc = emulator.program_counter();
+        let word = |addr: usize| {
+            let bytes = emulator.memory().get(addr..addr + 2)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+        let instruction = word(pc as usize)
+            .and_then(|opcode| Instruction::decode(opcode, word(pc as usize + 2).unwrap_or(0)))
+            .map_or_else(|| "???".to_string(), |instruction| instruction.to_string());
+        let mut lines = vec![
+            format!("PC {pc:04X}  I {:04X}", emulator.index()),
+            format!(
+                "SP {:02X}  DT {:02X}  ST {:02X}",
+                emulator.stack_pointer(),
+                emulator.delay_timer(),
+                emulator.sound_timer()
+            ),
+            String::new(),
+        ];
+        let v = emulator.registers();
+        for row in 0..8 {
+            lines.push(format!(
+                "V{:X} {:02X}  V{:X} {:02X}",
+                row,
+                v[row],
+                row + 8,
+                v[row + 8]
+            ));
+        }
+        lines.push(String::new());
+        lines.push(instruction);
+        lines.push(String::new());
+        if let Some(error) = &self.error {
+            lines.push(error.to_string());
+        } else if emulator.is_halted() {
+            lines.push("halted".to_string());
+        }
+        lines.push("Tab: rendering  Esc: quit".to_string());
+        lines
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        if self.redraw {
+            queue!(self.out, ResetColor, Clear(ClearType::All))?;
+            self.redraw = false;
+        }
+        let display = self.emulator.display();
+        let (columns, rows) = self.rendering.size(display);
+        match self.rendering {
+            Rendering::HalfBlock => {
+                for (y, row) in render::half_blocks(display).iter().enumerate() {
+                    queue!(self.out, MoveTo(0, y as u16))?;
+                    let mut colors = None;
+                    for &(top, bottom) in row {
+                        if colors != Some((top, bottom)) {
+                            colors = Some((top, bottom));
+                            let colors =
+                                Colors::new(PALETTE[top as usize], PALETTE[bottom as usize]);
+                            queue!(self.out, SetColors(colors))?;
+                        }
+                        queue!(self.out, Print('▀'))?;
+                    }
+                }
+            }
+            Rendering::Braille => {
+                let colors = Colors::new(PALETTE[1], PALETTE[0]);
+                queue!(self.out, SetColors(colors))?;
+                for (y, line) in render::braille(display).iter().enumerate() {
+                    queue!(self.out, MoveTo(0, y as u16), Print(line))?;
+                }
+            }
+        }
+        queue!(self.out, ResetColor)?;
+        let panel = self.panel();
+        // Clear what a taller screen, or the other rendering, left below the panel.
+        for y in 0..rows.max(panel.len()) {
+            let line = panel.get(y).map_or("", String::as_str);
+            queue!(
+                self.out,
+                MoveTo((columns + PANEL_GAP) as u16, y as u16),
+                Clear(ClearType::UntilNewLine),
+                Print(line)
+            )?;
+        }
+        self.out.flush()
+    }
+}
+
+/// The keypad key `code` stands for, if any.
+fn keypad_index(code: KeyCode) -> Option<usize> {
+    let KeyCode::Char(c) = code else {
+        return None;
+    };
+    KEYS.iter().position(|&key| key == c.to_ascii_lowercase())
+}
+
+impl Frontend for TuiFrontend {
+    fn poll_keys(&mut self) {
+        loop {
+            match event::poll(Duration::ZERO).and_then(|ready| ready.then(event::read).transpose())
+            {
+                Ok(Some(Event::Key(key))) => self.handle_key(key),
+                Ok(Some(Event::Resize(..))) => self.redraw = true,
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(error) => {
+                    self.failure = Some(error);
+                    break;
+                }
+            }
+        }
+        let keypad = self.keypad();
+        self.emulator.set_keypad(keypad);
+    }
+
+    fn render_display(&mut self) {
+        if let Err(error) = self.draw() {
+            self.failure.get_or_insert(error);
+        }
+    }
+
+    /// Rings the terminal bell when the sound timer starts. Terminals have no way to hold
+    /// a tone, so longer sounds ring once.
+    fn play_sound(&mut self) {
+        let started = self
+            .emulator
+            .sound_events()
+            .any(|event| event == SoundEvent::Started);
+        if started {
+            let rung = queue!(self.out, Print('\x07')).and_then(|()| self.out.flush());
+            if let Err(error) = rung {
+                self.failure.get_or_insert(error);
+            }
+        }
+    }
+
+    fn step(&mut self, duration: Duration) {
+        self.poll_keys();
+        if self.error.is_none() {
+            self.run_instructions(duration);
+        }
+        self.render_display();
+        self.play_sound();
+    }
+
+    /// Panics if the terminal fails, see [`TuiFrontend::try_run`] to handle that instead.
+    fn run(&mut self) {
+        self.try_run().expect("the terminal failed");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_keys_and_shows_registers() {
+        assert_eq!(keypad_index(KeyCode::Char('x')), Some(0));
+        assert_eq!(keypad_index(KeyCode::Char('V')), Some(0xF));
+        assert_eq!(keypad_index(KeyCode::Char('p')), None);
+        assert_eq!(keypad_index(KeyCode::Enter), None);
+
+        // 200: 6A2B  VA := 2B
+        let emulator = Emulator::from_bytes(&[0x6A, 0x2B]).unwrap();
+        let mut frontend = TuiFrontend::new(emulator);
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        frontend.handle_key(key(KeyCode::Char('w')));
+        assert!(frontend.keypad()[5]);
+        frontend.handle_key(KeyEvent::new_with_kind(
+            KeyCode::Char('w'),
+            KeyModifiers::NONE,
+            KeyEventKind::Release,
+        ));
+        assert!(!frontend.keypad()[5]);
+
+        assert!(frontend.panel().contains(&"PC 0200  I 0000".to_string()));
+        frontend.emulator.step(FRAME).unwrap();
+        let panel = frontend.panel();
+        assert!(panel.contains(&"PC 0202  I 0000".to_string()));
+        assert!(panel.contains(&"V2 00  VA 2B".to_string()));
+    }
+}